@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use scotty::PathIndexEntry;
+
+use crate::export;
+
+const BACKUP_PREFIX: &str = "scotty-";
+const BACKUP_SUFFIX: &str = ".json";
+
+/// Writes the entries as an export to a new file in dir, named after the current time
+/// Like any export file it is written atomically, see export::write_export_file.
+/// Returns the path of the backup.
+pub fn write_backup(dir: &Path, entries: &[PathIndexEntry], now: DateTime<Utc>) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let backup_path = dir.join(backup_file_name(now));
+    export::write_export_file(&backup_path, entries)?;
+    Ok(backup_path)
+}
+
+/// Returns the backups in dir, the oldest first
+/// A directory that doesn't exist yet has no backups
+pub fn list_backups(dir: &Path) -> Result<Vec<PathBuf>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = Vec::new();
+    for entry in read_dir {
+        let path = entry?.path();
+        if path.is_file() && is_backup_file_name(&path) {
+            backups.push(path);
+        }
+    }
+    // The timestamp in the name sorts chronologically
+    backups.sort();
+    Ok(backups)
+}
+
+fn backup_file_name(now: DateTime<Utc>) -> String {
+    format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        now.format("%Y%m%dT%H%M%SZ"),
+        BACKUP_SUFFIX
+    )
+}
+
+fn is_backup_file_name(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs::File;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::tempdir;
+
+    fn get_entries() -> Vec<PathIndexEntry> {
+        vec![PathIndexEntry {
+            timestamp: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            path: PathBuf::from("/foo"),
+            count: 3,
+            pinned: true,
+            weight: 0,
+            hits: 0,
+        }]
+    }
+
+    #[test]
+    fn write_backup_roundtrip() {
+        let dir = tempdir().unwrap();
+        let backup_dir = dir.path().join("backups");
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 5).unwrap();
+
+        let backup = write_backup(&backup_dir, &get_entries(), now).unwrap();
+
+        assert_eq!(backup, backup_dir.join("scotty-20240301T123005Z.json"));
+        let result = export::read_export(File::open(&backup).unwrap()).unwrap();
+        assert_eq!(result, get_entries());
+        // The temporary file is gone
+        assert_eq!(fs::read_dir(&backup_dir).unwrap().count(), 1);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn list_backups_sorted() {
+        let dir = tempdir().unwrap();
+        let newer = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let older = Utc.with_ymd_and_hms(2023, 12, 31, 23, 0, 0).unwrap();
+        let newer_backup = write_backup(dir.path(), &[], newer).unwrap();
+        let older_backup = write_backup(dir.path(), &[], older).unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        fs::create_dir(dir.path().join("scotty-dir.json")).unwrap();
+
+        let result = list_backups(dir.path()).unwrap();
+
+        assert_eq!(result, vec![older_backup, newer_backup]);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn list_backups_missing_dir() {
+        let dir = tempdir().unwrap();
+
+        let result = list_backups(&dir.path().join("backups")).unwrap();
+
+        assert!(result.is_empty());
+        dir.close().unwrap()
+    }
+}
@@ -0,0 +1,142 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use scotty::{Index, IndexBuilder, IndexStats};
+
+/// The fastest, slowest and average time an operation took
+#[derive(Debug, PartialEq, Eq)]
+pub struct Latency {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+}
+
+impl Latency {
+    /// Summarizes the durations of the runs, None if there were none
+    pub fn from_samples(samples: &[Duration]) -> Option<Latency> {
+        let min = *samples.iter().min()?;
+        let max = *samples.iter().max()?;
+        let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+        Some(Latency { min, max, mean })
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "min {:?}, max {:?}, mean {:?}",
+            self.min, self.max, self.mean
+        )
+    }
+}
+
+/// The results of timing the index operations
+#[derive(Debug)]
+pub struct BenchReport {
+    pub stats: IndexStats,
+    pub find_one: Latency,
+    pub add: Latency,
+}
+
+/// Times find_one with the query on the index and add of a directory, iterations times each
+/// add changes more than the path (it can evict and expire paths, and is journaled), so it
+/// is timed on a copy of the index in a temporary directory in scratch_dir instead
+pub fn run_bench(
+    index: &Index,
+    query: &str,
+    iterations: usize,
+    scratch_dir: &Path,
+) -> Result<BenchReport> {
+    let find_one = time(iterations, || {
+        index.find_one(&[query], &[], None)?;
+        Ok(())
+    })?;
+
+    let scratch = tempfile::tempdir_in(scratch_dir)?;
+    let copy = IndexBuilder::new().open(&scratch.path().join("bench.db"))?;
+    // Paths that no longer exist can't be imported, the copy is only a bit smaller for that
+    copy.import(&index.list()?)?;
+    let dir = scratch.path().join("visited");
+    fs::create_dir(&dir)?;
+    let add = time(iterations, || copy.add(&dir))?;
+
+    Ok(BenchReport {
+        stats: index.stats()?,
+        find_one,
+        add,
+    })
+}
+
+// Runs the operation iterations times and measures how long every run took
+fn time<F: FnMut() -> Result<()>>(iterations: usize, mut operation: F) -> Result<Latency> {
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        operation()?;
+        samples.push(start.elapsed());
+    }
+    Latency::from_samples(&samples).ok_or_else(|| anyhow::anyhow!("No iterations to time"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_from_samples() {
+        let samples = [1, 4, 7].map(Duration::from_millis);
+
+        assert_eq!(
+            Latency::from_samples(&samples),
+            Some(Latency {
+                min: Duration::from_millis(1),
+                max: Duration::from_millis(7),
+                mean: Duration::from_millis(4),
+            })
+        );
+        assert_eq!(Latency::from_samples(&[]), None)
+    }
+
+    #[test]
+    fn run_bench_leaves_index_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = IndexBuilder::new()
+            .max_entries(1)
+            .open(&dir.path().join("scotty.db"))
+            .unwrap();
+        index.add(dir.path()).unwrap();
+        let entries = index.list().unwrap();
+
+        let report = run_bench(&index, "foo", 2, dir.path()).unwrap();
+
+        // The full index didn't evict its only path for the benchmarked one
+        assert_eq!(report.stats.paths, 1);
+        assert_eq!(index.list().unwrap(), entries);
+        // Only the add before the bench was journaled
+        assert!(index.undo().unwrap().is_some());
+        assert!(index.undo().unwrap().is_none());
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn time_runs_every_iteration() {
+        let mut runs = 0;
+
+        time(3, || {
+            runs += 1;
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(runs, 3)
+    }
+}
@@ -0,0 +1,15 @@
+
+# Complete the targets of scotty search with directories, instead of any file
+_scotty_search() {
+    if [[ "${COMP_WORDS[1]}" == "search" && "${COMP_WORDS[COMP_CWORD]}" != -* ]]; then
+        COMPREPLY=( $(compgen -d -- "${COMP_WORDS[COMP_CWORD]}") )
+        return 0
+    fi
+    _scotty "$@"
+}
+
+if [[ "${BASH_VERSINFO[0]}" -eq 4 && "${BASH_VERSINFO[1]}" -ge 4 || "${BASH_VERSINFO[0]}" -gt 4 ]]; then
+    complete -F _scotty_search -o nosort -o bashdefault -o default scotty
+else
+    complete -F _scotty_search -o bashdefault -o default scotty
+fi
@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{self, Write};
+
+use clap::Command;
+use clap_complete::Shell;
+
+// clap_complete completes positional arguments in bash with any file, this replaces its
+// completion function with one that completes the targets of search with directories
+const BASH_SEARCH_COMPLETION: &str = include_str!("completions.bash");
+// In zsh the targets are completed with the components of the indexed paths
+const ZSH_SEARCH_COMPLETION: &str = include_str!("completions.zsh");
+
+/// Writes the completion script of the cli for a specific shell
+/// The zsh script can be eval'ed in .zshrc or saved as `_scotty` in a directory on $fpath
+pub fn write_completions(shell: Shell, cli: &mut Command, buf: &mut dyn Write) -> io::Result<()> {
+    let name = cli.get_name().to_owned();
+    match shell {
+        Shell::Bash => {
+            clap_complete::generate(shell, cli, name, buf);
+            buf.write_all(BASH_SEARCH_COMPLETION.as_bytes())
+        }
+        Shell::Zsh => {
+            let mut script = Vec::new();
+            clap_complete::generate(shell, cli, name, &mut script);
+            buf.write_all(complete_zsh_targets(&String::from_utf8_lossy(&script)).as_bytes())
+        }
+        _ => {
+            clap_complete::generate(shell, cli, name, buf);
+            Ok(())
+        }
+    }
+}
+
+// Replaces the directory completion of the target arguments with _scotty_search_targets
+// Its definition goes right below the #compdef line, so it also exists when the script is
+// autoloaded from $fpath, where the script only runs on the first completion
+fn complete_zsh_targets(script: &str) -> String {
+    let (compdef, body) = script.split_once('\n').unwrap_or((script, ""));
+    let body = body
+        .lines()
+        .map(|line| match line.starts_with("'*::target") {
+            true => line.replace(":_files -/'", ":_scotty_search_targets'"),
+            false => line.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n{}{}\n", compdef, ZSH_SEARCH_COMPLETION, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    fn test_cli() -> Command {
+        Command::new("scotty").subcommand(
+            Command::new("search").arg(
+                Arg::new("target")
+                    .value_hint(clap::ValueHint::DirPath)
+                    .num_args(1..),
+            ),
+        )
+    }
+
+    #[test]
+    fn write_completions_bash() {
+        let mut buf = Vec::new();
+
+        write_completions(Shell::Bash, &mut test_cli(), &mut buf).unwrap();
+
+        let script = String::from_utf8(buf).unwrap();
+        assert!(script.contains("_scotty()"));
+        assert!(script.contains("scotty,search)"));
+        assert!(script.ends_with(BASH_SEARCH_COMPLETION))
+    }
+
+    #[test]
+    fn write_completions_zsh() {
+        let mut buf = Vec::new();
+
+        write_completions(Shell::Zsh, &mut test_cli(), &mut buf).unwrap();
+
+        let script = String::from_utf8(buf).unwrap();
+        assert!(script.starts_with("#compdef scotty\n"));
+        assert!(!script.contains("_scotty_search()"));
+        assert!(script.contains("'*::target:_scotty_search_targets'"));
+        assert!(!script.contains("_files -/"));
+        assert!(
+            script.find("_scotty_search_targets() {").unwrap()
+                < script.find("_scotty() {").unwrap()
+        )
+    }
+}
@@ -2,19 +2,458 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
 use directories::ProjectDirs;
+use serde::Deserialize;
+use thiserror::Error;
+
+use scotty::{CaseSensitivity, IndexError, Matcher};
+
+const CONFIG_FILE: &str = "config.toml";
+const INDEX_FILE: &str = "scotty.db";
+const APPLICATION_DIR: &str = "scotty";
+// Overrides max_entries from the config file, e.g. for embedded environments without one
+const MAX_ENTRIES_VAR: &str = "SCOTTY_MAX_ENTRIES";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DirectoryError {
+    #[error("Could not determine a data directory, set XDG_DATA_HOME to an absolute path")]
+    NoDataDirectory,
+    #[error("Could not determine a cache directory, set XDG_CACHE_HOME to an absolute path")]
+    NoCacheDirectory,
+}
+
+/// The locations scotty keeps its files in
+#[derive(Debug, PartialEq, Eq)]
+pub struct DataPaths {
+    /// The database with the index, kept in the data directory
+    pub index: PathBuf,
+    /// Scratch files that can be removed at any time, kept in the cache directory
+    /// sled keeps all of its files in the database directory, so this only holds files
+    /// created by scotty itself
+    pub cache: PathBuf,
+}
+
+/// User settings, read from `config.toml` in the config directory for this application
+/// (e.g. `~/.config/scotty/config.toml` on Linux).
+/// Every setting is optional and falls back to its default value when it is not present.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The number of days after which the weight of a visited directory has halved
+    pub half_life_days: u64,
+    /// Adding a directory again within this many seconds is ignored
+    pub debounce_seconds: u64,
+    /// Directories that have not been visited for this many days are removed automatically
+    /// 0 disables this
+    pub max_age_days: u64,
+    /// The maximum number of directories in the index, the lowest ranked ones are removed
+    /// when a new directory is added beyond this. 0 means there is no limit
+    pub max_entries: usize,
+    /// How much the score of a directory that no longer exists is lowered when searching
+    /// 0 disables this
+    pub missing_penalty: i64,
+    /// The fuzzy matching algorithm that scores the search results: `clangd` or `skim`
+    pub matcher: Matcher,
+    /// Whether the case of the search terms has to match the directory
+    pub case_sensitive: bool,
+    /// When the case of the search terms has to match: `sensitive`, `insensitive` or `smart`
+    /// Overrides case_sensitive when it is set
+    pub case: Option<CaseSensitivity>,
+    /// Whether the search terms only match the last component of a path
+    pub basename: bool,
+    /// The points a directory gets on top of its match score for every (decayed) visit
+    /// 0 only uses the visits to break ties
+    pub frecency_weight: i64,
+    /// The points a directory gets when its name matches the last search term
+    pub name_bonus: i64,
+    /// Glob patterns of directories that are never added to the index (e.g. `**/node_modules`)
+    pub ignore: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            half_life_days: 30,
+            debounce_seconds: 60,
+            max_age_days: 0,
+            max_entries: 0,
+            missing_penalty: 1000,
+            matcher: Matcher::default(),
+            case_sensitive: false,
+            case: None,
+            basename: false,
+            frecency_weight: 0,
+            name_bonus: 100,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // A number of days too large to represent is as good as forever, here and in max_age
+    pub fn half_life(&self) -> Duration {
+        Duration::from_secs(self.half_life_days.saturating_mul(24 * 60 * 60))
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce_seconds)
+    }
+
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age_days.saturating_mul(24 * 60 * 60))
+    }
+
+    pub fn case_sensitivity(&self) -> CaseSensitivity {
+        match self.case {
+            Some(case) => case,
+            None if self.case_sensitive => CaseSensitivity::Sensitive,
+            None => CaseSensitivity::Insensitive,
+        }
+    }
+}
+
+/// Returns the paths of the database and the cache directory
+/// `$XDG_DATA_HOME/scotty` and `$XDG_CACHE_HOME/scotty` are used when these variables are set
+/// to an absolute path (on every OS), otherwise the directories for this application based
+/// on host OS standards are used. An index that already exists in the OS data directory is
+/// kept over a new one in `$XDG_DATA_HOME`, with a warning.
+pub fn get_data_paths() -> Result<DataPaths, DirectoryError> {
+    resolve_data_paths(
+        |name| env::var_os(name),
+        get_project_dirs().ok().as_ref(),
+        Path::exists,
+    )
+}
+
+/// Returns the path of the database, in the data directory for this application
+pub fn get_index_path() -> Result<PathBuf, DirectoryError> {
+    Ok(get_data_paths()?.index)
+}
+
+/// Returns the directory that backups of the index at index_path are written to by default,
+/// next to the database
+pub fn get_backup_dir(index_path: &Path) -> PathBuf {
+    index_path.with_file_name("backups")
+}
+
+/// Returns the cache directory for this application, creating it if it doesn't exist yet
+pub fn get_cache_dir() -> Result<PathBuf> {
+    let cache_dir = get_data_paths()?.cache;
+    fs::create_dir_all(&cache_dir)?;
+    Ok(cache_dir)
+}
+
+/// Returns the total size of the files in path and every directory below it, in bytes
+/// sled stores the database as a directory, so this is how much space it takes up on disk
+pub fn disk_usage(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += disk_usage(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+fn resolve_data_paths<V, E>(
+    lookup_var: V,
+    project_dirs: Option<&ProjectDirs>,
+    exists: E,
+) -> Result<DataPaths, DirectoryError>
+where
+    V: Fn(&str) -> Option<OsString>,
+    E: Fn(&Path) -> bool,
+{
+    // The XDG spec says relative paths are invalid and should be ignored
+    let xdg_dir = |name: &str| {
+        lookup_var(name)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join(APPLICATION_DIR))
+    };
+    let os_data_dir = project_dirs.map(|dirs| dirs.data_dir().to_path_buf());
+    let data_dir = match (xdg_dir("XDG_DATA_HOME"), os_data_dir) {
+        // Outside of Linux the OS data directory isn't XDG_DATA_HOME, setting it shouldn't
+        // silently start over with an empty index
+        (Some(xdg), Some(os)) if !exists(&xdg.join(INDEX_FILE)) && exists(&os.join(INDEX_FILE)) => {
+            tracing::warn!(
+                "Using the existing index in `{}`, move it to `{}` to use XDG_DATA_HOME",
+                os.display(),
+                xdg.display()
+            );
+            os
+        }
+        (Some(xdg), _) => xdg,
+        (None, os) => os.ok_or(DirectoryError::NoDataDirectory)?,
+    };
+    let cache = xdg_dir("XDG_CACHE_HOME")
+        .or_else(|| project_dirs.map(|dirs| dirs.cache_dir().to_path_buf()))
+        .ok_or(DirectoryError::NoCacheDirectory)?;
+    Ok(DataPaths {
+        index: data_dir.join(INDEX_FILE),
+        cache,
+    })
+}
+
+/// Reads the user settings from the config directory for this application
+/// A missing config file is not an error, it just results in the default settings
+/// SCOTTY_MAX_ENTRIES takes precedence over max_entries in the config file
+pub fn load_config() -> Result<Config> {
+    let config_path = get_project_dirs()?.config_dir().join(CONFIG_FILE);
+    tracing::debug!("Loading config from: {}", config_path.display());
+    let config = match fs::read_to_string(&config_path) {
+        Ok(contents) => parse_config(&contents)
+            .with_context(|| format!("Invalid config file `{}`", config_path.display()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(e.into()),
+    };
+    apply_env_overrides(config, |name| env::var_os(name))
+}
+
+fn apply_env_overrides<V>(mut config: Config, lookup_var: V) -> Result<Config>
+where
+    V: Fn(&str) -> Option<OsString>,
+{
+    if let Some(value) = lookup_var(MAX_ENTRIES_VAR) {
+        config.max_entries = value
+            .to_str()
+            .and_then(|value| value.trim().parse().ok())
+            .with_context(|| {
+                format!(
+                    "{} must be a number, got `{}`",
+                    MAX_ENTRIES_VAR,
+                    value.to_string_lossy()
+                )
+            })?;
+    }
+    Ok(config)
+}
+
+fn parse_config(contents: &str) -> Result<Config, toml::de::Error> {
+    toml::from_str(contents)
+}
+
+fn get_project_dirs() -> Result<ProjectDirs, IndexError> {
+    ProjectDirs::from("com", "wdullaer", "scotty").ok_or(IndexError::BadDataDirectory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config_empty() {
+        assert_eq!(parse_config("").unwrap(), Config::default())
+    }
+
+    #[test]
+    fn parse_config_half_life() {
+        let config = parse_config("half_life_days = 7").unwrap();
+
+        assert_eq!(config.half_life_days, 7);
+        assert_eq!(config.half_life(), Duration::from_secs(7 * 24 * 60 * 60));
+        let config = parse_config("half_life_days = 300000000000000").unwrap();
+        assert_eq!(config.half_life(), Duration::from_secs(u64::MAX))
+    }
+
+    #[test]
+    fn parse_config_debounce() {
+        let config = parse_config("debounce_seconds = 0").unwrap();
+
+        assert_eq!(config.debounce(), Duration::ZERO);
+        assert_eq!(config.half_life_days, Config::default().half_life_days)
+    }
+
+    #[test]
+    fn parse_config_ignore() {
+        let config = parse_config(r#"ignore = ["**/node_modules", "**/.git"]"#).unwrap();
+
+        assert_eq!(config.ignore, vec!["**/node_modules", "**/.git"])
+    }
+
+    #[test]
+    fn parse_config_max_age() {
+        assert_eq!(Config::default().max_age(), Duration::ZERO);
+        assert_eq!(
+            parse_config("max_age_days = 180").unwrap().max_age(),
+            Duration::from_secs(180 * 24 * 60 * 60)
+        );
+        assert_eq!(
+            parse_config("max_age_days = 300000000000000")
+                .unwrap()
+                .max_age(),
+            Duration::from_secs(u64::MAX)
+        )
+    }
+
+    #[test]
+    fn parse_config_max_entries() {
+        assert_eq!(Config::default().max_entries, 0);
+        assert_eq!(parse_config("max_entries = 500").unwrap().max_entries, 500)
+    }
+
+    #[test]
+    fn parse_config_missing_penalty() {
+        assert_eq!(Config::default().missing_penalty, 1000);
+        assert_eq!(
+            parse_config("missing_penalty = 0").unwrap().missing_penalty,
+            0
+        )
+    }
+
+    #[test]
+    fn parse_config_scoring() {
+        let config =
+            parse_config("matcher = \"skim\"\ncase_sensitive = true\nfrecency_weight = 5").unwrap();
+
+        assert_eq!(config.matcher, Matcher::Skim);
+        assert!(config.case_sensitive);
+        assert_eq!(config.frecency_weight, 5);
+        assert_eq!(config.name_bonus, 100);
+        assert!(parse_config("matcher = \"fzf\"").is_err())
+    }
+
+    #[test]
+    fn parse_config_case() {
+        assert_eq!(
+            Config::default().case_sensitivity(),
+            CaseSensitivity::Insensitive
+        );
+        assert_eq!(
+            parse_config("case_sensitive = true")
+                .unwrap()
+                .case_sensitivity(),
+            CaseSensitivity::Sensitive
+        );
+        assert_eq!(
+            parse_config("case_sensitive = true\ncase = \"smart\"")
+                .unwrap()
+                .case_sensitivity(),
+            CaseSensitivity::Smart
+        );
+        assert!(parse_config("case = \"upper\"").is_err())
+    }
+
+    #[test]
+    fn parse_config_basename() {
+        assert!(!Config::default().basename);
+        assert!(parse_config("basename = true").unwrap().basename)
+    }
+
+    #[test]
+    fn parse_config_unknown_key() {
+        assert!(parse_config("foo = 1").is_err())
+    }
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), OsString::from(value)))
+            .collect::<Vec<_>>();
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
+    #[test]
+    fn apply_env_overrides_max_entries() {
+        let config = parse_config("max_entries = 500").unwrap();
+
+        let result = apply_env_overrides(config, lookup(&[("SCOTTY_MAX_ENTRIES", "200")]));
+
+        assert_eq!(result.unwrap().max_entries, 200);
+        let result = apply_env_overrides(Config::default(), lookup(&[]));
+        assert_eq!(result.unwrap().max_entries, 0);
+        let result =
+            apply_env_overrides(Config::default(), lookup(&[("SCOTTY_MAX_ENTRIES", "-1")]));
+        assert!(result.is_err())
+    }
+
+    #[test]
+    fn resolve_data_paths_xdg() {
+        let vars = lookup(&[("XDG_DATA_HOME", "/data"), ("XDG_CACHE_HOME", "/cache")]);
+
+        assert_eq!(
+            resolve_data_paths(vars, None, |_| false),
+            Ok(DataPaths {
+                index: PathBuf::from("/data/scotty/scotty.db"),
+                cache: PathBuf::from("/cache/scotty"),
+            })
+        )
+    }
+
+    #[test]
+    fn resolve_data_paths_fallback() {
+        let dirs = get_project_dirs().unwrap();
+        let vars = lookup(&[("XDG_DATA_HOME", "relative"), ("XDG_CACHE_HOME", "/cache")]);
+
+        assert_eq!(
+            resolve_data_paths(vars, Some(&dirs), |_| false),
+            Ok(DataPaths {
+                index: dirs.data_dir().join("scotty.db"),
+                cache: PathBuf::from("/cache/scotty"),
+            })
+        )
+    }
+
+    #[test]
+    fn resolve_data_paths_missing() {
+        assert_eq!(
+            resolve_data_paths(lookup(&[]), None, |_| false),
+            Err(DirectoryError::NoDataDirectory)
+        );
+        assert_eq!(
+            resolve_data_paths(lookup(&[("XDG_DATA_HOME", "/data")]), None, |_| false),
+            Err(DirectoryError::NoCacheDirectory)
+        )
+    }
+
+    #[test]
+    fn disk_usage_sums_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("blobs")).unwrap();
+        fs::write(dir.path().join("db"), [0; 100]).unwrap();
+        fs::write(dir.path().join("blobs").join("1"), [0; 20]).unwrap();
+
+        assert_eq!(disk_usage(dir.path()).unwrap(), 120);
+        assert_eq!(disk_usage(&dir.path().join("db")).unwrap(), 100);
+        assert!(disk_usage(&dir.path().join("missing")).is_err());
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn resolve_data_paths_existing_index() {
+        let dirs = get_project_dirs().unwrap();
+        let os_index = dirs.data_dir().join("scotty.db");
+        let xdg_index = PathBuf::from("/data/scotty/scotty.db");
+        let vars = || lookup(&[("XDG_DATA_HOME", "/data"), ("XDG_CACHE_HOME", "/cache")]);
+        let resolve = |existing: &[&PathBuf]| {
+            resolve_data_paths(vars(), Some(&dirs), |path| {
+                existing.iter().any(|existing| existing.as_path() == path)
+            })
+            .unwrap()
+            .index
+        };
 
-use crate::index::IndexError;
-
-/// Constructs a sled config that will write the db to
-/// the data directory for this application based on host OS standards
-/// It will return an error if no data directory can be determined (we
-/// might make this location configurable in the future)
-pub fn get_index_config() -> Result<sled::Config, IndexError> {
-    let mut db_path = ProjectDirs::from("com", "wdullaer", "scotty")
-        .ok_or(IndexError::BadDataDirectory)?
-        .data_dir()
-        .to_path_buf();
-    db_path.push("scotty.db");
-    Ok(sled::Config::new().path(db_path.as_path()))
+        // An index in the OS data directory is kept, until there is one in XDG_DATA_HOME
+        assert_eq!(resolve(&[&os_index]), os_index);
+        assert_eq!(resolve(&[&os_index, &xdg_index]), xdg_index);
+        assert_eq!(resolve(&[]), xdg_index);
+        assert_eq!(
+            get_backup_dir(&xdg_index),
+            PathBuf::from("/data/scotty/backups")
+        )
+    }
 }
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::NaiveDate;
+use thiserror::Error;
+
+use crate::duration::parse_duration;
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d"];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DateParseError {
+    #[error(
+        "`{0}` is not a recognized date. Use YYYY-MM-DD, YYYY/MM/DD or a relative date like 7d or 1w"
+    )]
+    Unrecognized(String),
+}
+
+/// A half-open range of time, used to filter index entries by when they were last visited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    /// Inclusive lower bound
+    pub since: Option<SystemTime>,
+    /// Exclusive upper bound
+    pub before: Option<SystemTime>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: SystemTime) -> bool {
+        self.since.is_none_or(|since| time >= since)
+            && self.before.is_none_or(|before| time < before)
+    }
+}
+
+/// Parses a date argument into a point in time
+/// Absolute dates (YYYY-MM-DD or YYYY/MM/DD) are interpreted as midnight UTC, relative dates
+/// (durations like 7d or 1w) are interpreted as that amount of time before `now`
+pub fn parse_date(input: &str, now: SystemTime) -> Result<SystemTime, DateParseError> {
+    let input = input.trim();
+    if let Some(date) = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(input, format).ok())
+    {
+        let timestamp = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+        return system_time_from_timestamp(timestamp)
+            .ok_or_else(|| DateParseError::Unrecognized(input.to_owned()));
+    }
+
+    parse_duration(input)
+        .ok()
+        .and_then(|duration| now.checked_sub(duration))
+        .ok_or_else(|| DateParseError::Unrecognized(input.to_owned()))
+}
+
+fn system_time_from_timestamp(timestamp: i64) -> Option<SystemTime> {
+    if timestamp >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(timestamp as u64))
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(Duration::from_secs(timestamp.unsigned_abs()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn parse_date_iso() {
+        let result = parse_date("2024-01-01", SystemTime::now()).unwrap();
+
+        assert_eq!(
+            result,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200)
+        )
+    }
+
+    #[test]
+    fn parse_date_slashes() {
+        let result = parse_date("2024/01/01", SystemTime::now()).unwrap();
+
+        assert_eq!(
+            result,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200)
+        )
+    }
+
+    #[test]
+    fn parse_date_relative_days() {
+        let now = SystemTime::now();
+
+        assert_eq!(parse_date("7d", now).unwrap(), now - 7 * DAY)
+    }
+
+    #[test]
+    fn parse_date_relative_weeks() {
+        let now = SystemTime::now();
+
+        assert_eq!(parse_date("1w", now).unwrap(), now - 7 * DAY)
+    }
+
+    #[test]
+    fn parse_date_invalid() {
+        for input in ["", "yesterday", "7", "d", "7x", "2024-13-01", "-7d"] {
+            assert_eq!(
+                parse_date(input, SystemTime::now()),
+                Err(DateParseError::Unrecognized(input.to_owned()))
+            )
+        }
+    }
+
+    #[test]
+    fn time_range_contains() {
+        let now = SystemTime::now();
+        let range = TimeRange {
+            since: Some(now - 7 * DAY),
+            before: Some(now),
+        };
+
+        assert!(range.contains(now - 7 * DAY));
+        assert!(range.contains(now - DAY));
+        assert!(!range.contains(now));
+        assert!(!range.contains(now - 8 * DAY));
+        assert!(TimeRange::default().contains(now))
+    }
+}
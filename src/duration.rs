@@ -0,0 +1,98 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("`{0}` is not a valid duration. Use a number followed by a unit: h, d, w, mo or y (e.g. 30d)")]
+    InvalidDuration(String),
+}
+
+/// Parses a human readable duration of the form <number><unit>
+/// Supported units are h (hours), d (days), w (weeks), mo (months of 30 days) and y (years
+/// of 365 days). Calendar months and years vary in length, so these are approximations.
+pub fn parse_duration(input: &str) -> Result<Duration, ParseError> {
+    let input = input.trim();
+    let invalid = || ParseError::InvalidDuration(input.to_owned());
+
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = input.split_at(split);
+    let amount = amount.parse::<u64>().map_err(|_| invalid())?;
+    let seconds_per_unit = match unit {
+        "h" => HOUR,
+        "d" => DAY,
+        "w" => 7 * DAY,
+        "mo" => 30 * DAY,
+        "y" => 365 * DAY,
+        _ => return Err(invalid()),
+    };
+
+    amount
+        .checked_mul(seconds_per_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_hours() {
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * HOUR)))
+    }
+
+    #[test]
+    fn parse_duration_days() {
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * DAY)))
+    }
+
+    #[test]
+    fn parse_duration_weeks() {
+        assert_eq!(parse_duration("4w"), Ok(Duration::from_secs(28 * DAY)))
+    }
+
+    #[test]
+    fn parse_duration_months() {
+        assert_eq!(parse_duration("6mo"), Ok(Duration::from_secs(180 * DAY)))
+    }
+
+    #[test]
+    fn parse_duration_years() {
+        assert_eq!(parse_duration("1y"), Ok(Duration::from_secs(365 * DAY)))
+    }
+
+    #[test]
+    fn parse_duration_whitespace() {
+        assert_eq!(parse_duration(" 1d "), Ok(Duration::from_secs(DAY)))
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        for input in [
+            "",
+            "30",
+            "d",
+            "-1d",
+            "1.5d",
+            "1 d",
+            "1m",
+            "1dd",
+            "99999999999999999y",
+        ] {
+            assert_eq!(
+                parse_duration(input),
+                Err(ParseError::InvalidDuration(input.trim().to_owned()))
+            )
+        }
+    }
+}
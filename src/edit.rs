@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Result;
+use thiserror::Error;
+
+use scotty::{validate_path, IndexError};
+
+const HEADER: &str = "\
+# Remove a line to remove the path from the index, add a line to add a path.
+# Lines starting with # and empty lines are ignored.
+";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EditError {
+    #[error("Line {0}: {1}")]
+    InvalidLine(usize, IndexError),
+    #[error("Editor `{0}` did not exit successfully, the index was not changed")]
+    EditorFailed(String),
+}
+
+/// The changes made to the list of paths in the editor
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct EditDiff {
+    pub removed: Vec<PathBuf>,
+    pub added: Vec<PathBuf>,
+}
+
+impl EditDiff {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Writes the paths to a temporary file, opens it in the editor of the user ($VISUAL or
+/// $EDITOR, falling back to vi) and returns its contents once the editor exits
+/// The temporary file is created in scratch_dir
+pub fn edit_paths(paths: &[PathBuf], scratch_dir: &Path) -> Result<String> {
+    let mut file = tempfile::Builder::new()
+        .prefix("scotty-")
+        .suffix(".txt")
+        .tempfile_in(scratch_dir)?;
+    file.write_all(format_paths(paths).as_bytes())?;
+    file.flush()?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    tracing::debug!("Editing {} with: {}", file.path().display(), editor);
+    // The editor can come with arguments, like `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+    let status = Command::new(program).args(args).arg(file.path()).status()?;
+    if !status.success() {
+        return Err(EditError::EditorFailed(editor).into());
+    }
+
+    Ok(fs::read_to_string(file.path())?)
+}
+
+/// Formats the paths one per line, after a header that explains how to edit them
+pub fn format_paths(paths: &[PathBuf]) -> String {
+    let mut contents = HEADER.to_owned();
+    for path in paths {
+        contents.push_str(&path.to_string_lossy());
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Compares the edited list of paths to the original one
+/// Every added path has to be valid, otherwise the line of the first invalid path is returned
+pub fn diff_paths(original: &[PathBuf], edited: &str) -> Result<EditDiff, EditError> {
+    let original_set = original
+        .iter()
+        .map(PathBuf::as_path)
+        .collect::<HashSet<_>>();
+    let mut edited_set = HashSet::new();
+    let mut added = Vec::new();
+    for (number, line) in edited.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = Path::new(line);
+        if !edited_set.insert(path) || original_set.contains(path) {
+            continue;
+        }
+        validate_path(path).map_err(|e| EditError::InvalidLine(number + 1, e))?;
+        added.push(path.to_path_buf());
+    }
+
+    let removed = original
+        .iter()
+        .filter(|path| !edited_set.contains(path.as_path()))
+        .cloned()
+        .collect();
+    Ok(EditDiff { removed, added })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn diff_paths_unchanged() {
+        let original = vec![PathBuf::from("/foo"), PathBuf::from("/bar")];
+
+        let result = diff_paths(&original, &format_paths(&original)).unwrap();
+
+        assert!(result.is_empty())
+    }
+
+    #[test]
+    fn diff_paths_removed_and_added() {
+        let dir = tempdir().unwrap();
+        let original = vec![PathBuf::from("/foo"), PathBuf::from("/bar")];
+        let edited = format!("# comment\n/bar\n\n  {0}  \n{0}\n", dir.path().display());
+
+        let result = diff_paths(&original, &edited).unwrap();
+
+        assert_eq!(
+            result,
+            EditDiff {
+                removed: vec![PathBuf::from("/foo")],
+                added: vec![dir.path().to_owned()],
+            }
+        );
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn diff_paths_invalid_line() {
+        let original = vec![PathBuf::from("/foo")];
+
+        let result = diff_paths(&original, "/foo\nrelative\n");
+
+        assert_eq!(
+            result,
+            Err(EditError::InvalidLine(
+                2,
+                IndexError::PathDoesNotExist("relative".to_owned())
+            ))
+        )
+    }
+}
@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::env;
+use std::path::PathBuf;
+
+use directories::BaseDirs;
+
+/// Expands a leading `~` or `~user` into the home directory and `$VAR` or `${VAR}` into the
+/// value of the environment variable, like a shell would do for an unquoted word
+/// Anything that can't be expanded (an unknown user or variable) is left as it is
+pub fn expand_target(target: &str) -> String {
+    expand(target, home_dir, user_home_dir, |name| env::var(name).ok())
+}
+
+fn expand<H, U, V>(target: &str, home_dir: H, user_home_dir: U, lookup_var: V) -> String
+where
+    H: Fn() -> Option<PathBuf>,
+    U: Fn(&str) -> Option<PathBuf>,
+    V: Fn(&str) -> Option<String>,
+{
+    let (prefix, rest) = match target.strip_prefix('~') {
+        Some(after_tilde) => {
+            let user_end = after_tilde.find('/').unwrap_or(after_tilde.len());
+            let (user, rest) = after_tilde.split_at(user_end);
+            let home = if user.is_empty() {
+                home_dir()
+            } else {
+                user_home_dir(user)
+            };
+            match home {
+                Some(home) => (home.to_string_lossy().into_owned(), rest),
+                None => (String::new(), target),
+            }
+        }
+        None => (String::new(), target),
+    };
+
+    prefix + &expand_vars(rest, lookup_var)
+}
+
+fn expand_vars<V: Fn(&str) -> Option<String>>(input: &str, lookup_var: V) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after_dollar = &rest[start + 1..];
+        let (name, reference_len) = match after_dollar.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after_dollar
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after_dollar.len());
+                (&after_dollar[..end], end)
+            }
+        };
+
+        match Some(name)
+            .filter(|name| is_var_name(name))
+            .and_then(&lookup_var)
+        {
+            Some(value) => {
+                output.push_str(&value);
+                rest = &after_dollar[reference_len..];
+            }
+            None => {
+                output.push('$');
+                rest = after_dollar;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn is_var_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the home directory of the current user
+pub fn home_dir() -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf())
+}
+
+#[cfg(unix)]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    use uzers::os::unix::UserExt;
+
+    uzers::get_user_by_name(user).map(|user| user.home_dir().to_path_buf())
+}
+
+#[cfg(not(unix))]
+fn user_home_dir(_user: &str) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_test(target: &str) -> String {
+        expand(
+            target,
+            || Some(PathBuf::from("/home/me")),
+            |user| (user == "other").then(|| PathBuf::from("/home/other")),
+            |name| (name == "PROJECTS").then(|| "/src".to_owned()),
+        )
+    }
+
+    #[test]
+    fn expand_tilde() {
+        assert_eq!(expand_test("~"), "/home/me")
+    }
+
+    #[test]
+    fn expand_tilde_slash() {
+        assert_eq!(expand_test("~/"), "/home/me/");
+        assert_eq!(expand_test("~/pro"), "/home/me/pro")
+    }
+
+    #[test]
+    fn expand_tilde_user() {
+        assert_eq!(expand_test("~other/pro"), "/home/other/pro");
+        assert_eq!(expand_test("~nobody/pro"), "~nobody/pro")
+    }
+
+    #[test]
+    fn expand_tilde_not_leading() {
+        assert_eq!(expand_test("pro~/foo"), "pro~/foo")
+    }
+
+    #[test]
+    fn expand_tilde_without_home() {
+        assert_eq!(expand("~/pro", || None, |_| None, |_| None), "~/pro")
+    }
+
+    #[test]
+    fn expand_var() {
+        assert_eq!(expand_test("$PROJECTS/foo"), "/src/foo");
+        assert_eq!(expand_test("${PROJECTS}foo"), "/srcfoo");
+        assert_eq!(expand_test("~/$PROJECTS"), "/home/me//src")
+    }
+
+    #[test]
+    fn expand_missing_var() {
+        assert_eq!(expand_test("$MISSING/foo"), "$MISSING/foo");
+        assert_eq!(expand_test("${MISSING}"), "${MISSING}")
+    }
+
+    #[test]
+    fn expand_not_a_var() {
+        for input in ["$", "foo$", "$1", "${", "${PROJECTS", "$-foo", "${}"] {
+            assert_eq!(expand_test(input), input)
+        }
+    }
+}
@@ -0,0 +1,289 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use scotty::{Index, PathIndexEntry};
+
+use crate::date::TimeRange;
+
+/// Version of the export format, bump this whenever a change is not backwards compatible
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ExportError {
+    #[error("Unsupported export schema version {0}, this version of scotty supports version {SCHEMA_VERSION}")]
+    UnsupportedSchema(u32),
+}
+
+/// The stable serialization of the index, which can be moved between machines and versions
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Export {
+    schema: u32,
+    entries: Vec<ExportEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportEntry {
+    pub path: PathBuf,
+    pub timestamp: DateTime<Utc>,
+    pub count: u64,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub weight: i64,
+    #[serde(default)]
+    pub hits: u64,
+}
+
+impl From<&PathIndexEntry> for ExportEntry {
+    fn from(entry: &PathIndexEntry) -> Self {
+        ExportEntry {
+            path: entry.path.clone(),
+            timestamp: entry.timestamp.into(),
+            count: entry.count,
+            pinned: entry.pinned,
+            weight: entry.weight,
+            hits: entry.hits,
+        }
+    }
+}
+
+impl From<ExportEntry> for PathIndexEntry {
+    fn from(entry: ExportEntry) -> Self {
+        PathIndexEntry {
+            timestamp: entry.timestamp.into(),
+            path: entry.path,
+            count: entry.count,
+            pinned: entry.pinned,
+            weight: entry.weight,
+            hits: entry.hits,
+        }
+    }
+}
+
+/// Returns the entries of the index that were last visited in the range, ordered by path
+pub fn list_range(index: &Index, range: TimeRange) -> Result<Vec<PathIndexEntry>> {
+    let mut entries = index.list()?;
+    entries.retain(|entry| range.contains(entry.timestamp));
+    Ok(entries)
+}
+
+/// Writes the index entries as a json export document
+/// JSON can only represent paths that are valid UTF-8, other paths are skipped with a warning
+pub fn write_export<W: Write>(writer: W, index_entries: &[PathIndexEntry]) -> Result<()> {
+    let entries = index_entries
+        .iter()
+        .filter(|entry| {
+            let is_utf8 = entry.path.to_str().is_some();
+            if !is_utf8 {
+                tracing::warn!("Skipping non UTF-8 path: {}", entry.path.display());
+            }
+            is_utf8
+        })
+        .map(ExportEntry::from)
+        .collect();
+    let export = Export {
+        schema: SCHEMA_VERSION,
+        entries,
+    };
+    serde_json::to_writer_pretty(writer, &export)?;
+    Ok(())
+}
+
+/// Replaces the file at path by an export of the entries
+/// The export is written next to it first and then renamed, so an interrupted write never
+/// leaves a truncated file behind
+pub fn write_export_file(path: &Path, entries: &[PathIndexEntry]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut file = tempfile::Builder::new()
+        .prefix(".scotty-")
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    {
+        let mut writer = BufWriter::new(file.as_file_mut());
+        write_export(&mut writer, entries)?;
+        writer.flush()?;
+    }
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Reads a json export document, produced by write_export, into index entries
+pub fn read_export<R: Read>(reader: R) -> Result<Vec<PathIndexEntry>> {
+    let export: Export = serde_json::from_reader(reader)?;
+    if export.schema != SCHEMA_VERSION {
+        return Err(ExportError::UnsupportedSchema(export.schema).into());
+    }
+    Ok(export
+        .entries
+        .into_iter()
+        .map(PathIndexEntry::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scotty::IndexBuilder;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::tempdir;
+
+    fn get_entries() -> Vec<PathIndexEntry> {
+        vec![
+            PathIndexEntry {
+                timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+                path: PathBuf::from("/foo"),
+                count: 3,
+                pinned: true,
+                weight: -10,
+                hits: 0,
+            },
+            PathIndexEntry {
+                timestamp: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+                path: PathBuf::from("/bar"),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        ]
+    }
+
+    #[test]
+    fn export_roundtrip() {
+        let mut buffer = Vec::new();
+
+        write_export(&mut buffer, &get_entries()).unwrap();
+        let result = read_export(buffer.as_slice()).unwrap();
+
+        assert_eq!(result, get_entries())
+    }
+
+    #[test]
+    fn export_format() {
+        let mut buffer = Vec::new();
+
+        write_export(&mut buffer, &get_entries()[1..]).unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "schema": 1,
+                "entries": [{
+                    "path": "/bar",
+                    "timestamp": "2020-09-13T12:26:40Z",
+                    "count": 1,
+                    "pinned": false,
+                    "weight": 0,
+                    "hits": 0
+                }]
+            })
+        )
+    }
+
+    #[test]
+    fn import_defaults_optional_fields() {
+        let input = r#"{"schema": 1, "entries": [{"path": "/bar", "timestamp": "2020-09-13T12:26:40Z", "count": 1}]}"#;
+
+        let result = read_export(input.as_bytes()).unwrap();
+
+        assert_eq!(result, get_entries()[1..])
+    }
+
+    #[test]
+    fn import_unsupported_schema() {
+        let input = r#"{"schema": 2, "entries": []}"#;
+
+        let result = read_export(input.as_bytes());
+
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ExportError>(),
+            Some(&ExportError::UnsupportedSchema(2))
+        )
+    }
+
+    #[test]
+    fn export_range_roundtrip() {
+        let dir = tempdir().unwrap();
+        let day = |n: u64| UNIX_EPOCH + Duration::from_secs(1_700_000_000 + n * 24 * 60 * 60);
+        let entries = (1..=3)
+            .map(|n| {
+                let path = dir.path().join(format!("day{}", n));
+                std::fs::create_dir(&path).unwrap();
+                PathIndexEntry {
+                    timestamp: day(n),
+                    path,
+                    count: n,
+                    pinned: false,
+                    weight: 0,
+                    hits: 0,
+                }
+            })
+            .collect::<Vec<_>>();
+        let source = IndexBuilder::new()
+            .open(&dir.path().join("source.db"))
+            .unwrap();
+        source.import(&entries).unwrap();
+        let target = IndexBuilder::new()
+            .open(&dir.path().join("target.db"))
+            .unwrap();
+        // The start of the range is included and the end is not
+        let range = TimeRange {
+            since: Some(day(2)),
+            before: Some(day(3)),
+        };
+
+        let mut buffer = Vec::new();
+        write_export(&mut buffer, &list_range(&source, range).unwrap()).unwrap();
+        target
+            .import(&read_export(buffer.as_slice()).unwrap())
+            .unwrap();
+
+        assert_eq!(target.list().unwrap(), entries[1..2]);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn write_export_file_replaces() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("remote.json");
+        std::fs::write(&path, "old").unwrap();
+
+        write_export_file(&path, &get_entries()).unwrap();
+
+        let read = read_export(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(read, get_entries());
+        // Only the export is left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        dir.close().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn export_skips_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut entries = get_entries();
+        entries[0].path = PathBuf::from(OsStr::from_bytes(b"/foo\xff"));
+        let mut buffer = Vec::new();
+
+        write_export(&mut buffer, &entries).unwrap();
+        let result = read_export(buffer.as_slice()).unwrap();
+
+        assert_eq!(result, get_entries()[1..])
+    }
+}
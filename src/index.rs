@@ -2,25 +2,92 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::fs;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::str;
-use std::time::SystemTime;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Result;
+use fs2::FileExt;
 use fst::automaton;
-use fst::{Automaton, IntoStreamer, Set, SetBuilder};
+use fst::set::OpBuilder;
+use fst::{Automaton, Set, SetBuilder, Streamer};
 use fuzzy_matcher::clangd::ClangdMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use regex_automata::dense::Builder;
-use serde::Serialize;
-use sled::{Config, Tree};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionError, Transactional,
+    TransactionalTree,
+};
+use sled::{Config, Db, Tree};
 use thiserror::Error;
 
 const PATHS_TREE: &str = "paths";
 const MAIN_TREE: &str = "main";
+const HISTORY_TREE: &str = "history";
+const JOURNAL_TREE: &str = "journal";
 const INDEX_KEY: &str = "index";
+const DELTA_KEY: &str = "index_delta";
+const LAST_EXPIRY_KEY: &str = "last_expiry";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+// The number of jumps that are remembered, the oldest ones are forgotten first
+const HISTORY_MAX_LEN: usize = 100;
+// The number of changes that can be undone, the oldest ones are forgotten first
+const JOURNAL_MAX_LEN: usize = 10;
+// Appended to the path of the database while it is being compacted
+const COMPACTING_SUFFIX: &str = ".compacting";
+const COMPACTED_SUFFIX: &str = ".compacted";
+// How long opening the database waits for another process to close it, and how often it checks
+const LOCK_TIMEOUT: Duration = Duration::from_millis(500);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+// Migrations of the data in the database, the migration at position n brings the database
+// from schema version n to n + 1. Only ever append to this list.
+const MIGRATIONS: &[fn(&Index) -> Result<()>] = &[migrate_full_records];
+
+// The schema version of the data this version of scotty writes
+const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// New paths are added to a small delta fst, which is merged into the main fst once it
+// holds more than this many entries. This keeps the cost of an add independent of the
+// size of the index for all but one in every DELTA_MAX_LEN adds.
+const DELTA_MAX_LEN: usize = 256;
+
+// A pinned path only wins from unpinned paths if its fuzzy score is above this threshold
+const PIN_SCORE_THRESHOLD: i64 = 0;
+
+// The last component of a path only counts as matching the last term if its fuzzy score is
+// above this threshold
+const NAME_SCORE_THRESHOLD: i64 = 0;
+
+// The default bonus for paths whose last component matches the last term
+const DEFAULT_NAME_BONUS: i64 = 100;
+
+// The manual weight of a path can not be adjusted beyond this value (in either direction)
+pub const MAX_WEIGHT: i64 = 100;
+
+// Added to the score of paths below the current directory, so they win from similar matches
+// elsewhere without overriding a much better match
+const CWD_BONUS: i64 = 30;
+
+// A jump to a path found by search counts this many times as much as a plain visit, so the
+// paths that are jumped to rank higher quicker than the ones that are passed through
+const HIT_WEIGHT: u64 = 4;
+
+// Automatic expiry of old paths runs at most once per EXPIRY_INTERVAL
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// The weight of a visited directory halves every DEFAULT_HALF_LIFE, unless configured otherwise
+const DEFAULT_HALF_LIFE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
 
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum IndexError {
@@ -32,45 +99,560 @@ pub enum IndexError {
     RelativePath(String),
     #[error("Could not determine writable location for index data")]
     BadDataDirectory,
+    #[error("Path `{0}` is not in the index")]
+    NotIndexed(String),
+    #[error(
+        "The index was written by a newer version of scotty (schema version {0}), upgrade scotty to use it"
+    )]
+    UnsupportedSchema(u32),
+    #[error("Can't move `{0}` into a directory below itself")]
+    MoveIntoSelf(String),
+    #[error("The search index is corrupt ({0}), run `scotty rebuild-index` to repair it")]
+    CorruptFst(String),
+    #[error("No jumps have been recorded yet")]
+    NoHistory,
+    #[error("There is nothing to undo")]
+    NothingToUndo,
+    #[error("The database `{0}` is in use by another scotty process, try again later")]
+    DatabaseLocked(String),
+}
+
+impl IndexError {
+    /// A stable identifier of the kind of error, for scripts that need to tell them apart
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IndexError::NoResults(_) => "no_results",
+            IndexError::PathDoesNotExist(_) => "path_does_not_exist",
+            IndexError::RelativePath(_) => "relative_path",
+            IndexError::BadDataDirectory => "bad_data_directory",
+            IndexError::NotIndexed(_) => "not_indexed",
+            IndexError::UnsupportedSchema(_) => "unsupported_schema",
+            IndexError::MoveIntoSelf(_) => "move_into_self",
+            IndexError::CorruptFst(_) => "corrupt_fst",
+            IndexError::NoHistory => "no_history",
+            IndexError::NothingToUndo => "nothing_to_undo",
+            IndexError::DatabaseLocked(_) => "database_locked",
+        }
+    }
+}
+
+/// How the search terms are matched against the paths in the index
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The characters of a term appear in the path in order, but not necessarily next to
+    /// each other
+    #[default]
+    Fuzzy,
+    /// Every term matches the start of a path component, the last one that of the final
+    /// component
+    Prefix,
+}
+
+/// The fuzzy matching algorithm that scores the search results
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Matcher {
+    /// The algorithm of the clangd code completion, which favours matches at word boundaries
+    #[default]
+    Clangd,
+    /// The algorithm of the skim fuzzy finder, which favours consecutive matches
+    Skim,
+}
+
+/// Whether the case of the search terms has to match the case of the paths
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseSensitivity {
+    /// The case has to match
+    Sensitive,
+    /// The case is ignored
+    #[default]
+    Insensitive,
+    /// The case has to match when a search term contains an uppercase character
+    Smart,
+}
+
+impl CaseSensitivity {
+    /// Returns whether a search for 'terms' has to match their case
+    pub fn is_sensitive(self, terms: &[&str]) -> bool {
+        match self {
+            CaseSensitivity::Sensitive => true,
+            CaseSensitivity::Insensitive => false,
+            CaseSensitivity::Smart => terms
+                .iter()
+                .any(|term| term.chars().any(char::is_uppercase)),
+        }
+    }
 }
 
+type TransactionResult<T> = ConflictableTransactionResult<T, anyhow::Error>;
+
 pub struct Index {
+    db: Db,
     main: Tree,
     paths: Tree,
+    history: Tree,
+    journal: Tree,
+    half_life: Duration,
+    debounce: Duration,
+    ignore: GlobSet,
+    max_age: Duration,
+    max_entries: usize,
+    missing_penalty: i64,
+    match_mode: MatchMode,
+    matcher: Matcher,
+    case_sensitivity: CaseSensitivity,
+    frecency_weight: i64,
+    name_bonus: i64,
+    fuzzy_scoring: bool,
+    basename: bool,
+    // Held as long as the database is open, see lock_database
+    _lock: Option<File>,
+}
+
+/// Configures an Index before it is opened, every setting has a default
+#[derive(Debug, Clone)]
+pub struct IndexBuilder {
+    half_life: Duration,
+    debounce: Duration,
+    ignore_patterns: Vec<String>,
+    max_age: Duration,
+    max_entries: usize,
+    missing_penalty: i64,
+    match_mode: MatchMode,
+    matcher: Matcher,
+    case_sensitivity: CaseSensitivity,
+    frecency_weight: i64,
+    name_bonus: i64,
+    fuzzy_scoring: bool,
+    basename: bool,
 }
 
 #[derive(Serialize, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct PathIndexEntry {
     pub timestamp: SystemTime,
     pub path: PathBuf,
+    pub count: u64,
+    pub pinned: bool,
+    pub weight: i64,
+    pub hits: u64,
 }
 
-impl Index {
+/// The outcome of adding several paths at once
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AddSummary {
+    /// The number of paths that were not indexed before
+    pub added: usize,
+    /// The number of paths that matched an ignore pattern
+    pub ignored: usize,
+    /// The paths that are not valid, together with the reason why
+    pub rejected: Vec<(PathBuf, IndexError)>,
+}
+
+/// The outcome of importing entries, see Index::import_with
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// The number of paths that were not indexed before
+    pub added: usize,
+    /// The number of indexed paths whose record was replaced by the imported entry
+    pub replaced: usize,
+    /// The number of indexed paths that kept their record
+    pub kept: usize,
+    /// The number of paths that matched an ignore pattern
+    pub ignored: usize,
+    /// The paths that are not valid, together with the reason why
+    pub rejected: Vec<(PathBuf, IndexError)>,
+}
+
+/// What adding a path to the index would do, see Index::check_add
+#[derive(Debug, PartialEq, Eq)]
+pub enum AddAction {
+    /// The path is new and would be added
+    Insert,
+    /// The path is indexed already, its visit would be recorded
+    Refresh,
+    /// The path matches an ignore pattern and would be skipped
+    Ignore,
+    /// The path is not valid
+    Reject(IndexError),
+}
+
+/// The differences between the paths tree and the fsts used to search it
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Paths that are stored, but can't be found by a search
+    pub missing_from_fst: Vec<PathBuf>,
+    /// Paths that can be found by a search, but are not stored
+    pub missing_from_paths: Vec<PathBuf>,
+    /// Stored paths that are relative or can't be converted back into the same bytes
+    pub invalid_paths: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Checks that every stored path can be searched and every searchable path is stored
+    pub fn is_consistent(&self) -> bool {
+        self.missing_from_fst.is_empty()
+            && self.missing_from_paths.is_empty()
+            && self.invalid_paths.is_empty()
+    }
+}
+
+/// Which spelling of a directory is kept when entries that resolve to it are merged
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DedupePreference {
+    /// The real path of the directory, with every symlink resolved
+    #[default]
+    Canonical,
+    /// The entry with the most visits, the most recently visited one on a tie
+    MostUsed,
+}
+
+/// Which record is kept when an imported entry is for a path that is already indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// The record in the index
+    KeepLocal,
+    /// The imported entry
+    KeepRemote,
+    /// Whichever was visited last, the record in the index on a tie
+    KeepNewer,
+}
+
+/// Indexed paths that resolve to the same directory, and the one they are merged into
+#[derive(Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub kept: PathBuf,
+    pub merged: Vec<PathBuf>,
+}
+
+/// How large the index has grown
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    /// The number of indexed paths
+    pub paths: usize,
+    /// The size of the search index, in bytes
+    pub fst_bytes: usize,
+    /// The size of the database on disk, in bytes
+    pub disk_bytes: u64,
+}
+
+/// How much space compacting the database reclaimed, see Index::compact_path
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CompactReport {
+    /// The size of the database on disk before it was compacted, in bytes
+    pub before_bytes: u64,
+    /// The size of the database on disk after it was compacted, in bytes
+    pub after_bytes: u64,
+}
+
+impl CompactReport {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.before_bytes.saturating_sub(self.after_bytes)
+    }
+}
+
+/// A jump from one directory to another through search, see Index::record_jump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+    pub timestamp: SystemTime,
+    /// The directory the search was run from
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// The value stored for every jump in the history tree, keyed by an increasing id
+/// Paths are stored as bytes, like the keys of the paths tree
+#[derive(Serialize, Deserialize, Debug)]
+struct JumpRecord {
+    timestamp: SystemTime,
+    from: Vec<u8>,
+    to: Vec<u8>,
+}
+
+/// The kinds of changes to the index that are journaled, so they can be undone
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalOperation {
+    /// New paths were added
+    Add,
+    /// Paths were removed, by hand or because they expired or were evicted
+    Delete,
+    /// Paths that no longer exist were removed by clean
+    Prune,
+    /// All paths were removed
+    Clear,
+}
+
+impl fmt::Display for JournalOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JournalOperation::Add => "add",
+            JournalOperation::Delete => "delete",
+            JournalOperation::Prune => "prune",
+            JournalOperation::Clear => "clear",
+        })
+    }
+}
+
+/// What Index::undo reverted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoReport {
+    pub operation: JournalOperation,
+    /// When the change that was undone was made
+    pub timestamp: SystemTime,
+    /// The paths that got back the record they had before the change
+    pub restored: Vec<PathBuf>,
+    /// The paths the change added, which were removed again
+    pub removed: Vec<PathBuf>,
+}
+
+// The key of a path in the paths tree with its value before a change, None if it was not stored
+type JournalChange = (Vec<u8>, Option<Vec<u8>>);
+
+/// The value stored for every change in the journal tree, keyed by an increasing id
+#[derive(Serialize, Deserialize, Debug)]
+struct JournalRecord {
+    timestamp: SystemTime,
+    operation: JournalOperation,
+    changes: Vec<JournalChange>,
+}
+
+impl From<JumpRecord> for Jump {
+    fn from(record: JumpRecord) -> Self {
+        Jump {
+            timestamp: record.timestamp,
+            from: path_from_bytes(&record.from),
+            to: path_from_bytes(&record.to),
+        }
+    }
+}
+
+/// The value stored for every path in the paths tree
+/// Fields are only ever appended to this struct: a record written by an older version is a
+/// prefix of the current encoding and the fields it lacks are filled in when it is decoded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct PathRecord {
+    timestamp: SystemTime,
+    count: u64,
+    pinned: bool,
+    weight: i64,
+    hits: u64,
+}
+
+impl PathRecord {
+    fn from_bytes(bytes: &[u8]) -> Result<PathRecord> {
+        let mut reader = bytes;
+        let timestamp = bincode::deserialize_from(&mut reader)?;
+        // Versions before 0.4.0 only stored the timestamp, count that as a single visit
+        let count = decode_appended_field(&mut reader)?.unwrap_or(1);
+        let pinned = decode_appended_field(&mut reader)?.unwrap_or(false);
+        let weight = decode_appended_field(&mut reader)?.unwrap_or(0);
+        let hits = decode_appended_field(&mut reader)?.unwrap_or(0);
+        Ok(PathRecord {
+            timestamp,
+            count,
+            pinned,
+            weight,
+            hits,
+        })
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    /// Takes the place of another record of the same path, only its most recent visit is kept
+    fn replace(&mut self, other: &PathRecord) {
+        self.timestamp = self.timestamp.max(other.timestamp);
+    }
+
+    /// Combines the visits of another record of the same directory into this one: the visit
+    /// counts are added up, the most recent visit is kept and it stays pinned if either was
+    fn merge(&mut self, other: &PathRecord) {
+        self.timestamp = self.timestamp.max(other.timestamp);
+        self.count = self.count.saturating_add(other.count);
+        self.hits = self.hits.saturating_add(other.hits);
+        self.pinned |= other.pinned;
+    }
+
+    /// Weighs the visit count by how long ago the path was last visited: the weight halves
+    /// every half_life. This is computed at ranking time, so nothing is written back.
+    /// A jump through search counts HIT_WEIGHT times as much as any other visit
+    fn frecency(&self, now: SystemTime, half_life: Duration) -> f64 {
+        let visits = self
+            .count
+            .saturating_add(self.hits.saturating_mul(HIT_WEIGHT)) as f64;
+        if half_life.is_zero() {
+            return visits;
+        }
+        let age = now.duration_since(self.timestamp).unwrap_or_default();
+        visits * 0.5f64.powf(age.as_secs_f64() / half_life.as_secs_f64())
+    }
+}
+
+impl Default for IndexBuilder {
+    fn default() -> Self {
+        IndexBuilder {
+            half_life: DEFAULT_HALF_LIFE,
+            debounce: Duration::ZERO,
+            ignore_patterns: Vec::new(),
+            max_age: Duration::ZERO,
+            max_entries: 0,
+            missing_penalty: 0,
+            match_mode: MatchMode::default(),
+            matcher: Matcher::default(),
+            case_sensitivity: CaseSensitivity::default(),
+            frecency_weight: 0,
+            name_bonus: DEFAULT_NAME_BONUS,
+            fuzzy_scoring: true,
+            basename: false,
+        }
+    }
+}
+
+impl IndexBuilder {
+    pub fn new() -> IndexBuilder {
+        IndexBuilder::default()
+    }
+
+    /// Sets the time after which the weight of a visited path has halved
+    pub fn half_life(mut self, half_life: Duration) -> IndexBuilder {
+        self.half_life = half_life;
+        self
+    }
+
+    /// Sets the time during which adding a path again is ignored, so a shell hook that runs
+    /// on every prompt doesn't rewrite the same record over and over. Disabled by default.
+    pub fn debounce(mut self, debounce: Duration) -> IndexBuilder {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Sets the time after which paths that have not been visited are removed automatically
+    /// This is checked when paths are added, at most once a day. Disabled by default.
+    pub fn max_age(mut self, max_age: Duration) -> IndexBuilder {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sets the maximum number of paths in the index, adding a new path beyond this evicts
+    /// the lowest ranked paths. 0, the default, means there is no limit.
+    pub fn max_entries(mut self, max_entries: usize) -> IndexBuilder {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Sets how much the score of a path that is no longer a directory is lowered, so it only
+    /// wins when nothing else matches. Such a path also loses its pin. 0, the default, disables
+    /// this and skips checking whether search results exist.
+    pub fn missing_penalty(mut self, missing_penalty: i64) -> IndexBuilder {
+        self.missing_penalty = missing_penalty;
+        self
+    }
+
+    /// Sets how search terms are matched against the paths in the index
+    pub fn match_mode(mut self, match_mode: MatchMode) -> IndexBuilder {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Sets the algorithm that scores how well a path matches the search terms
+    pub fn matcher(mut self, matcher: Matcher) -> IndexBuilder {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Sets whether the case of the search terms has to match that of the path
+    /// Searches ignore case by default.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> IndexBuilder {
+        self.case_sensitivity = if case_sensitive {
+            CaseSensitivity::Sensitive
+        } else {
+            CaseSensitivity::Insensitive
+        };
+        self
+    }
+
+    /// Sets when the case of the search terms has to match that of the path
+    pub fn case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> IndexBuilder {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+
+    /// Sets how many points a path gets on top of its fuzzy score for every decayed visit
+    /// 0, the default, only uses the visits to break ties between equal scores.
+    pub fn frecency_weight(mut self, frecency_weight: i64) -> IndexBuilder {
+        self.frecency_weight = frecency_weight;
+        self
+    }
+
+    /// Sets the points a path gets when its last component matches the last search term, so
+    /// `/src/prometheus` wins from `/usr/prometheus-data/src` for `pro`. Defaults to 100.
+    pub fn name_bonus(mut self, name_bonus: i64) -> IndexBuilder {
+        self.name_bonus = name_bonus;
+        self
+    }
+
+    /// Sets whether matches are scored on how well they match the search terms
+    /// Without it every match scores the same and the most recently visited one wins, after
+    /// pinned paths, the manual weight and the bonuses. Enabled by default.
+    pub fn fuzzy_scoring(mut self, fuzzy_scoring: bool) -> IndexBuilder {
+        self.fuzzy_scoring = fuzzy_scoring;
+        self
+    }
+
+    /// Sets whether the search terms only match the last component of a path, and are scored
+    /// against it. Off by default, so the terms can match anywhere in the path.
+    pub fn basename(mut self, basename: bool) -> IndexBuilder {
+        self.basename = basename;
+        self
+    }
+
+    /// Sets the glob patterns of paths that are never added to the index
+    /// Like in gitignore, a path is also ignored when one of its parents matches a pattern
+    /// `*` and `?` don't match the path separator, `**` matches any number of components
+    pub fn ignore_patterns(mut self, patterns: &[String]) -> IndexBuilder {
+        self.ignore_patterns = patterns.to_vec();
+        self
+    }
+
+    /// Opens the index stored in the database at path, creating it if it doesn't exist
+    /// Only one process can have the database open at a time, this waits a moment for the others
+    /// and fails with IndexError::DatabaseLocked if they don't close it in time
+    pub fn open(self, path: &Path) -> Result<Index> {
+        self.open_config(Config::new().path(path))
+    }
+
     /// Opens and configures a new sled database with config
-    pub fn open(config: Config) -> Result<Index> {
-        log::debug!("Opening db for config: {:?}", config);
+    pub(crate) fn open_config(self, config: Config) -> Result<Index> {
+        // Invalid patterns are reported before waiting for the database
+        let ignore = build_ignore_set(&self.ignore_patterns)?;
+        tracing::debug!("Opening db for config: {:?}", config);
+        let lock = match config.temporary {
+            true => None,
+            false => Some(lock_database(&config.path)?),
+        };
+        if !config.temporary {
+            recover_compaction(&config.path)?;
+        }
         let db = match config.open() {
             // versions 0.1.0 and 0.2.0 used an older version of sled which has
             // a different serialization format
-            // Current versions can't open this. We are chosing to handle this by
-            // just removing the old database and trying to open again.
-            // This is of course distructive, but given that we're still very early
-            // on in this project, we should be able to get away with it.
-            // The other alternatives are:
-            //    * Ask the user to reset the database himself. This is useless
-            //      without also implementing import/export functionality to a stable
-            //      serialization. The kind of data and the limited amount of user
-            //      don't justify this effort at this point
-            //    * Compile in the old version of sled as well and use its built-in
-            //      import/export functionality. We'd have to drag this dependency
-            //      with us for a long time. Like for the previous option, the limited
-            //      userbase probably doesn't justify creating this kind of technical
-            //      debt right now
+            // Current versions can't open this and, unlike our own records, it can't be
+            // migrated without compiling in the old version of sled as well. We'd have to
+            // drag this dependency with us for a long time, which the limited userbase
+            // doesn't justify.
+            // Instead the old database is moved aside, so nothing is lost, and a new one is
+            // created in its place.
             // In any case: let's hope the sled serialization format remains stable
             // for the foreseeable future
             Err(sled::Error::Unsupported(_)) => {
-                eprintln! {"Found incompatible database. Recreating."}
-                fs::remove_dir_all(&config.path)?;
+                let backup_path = incompatible_backup_path(&config.path);
+                fs::rename(&config.path, &backup_path)?;
+                tracing::warn!(
+                    "Found a database in a format this version of scotty can't read. \
+                    It was moved to {} and a new, empty database was created.",
+                    backup_path.display()
+                );
                 config.open()?
             }
             Err(e) => return Err(e.into()),
@@ -78,569 +660,5310 @@ impl Index {
         };
         let main_tree = db.open_tree(MAIN_TREE)?;
         let paths_tree = db.open_tree(PATHS_TREE)?;
-        Ok(Index {
+        let history_tree = db.open_tree(HISTORY_TREE)?;
+        let journal_tree = db.open_tree(JOURNAL_TREE)?;
+        let index = Index {
+            db,
             main: main_tree,
             paths: paths_tree,
+            history: history_tree,
+            journal: journal_tree,
+            half_life: self.half_life,
+            debounce: self.debounce,
+            ignore,
+            max_age: self.max_age,
+            max_entries: self.max_entries,
+            missing_penalty: self.missing_penalty,
+            match_mode: self.match_mode,
+            matcher: self.matcher,
+            case_sensitivity: self.case_sensitivity,
+            frecency_weight: self.frecency_weight,
+            name_bonus: self.name_bonus,
+            fuzzy_scoring: self.fuzzy_scoring,
+            basename: self.basename,
+            _lock: lock,
+        };
+        index.migrate()?;
+        Ok(index)
+    }
+}
+
+impl Index {
+    /// Opens the index stored in the database at path with the default settings
+    /// Use IndexBuilder to configure the index before opening it
+    pub fn open_path(path: &Path) -> Result<Index> {
+        IndexBuilder::new().open(path)
+    }
+
+    /// Rewrites the database at path into a fresh database, to reclaim the space sled's log
+    /// has grown to. The old database is only replaced once the copy is complete, and an
+    /// interrupted swap is finished the next time the database is opened.
+    pub fn compact_path(path: &Path) -> Result<CompactReport> {
+        let _lock = lock_database(path)?;
+        recover_compaction(path)?;
+        let old_db = Config::new().path(path).open()?;
+        old_db.flush()?;
+        let before_bytes = old_db.size_on_disk()?;
+
+        // A copy left behind by an interrupted compaction is incomplete
+        let new_path = sibling_path(path, COMPACTING_SUFFIX);
+        if new_path.exists() {
+            fs::remove_dir_all(&new_path)?;
+        }
+        let new_db = Config::new().path(&new_path).open()?;
+        for name in old_db.tree_names() {
+            let old_tree = old_db.open_tree(&name)?;
+            let new_tree = new_db.open_tree(&name)?;
+            for item in old_tree.iter() {
+                let (key, value) = item?;
+                new_tree.insert(key, value)?;
+            }
+        }
+        new_db.flush()?;
+        let after_bytes = new_db.size_on_disk()?;
+        drop(new_db);
+        drop(old_db);
+
+        // The database is missing between these renames, recover_compaction moves the old one
+        // back if we are interrupted
+        let old_path = sibling_path(path, COMPACTED_SUFFIX);
+        fs::rename(path, &old_path)?;
+        fs::rename(&new_path, path)?;
+        fs::remove_dir_all(&old_path)?;
+        tracing::debug!(
+            "Compacted {} from {} to {} bytes",
+            path.display(),
+            before_bytes,
+            after_bytes
+        );
+        Ok(CompactReport {
+            before_bytes,
+            after_bytes,
+        })
+    }
+
+    // Brings the data in the database up to SCHEMA_VERSION by running all migrations that
+    // have not run yet. A database that is newer than this version of scotty is not touched.
+    fn migrate(&self) -> Result<()> {
+        let version = match self.read_schema_version()? {
+            Some(SCHEMA_VERSION) => return Ok(()),
+            Some(version) if version > SCHEMA_VERSION => {
+                return Err(IndexError::UnsupportedSchema(version).into())
+            }
+            Some(version) => version,
+            // A new database doesn't need any migrations
+            None if self.paths.is_empty() && self.main.is_empty() => SCHEMA_VERSION,
+            // Databases written before the schema was versioned
+            None => 0,
+        };
+
+        for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            tracing::debug!("Migrating database from schema version {}", from_version);
+            migration(self)?;
+            self.write_schema_version(from_version as u32 + 1)?;
+        }
+        self.write_schema_version(SCHEMA_VERSION)
+    }
+
+    fn read_schema_version(&self) -> Result<Option<u32>> {
+        Ok(match self.main.get(SCHEMA_VERSION_KEY)? {
+            Some(bytes) => Some(bincode::deserialize(&bytes)?),
+            None => None,
         })
     }
 
-    /// Produces a Vec that contains all current entries in the index
+    fn write_schema_version(&self, version: u32) -> Result<()> {
+        self.main
+            .insert(SCHEMA_VERSION_KEY, bincode::serialize(&version)?)?;
+        Ok(())
+    }
+
+    /// Checks if a path has been added to the index
+    pub fn contains(&self, path_buf: &Path) -> Result<bool> {
+        let path_buf = normalize_path(path_buf);
+        Ok(self.paths.contains_key(path_to_bytes(&path_buf))?)
+    }
+
+    /// Produces a Vec that contains all current entries in the index, ordered by path
     pub fn list(&self) -> Result<Vec<PathIndexEntry>> {
         self.paths
             .iter()
             .map(|item| {
                 let tuple = item?;
-                let path = PathBuf::from(str::from_utf8(tuple.0.as_ref())?);
-                let timestamp = bincode::deserialize(tuple.1.as_ref())?;
-                Ok(PathIndexEntry { timestamp, path })
+                let path = path_from_bytes(tuple.0.as_ref());
+                let record = PathRecord::from_bytes(tuple.1.as_ref())?;
+                Ok(PathIndexEntry {
+                    timestamp: record.timestamp,
+                    path,
+                    count: record.count,
+                    pinned: record.pinned,
+                    weight: record.weight,
+                    hits: record.hits,
+                })
             })
             .collect()
     }
 
+    /// Returns the count most recently visited entries, most recent first
+    /// Unlike sorting the result of list, this reads the index in one pass and only keeps
+    /// count entries in memory
+    pub fn recent(&self, count: usize) -> Result<Vec<PathIndexEntry>> {
+        // count comes from the user, the heap never holds more than the stored paths
+        let mut heap = BinaryHeap::with_capacity(count.min(self.paths.len()).saturating_add(1));
+        for item in self.paths.iter() {
+            let (key, value) = item?;
+            let record = PathRecord::from_bytes(&value)?;
+            heap.push(ByRecency(PathIndexEntry {
+                timestamp: record.timestamp,
+                path: path_from_bytes(&key),
+                count: record.count,
+                pinned: record.pinned,
+                weight: record.weight,
+                hits: record.hits,
+            }));
+            // The top of the heap is the least recent entry
+            if heap.len() > count {
+                heap.pop();
+            }
+        }
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|entry| entry.0)
+            .collect())
+    }
+
     /// Adds a path to the database and update the indexes
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn add(&self, path_buf: &Path) -> Result<()> {
-        log::debug!("Adding path to index: {}", path_buf.display());
-        let path_string = path_buf.to_string_lossy();
-        if !path_buf.is_dir() {
-            return Err(IndexError::PathDoesNotExist(path_string.into_owned()).into());
+        tracing::debug!("Adding path to index: {}", path_buf.display());
+        validate_path(path_buf)?;
+        let path_buf = normalize_path(path_buf);
+        let path_buf = path_buf.as_path();
+        if self.is_ignored(path_buf) {
+            tracing::debug!("Skipping ignored path: {}", path_buf.display());
+            return Ok(());
+        }
+
+        let now = SystemTime::now();
+        if self.update_known_record(path_buf, now)? {
+            return self.expire_if_due();
+        }
+        let path_fst = Set::from_iter(vec![path_to_bytes(path_buf)])?;
+        let is_new = self.transaction(|paths, main| {
+            let is_new = self.upsert_record(paths, path_buf, now)?;
+            if is_new {
+                // New path: update the fst
+                insert_into_fsts(main, &path_fst)?;
+            }
+            Ok(is_new)
+        })?;
+        if is_new {
+            let path_bytes = path_to_bytes(path_buf).into_owned();
+            self.journal(JournalOperation::Add, vec![(path_bytes, None)])?;
+            // The path that was just visited has the lowest rank, but it has to stick
+            self.enforce_max_entries(Some(path_buf))?;
+        }
+        self.expire_if_due()
+    }
+
+    /// Sets the time of the last visit of an indexed path to now, without counting a visit
+    /// A path that is not indexed yet is added if create is set, otherwise it is an error
+    pub fn touch(&self, path_buf: &Path, create: bool) -> Result<()> {
+        tracing::debug!("Touching path: {}", path_buf.display());
+        validate_path(path_buf)?;
+        let path_buf = normalize_path(path_buf);
+        if self.contains(&path_buf)? {
+            let now = SystemTime::now();
+            self.update_record(&path_buf, |record| record.timestamp = now)?;
+            return Ok(());
+        }
+        match create {
+            true => self.add(&path_buf),
+            false => Err(IndexError::NotIndexed(path_buf.to_string_lossy().into_owned()).into()),
+        }
+    }
+
+    /// Records that the path was jumped to after a search, which weighs more in the ranking
+    /// than a plain visit. A path that is not indexed yet is added first
+    pub fn hit(&self, path_buf: &Path) -> Result<()> {
+        tracing::debug!("Recording hit of path: {}", path_buf.display());
+        validate_path(path_buf)?;
+        let path_buf = normalize_path(path_buf);
+        if self.is_ignored(&path_buf) {
+            tracing::debug!("Skipping ignored path: {}", path_buf.display());
+            return Ok(());
+        }
+        if !self.contains(&path_buf)? {
+            self.add(&path_buf)?;
+        }
+        let now = SystemTime::now();
+        self.update_record(&path_buf, |record| {
+            record.hits = record.hits.saturating_add(1);
+            record.timestamp = now;
+        })?;
+        Ok(())
+    }
+
+    /// Returns what add would do with the path, without changing the index
+    pub fn check_add(&self, path_buf: &Path) -> Result<AddAction> {
+        if let Err(e) = validate_path(path_buf) {
+            return Ok(AddAction::Reject(e));
+        }
+        let path_buf = normalize_path(path_buf);
+        if self.is_ignored(&path_buf) {
+            return Ok(AddAction::Ignore);
+        }
+        Ok(match self.contains(&path_buf)? {
+            true => AddAction::Refresh,
+            false => AddAction::Insert,
+        })
+    }
+
+    /// Adds several paths to the database, updating the indexes only once for all of them
+    /// Paths that are not valid are skipped and returned together with the reason why
+    pub fn add_many(&self, paths: &[PathBuf]) -> Result<AddSummary> {
+        tracing::debug!("Adding {} paths to index", paths.len());
+        let mut summary = AddSummary::default();
+        let mut valid_paths = Vec::new();
+        for path_buf in paths {
+            if let Err(e) = validate_path(path_buf) {
+                summary.rejected.push((path_buf.clone(), e));
+                continue;
+            }
+            let path_buf = normalize_path(path_buf);
+            if self.is_ignored(&path_buf) {
+                tracing::debug!("Skipping ignored path: {}", path_buf.display());
+                summary.ignored += 1;
+                continue;
+            }
+            valid_paths.push(path_buf);
+        }
+
+        let now = SystemTime::now();
+        let new_paths = self.transaction(|paths, main| {
+            let mut new_paths = Vec::new();
+            for path_buf in valid_paths.iter() {
+                if self.upsert_record(paths, path_buf, now)? {
+                    new_paths.push(path_to_bytes(path_buf).into_owned());
+                }
+            }
+            index_new_paths(main, new_paths.clone())?;
+            Ok(new_paths)
+        })?;
+        summary.added = new_paths.len();
+        if summary.added > 0 {
+            let changes = new_paths.into_iter().map(|key| (key, None)).collect();
+            self.journal(JournalOperation::Add, changes)?;
+            self.enforce_max_entries(None)?;
+        }
+        self.expire_if_due()?;
+        Ok(summary)
+    }
+
+    /// Stores the given entries as they are, replacing the records of paths that are already
+    /// indexed. This is the inverse of list and updates the indexes only once.
+    /// Paths matching the ignore patterns are skipped, new paths can be undone like add.
+    /// Entries with a path that is not valid are skipped and returned together with the reason
+    pub fn import(&self, entries: &[PathIndexEntry]) -> Result<Vec<(PathBuf, IndexError)>> {
+        Ok(self
+            .import_with(entries, ConflictResolution::KeepRemote)?
+            .rejected)
+    }
+
+    /// Stores the given entries like import, but only replaces the record of a path that is
+    /// already indexed when 'resolution' picks the entry over it
+    pub fn import_with(
+        &self,
+        entries: &[PathIndexEntry],
+        resolution: ConflictResolution,
+    ) -> Result<ImportSummary> {
+        tracing::debug!(
+            "Importing {} entries into index, resolving conflicts with {:?}",
+            entries.len(),
+            resolution
+        );
+        let (mut records, rejected) = encode_entries(entries)?;
+        let count = records.len();
+        records.retain(|(path_bytes, _)| !self.is_ignored(&path_from_bytes(path_bytes)));
+        let ignored = count - records.len();
+
+        let (new_paths, replaced, kept) = self.transaction(|paths, main| {
+            let mut new_paths = Vec::new();
+            let (mut replaced, mut kept) = (0, 0);
+            for (path_bytes, record_bytes) in records.iter() {
+                let replace = match paths.get(path_bytes.as_slice())? {
+                    None => {
+                        new_paths.push(path_bytes.to_vec());
+                        true
+                    }
+                    Some(existing) => {
+                        let replace = match resolution {
+                            ConflictResolution::KeepLocal => false,
+                            ConflictResolution::KeepRemote => true,
+                            ConflictResolution::KeepNewer => {
+                                let existing = PathRecord::from_bytes(&existing).map_err(abort)?;
+                                let record = PathRecord::from_bytes(record_bytes).map_err(abort)?;
+                                record.timestamp > existing.timestamp
+                            }
+                        };
+                        match replace {
+                            true => replaced += 1,
+                            false => kept += 1,
+                        }
+                        replace
+                    }
+                };
+                if replace {
+                    paths.insert(path_bytes.as_slice(), record_bytes.as_slice())?;
+                }
+            }
+            index_new_paths(main, new_paths.clone())?;
+            Ok((new_paths, replaced, kept))
+        })?;
+        let added = new_paths.len();
+        if added > 0 {
+            let changes = new_paths.into_iter().map(|key| (key, None)).collect();
+            self.journal(JournalOperation::Add, changes)?;
+            self.enforce_max_entries(None)?;
         }
-        if !path_buf.is_absolute() {
-            return Err(IndexError::RelativePath(path_string.into_owned()).into());
+        Ok(ImportSummary {
+            added,
+            replaced,
+            kept,
+            ignored,
+            rejected,
+        })
+    }
+
+    /// Adds the visits of the given entries to the index, updating the indexes only once
+    /// Entries of paths that are already indexed are merged with them like dedupe does, so
+    /// nothing is lost. Paths matching the ignore patterns are skipped.
+    /// Entries with a path that is not valid are skipped and returned together with the reason
+    pub fn merge(&self, entries: &[PathIndexEntry]) -> Result<Vec<(PathBuf, IndexError)>> {
+        tracing::debug!("Merging {} entries into index", entries.len());
+        let (mut records, rejected) = encode_entries(entries)?;
+        records.retain(|(path_bytes, _)| !self.is_ignored(&path_from_bytes(path_bytes)));
+
+        let new_paths = self.transaction(|paths, main| {
+            let mut new_paths = Vec::new();
+            for (path_bytes, record_bytes) in records.iter() {
+                let record = PathRecord::from_bytes(record_bytes).map_err(abort)?;
+                // The existing record keeps its manual weight
+                let merged = match paths.get(path_bytes.as_slice())? {
+                    Some(bytes) => {
+                        let mut existing = PathRecord::from_bytes(&bytes).map_err(abort)?;
+                        existing.merge(&record);
+                        existing
+                    }
+                    None => {
+                        new_paths.push(path_bytes.clone());
+                        record
+                    }
+                };
+                paths.insert(path_bytes.as_slice(), merged.to_bytes().map_err(abort)?)?;
+            }
+            index_new_paths(main, new_paths.clone())?;
+            Ok(new_paths)
+        })?;
+        if !new_paths.is_empty() {
+            let changes = new_paths.into_iter().map(|key| (key, None)).collect();
+            self.journal(JournalOperation::Add, changes)?;
+            self.enforce_max_entries(None)?;
         }
+        Ok(rejected)
+    }
+
+    /// Replaces every entry in the index by the given entries, in a single transaction
+    /// Entries with a path that is not valid are skipped and returned together with the reason
+    pub fn replace(&self, entries: &[PathIndexEntry]) -> Result<Vec<(PathBuf, IndexError)>> {
+        tracing::debug!("Replacing index with {} entries", entries.len());
+        let (mut records, rejected) = encode_entries(entries)?;
+        // The fst requires its input to be sorted and without duplicates, the last entry of a
+        // path wins like it does in import
+        records.reverse();
+        records.sort_by(|a, b| a.0.cmp(&b.0));
+        records.dedup_by(|a, b| a.0 == b.0);
+        let paths_fst = Set::from_iter(records.iter().map(|(path_bytes, _)| path_bytes))?;
+        // Other processes wait for the lock on the database, so no path can be added between
+        // reading the stored paths and the transaction
+        let stored = self.read_stored_paths()?;
 
+        self.transaction(|paths, main| {
+            for key in &stored {
+                paths.remove(key.as_slice())?;
+            }
+            for (path_bytes, record_bytes) in records.iter() {
+                paths.insert(path_bytes.as_slice(), record_bytes.as_slice())?;
+            }
+            main.insert(INDEX_KEY, paths_fst.as_fst().as_bytes())?;
+            main.remove(DELTA_KEY)?;
+            Ok(())
+        })?;
+        Ok(rejected)
+    }
+
+    // Records a visit of the path in the paths tree, returns true if the path was not known yet
+    // Visits that follow the previous one within the debounce interval are not recorded
+    fn upsert_record(
+        &self,
+        paths: &TransactionalTree,
+        path_buf: &Path,
+        now: SystemTime,
+    ) -> TransactionResult<bool> {
         // Check if the path is already known and update its last modified timestamp
-        let path_bytes = path_string.as_bytes();
+        let path_bytes = path_to_bytes(path_buf);
+        let previous = match paths.get(path_bytes.as_ref())? {
+            Some(bytes) => Some(PathRecord::from_bytes(&bytes).map_err(abort)?),
+            None => None,
+        };
+        let record = match self.record_visit(path_buf, previous, now) {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        let previous = paths.insert(path_bytes.as_ref(), record.to_bytes().map_err(abort)?)?;
+        Ok(previous.is_none())
+    }
 
-        let time_bytes = bincode::serialize(&SystemTime::now())?;
-        match self.paths.insert(path_bytes, time_bytes)? {
-            // New path: update the fst
-            None => self.update_paths_index(path_bytes, merge_fst_sets),
-            _ => Ok(()),
+    // Records a visit of a path that is already known, returns false if it is not known yet
+    // This only changes the record of the path, so it doesn't need a transaction on both trees
+    fn update_known_record(&self, path_buf: &Path, now: SystemTime) -> Result<bool> {
+        let path_bytes = path_to_bytes(path_buf);
+        loop {
+            let current = match self.paths.get(path_bytes.as_ref())? {
+                Some(current) => current,
+                None => return Ok(false),
+            };
+            let previous = PathRecord::from_bytes(&current)?;
+            let record = match self.record_visit(path_buf, Some(previous), now) {
+                Some(record) => record,
+                None => return Ok(true),
+            };
+            // Try again if a concurrent process changed the record in the meantime
+            if self
+                .paths
+                .compare_and_swap(path_bytes.as_ref(), Some(current), Some(record.to_bytes()?))?
+                .is_ok()
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    // Returns the record of a path after a visit at now, or None if the visit should not be
+    // recorded because it follows the previous one within the debounce interval
+    fn record_visit(
+        &self,
+        path_buf: &Path,
+        previous: Option<PathRecord>,
+        now: SystemTime,
+    ) -> Option<PathRecord> {
+        match previous {
+            Some(record) if is_recent(record.timestamp, now, self.debounce) => {
+                tracing::debug!("Skipping recently added path: {}", path_buf.display());
+                None
+            }
+            Some(record) => Some(PathRecord {
+                timestamp: now,
+                count: record.count.saturating_add(1),
+                ..record
+            }),
+            None => Some(PathRecord {
+                timestamp: now,
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            }),
         }
     }
 
-    /// Returns a vec with all strings from the index that match the 'target' string
+    /// Returns a vec with all keys from the index that match all of the 'terms', in any order
+    /// Every term matches when its characters appear in the key in order
     /// This is the internal implemenation backing find_one and find_all
-    fn search(&self, target: &str, exclude: Option<&Path>) -> Result<Vec<String>> {
-        log::debug!("Searching target in index: {}", target);
+    #[tracing::instrument(level = "debug", skip(self))]
+    fn search(&self, terms: &[&str], exclude: &[&Path]) -> Result<Vec<Vec<u8>>> {
+        tracing::debug!("Searching terms in index: {:?}", terms);
         // Special case an empty target
-        if target.is_empty() {
+        let terms = split_words(terms);
+        if terms.is_empty() {
             return Ok(Vec::new());
         }
 
         // Get the index from the database
-        let fst_index = match self.main.get(INDEX_KEY)? {
-            Some(bytes) => Set::new(bytes.deref().into())?,
-            None => Set::default(),
-        };
+        let fst_indexes = [self.read_fst(INDEX_KEY)?, self.read_fst(DELTA_KEY)?];
 
         // Create the query automaton and run it
-        let subseq = automaton::Subsequence::new(target);
-        let regex = Builder::new()
-            .case_insensitive(true)
-            .build(&format!(".*{}.*", target))?;
-        let query = subseq.union(regex);
-        match exclude {
-            Some(p) => {
-                let path_str = p.to_string_lossy();
-                let filter = automaton::Str::new(path_str.as_ref()).complement();
-                Ok(fst_index
-                    .search(query.intersection(filter))
-                    .into_stream()
-                    .into_strs()?)
-            }
-            // A more elegant way would seem to use automaton::AlwaysMatch, but I just can't
-            // find a way to make that typecheck (since operations on an Automaton don't return
-            // an Automaton but types of the form Union<S,T>)
-            // This is also why we only support one exclude string: a vec of exclude strings would
-            // result in a type sig of Union<Union<...,_>> that can't be known at compile time
-            None => Ok(fst_index.search(query).into_stream().into_strs()?),
+        let case_insensitive = !self.case_sensitivity.is_sensitive(&terms);
+        // Between the terms anything can follow, but not a new path component when only the
+        // last one is matched
+        let gap = if self.basename { "[^/]*" } else { ".*" };
+        if self.match_mode == MatchMode::Prefix {
+            let separator = if self.basename { gap } else { ".*/" };
+            let regex = Builder::new()
+                .case_insensitive(case_insensitive)
+                .build(&format!(".*/{}[^/]*", terms.join(separator)))?;
+            return Ok(search_fsts_excluding(&fst_indexes, regex, exclude));
         }
+        let start = if self.basename { ".*/[^/]*" } else { ".*" };
+        let regex = Builder::new()
+            .case_insensitive(case_insensitive)
+            .build(&format!("{}{}{}", start, terms.join(gap), gap))?;
+        // Every word also matches when its characters appear in the path in order, the words
+        // themselves can appear in any order
+        let subsequences = Subsequences {
+            words: terms
+                .iter()
+                .map(|word| {
+                    Builder::new()
+                        .case_insensitive(case_insensitive)
+                        .allow_invalid_utf8(true)
+                        .build(&subsequence_pattern(word, self.basename))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(search_fsts_excluding(
+            &fst_indexes,
+            subsequences.union(regex),
+            exclude,
+        ))
+    }
+
+    /// Returns a vec with all paths from the index that match the 'terms'
+    pub fn find_all(&self, terms: &[&str], exclude: &[&Path]) -> Result<Vec<PathBuf>> {
+        self.search(terms, exclude)
+            .map(|result| result.iter().map(|key| path_from_bytes(key)).collect())
+    }
+
+    /// Returns at most 'n' paths from the index that match the 'terms', the best match first
+    pub fn find_n(
+        &self,
+        terms: &[&str],
+        n: usize,
+        exclude: &[&Path],
+        cwd: Option<&Path>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut scores = self.rank(terms, exclude, cwd)?;
+        scores.truncate(n);
+        Ok(scores.into_iter().map(|score| score.path).collect())
+    }
+
+    /// Returns the best directory path from the index for the given 'terms',
+    /// uses last-visited timestamp as a tie-breaker for equally scored paths.
+    /// If cwd is set, paths below it get a bonus on top of their fuzzy score.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn find_one(
+        &self,
+        terms: &[&str],
+        exclude: &[&Path],
+        cwd: Option<&Path>,
+    ) -> Result<Option<PathBuf>> {
+        Ok(self
+            .find_one_detailed(terms, exclude, cwd)?
+            .map(|score| score.path))
     }
 
-    /// Returns a vec with all paths from the index that match the 'target' string
-    pub fn find_all(&self, target: &str, exclude: Option<&Path>) -> Result<Vec<PathBuf>> {
-        self.search(target, exclude)
-            .map(|result| result.iter().map(PathBuf::from).collect())
+    /// Returns the best match like find_one, together with how it was scored
+    pub fn find_one_detailed(
+        &self,
+        terms: &[&str],
+        exclude: &[&Path],
+        cwd: Option<&Path>,
+    ) -> Result<Option<Score>> {
+        let best_score = self.get_best_score(self.score_matches(terms, exclude)?, cwd)?;
+        tracing::debug!("Best result: {:?}", best_score);
+
+        Ok(best_score)
     }
 
-    /// Returns the best directory path from the index for the given 'target' string,
-    // uses last-visited timestamp as a tie-breaker for equally scored paths.
-    pub fn find_one(&self, target: &str, exclude: Option<&Path>) -> Result<Option<PathBuf>> {
-        // Special case the empty target
-        if target.is_empty() {
-            return Ok(None);
+    /// Returns the best directory path like find_one, leaving out the current directory
+    /// Jumping to the directory the shell is already in is never useful. If the current
+    /// directory can't be determined nothing is left out.
+    pub fn find_one_except_current(
+        &self,
+        terms: &[&str],
+        cwd: Option<&Path>,
+    ) -> Result<Option<PathBuf>> {
+        match std::env::current_dir() {
+            Ok(current_dir) => self.find_one(terms, &[&current_dir], cwd),
+            Err(_) => self.find_one(terms, &[], cwd),
         }
+    }
+
+    /// Returns every match for the given 'terms' with its score, the best match first
+    /// This is the ranking find_one picks its result from
+    pub fn rank(
+        &self,
+        terms: &[&str],
+        exclude: &[&Path],
+        cwd: Option<&Path>,
+    ) -> Result<Vec<Score>> {
+        self.rank_scores(self.score_matches(terms, exclude)?, cwd)
+    }
 
+    // Returns the fuzzy scores of the matches for the given 'terms', without reading their
+    // records
+    fn score_matches(&self, terms: &[&str], exclude: &[&Path]) -> Result<Vec<Score>> {
         // Search the index for strings that match
-        let results = self.search(target, exclude)?;
-        log::debug!("FST result set: {:?}", results);
+        let results = self.search(terms, exclude)?;
+        tracing::debug!(
+            "FST result set: {:?}",
+            results
+                .iter()
+                .map(|key| String::from_utf8_lossy(key))
+                .collect::<Vec<_>>()
+        );
 
         // Score the results
-        let score_vec = score_results(&results, target);
-        log::debug!("Scored FST result set: {:?}", score_vec);
+        let terms = split_words(terms);
+        let case_sensitive = self.case_sensitivity.is_sensitive(&terms);
+        let mut score_vec = if self.fuzzy_scoring {
+            score_results(
+                &results,
+                &terms,
+                self.fuzzy_matcher(case_sensitive).as_ref(),
+                self.name_bonus,
+                self.basename,
+            )
+        } else {
+            results
+                .iter()
+                .map(|key| Score::new(path_from_bytes(key), 0))
+                .collect()
+        };
+        if let Some(term) = terms.last() {
+            for score in score_vec.iter_mut() {
+                score.exact = is_exact_match(&score.path, term, case_sensitive);
+            }
+        }
+        tracing::debug!("Scored FST result set: {:?}", score_vec);
 
-        let best_score = self.get_best_score(score_vec)?;
-        log::debug!("Best result: {:?}", best_score);
+        Ok(score_vec)
+    }
 
-        Ok(best_score.map(|p| p.path))
+    // Creates the configured fuzzy matcher
+    fn fuzzy_matcher(&self, case_sensitive: bool) -> Box<dyn FuzzyMatcher> {
+        match (self.matcher, case_sensitive) {
+            (Matcher::Clangd, false) => Box::new(ClangdMatcher::default().ignore_case()),
+            (Matcher::Clangd, true) => Box::new(ClangdMatcher::default().respect_case()),
+            (Matcher::Skim, false) => Box::new(SkimMatcherV2::default().ignore_case()),
+            (Matcher::Skim, true) => Box::new(SkimMatcherV2::default().respect_case()),
+        }
     }
 
     /// Removes a path from the index, will succeed even if the path is not indexed
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn delete(&self, path_buf: &Path) -> Result<()> {
-        log::debug!("Deleting path from index: {}", path_buf.display());
-        let path_string = path_buf.to_string_lossy();
-        let path_bytes = path_string.as_bytes();
-        match self.paths.remove(path_bytes)? {
+        tracing::debug!("Deleting path from index: {}", path_buf.display());
+        let path_buf = normalize_path(path_buf);
+        let path_bytes = path_to_bytes(&path_buf);
+        let path_fst = Set::from_iter(vec![path_bytes.as_ref()])?;
+        let previous = self.transaction(|paths, main| {
+            let previous = paths.remove(path_bytes.as_ref())?;
+            if previous.is_some() {
+                remove_from_fsts(main, &path_fst)?;
+            }
+            Ok(previous)
+        })?;
+        match previous {
+            Some(previous) => {
+                let changes = vec![(path_bytes.into_owned(), Some(previous.to_vec()))];
+                self.journal(JournalOperation::Delete, changes)
+            }
             None => Ok(()),
-            Some(_) => self.update_paths_index(path_bytes, remove_fst_set),
         }
     }
 
-    fn get_timestamp(&self, path: &Path) -> Result<Option<SystemTime>> {
-        let time_bytes = self.paths.get(path.to_string_lossy().as_bytes())?;
-        Ok(time_bytes
-            .map(|x| bincode::deserialize::<SystemTime>(x.as_ref()))
-            .transpose()?)
+    /// Removes several paths from the index, updating the indexes only once for all of them
+    /// Returns the number of paths that were actually removed
+    pub fn delete_many(&self, paths: &[&Path]) -> Result<usize> {
+        self.remove_paths(paths, JournalOperation::Delete)
     }
 
-    // Consumes the vector and returns the item with the best score
-    // It will use the timestamp stored in the database as a tie-breaker
-    // Care is taken to minimize the amount of database lookups
-    fn get_best_score(&self, mut results: Vec<Score>) -> Result<Option<Score>> {
-        if results.is_empty() {
-            return Ok(None);
-        }
-
-        // Get max score
-        results.sort();
-        let max_score = results.last().unwrap().score;
+    // Removes several paths from the index like delete_many, journaling them as operation
+    fn remove_paths(&self, paths: &[&Path], operation: JournalOperation) -> Result<usize> {
+        tracing::debug!("Deleting {} paths from index", paths.len());
+        // The fst requires its input to be sorted and without duplicates
+        let mut path_keys = paths
+            .iter()
+            .map(|path_buf| path_to_bytes(&normalize_path(path_buf)).into_owned())
+            .collect::<Vec<_>>();
+        path_keys.sort();
+        path_keys.dedup();
 
-        results.retain(|x| x.score == max_score);
-        // Get timestamp for ties
-        if results.len() > 1 {
-            for score in results.iter_mut() {
-                score.timestamp = self.get_timestamp(&score.path)?;
+        let changes = self.transaction(|paths, main| {
+            let mut changes = Vec::new();
+            for path_bytes in path_keys.iter() {
+                if let Some(previous) = paths.remove(path_bytes.as_slice())? {
+                    changes.push((path_bytes.to_vec(), Some(previous.to_vec())));
+                }
             }
-            results.sort();
-        }
-
-        // Return best result
-        Ok(results.pop())
+            if !changes.is_empty() {
+                let removed = changes.iter().map(|(key, _)| key);
+                remove_from_fsts(main, &Set::from_iter(removed).map_err(abort)?)?;
+            }
+            Ok(changes)
+        })?;
+        let removed = changes.len();
+        self.journal(operation, changes)?;
+        Ok(removed)
     }
 
-    // Updates the fts index with the new path using the passed in operation (merge or remove)
-    fn update_paths_index<F>(&self, path_bytes: &[u8], op: F) -> Result<()>
-    where
-        F: Fn(&Set<Vec<u8>>, &Set<Vec<u8>>) -> fst::Result<Set<Vec<u8>>>,
-    {
-        log::debug!(
-            "Updating path index: {}",
-            std::str::from_utf8(path_bytes).unwrap_or_default()
-        );
-        let delta_fst = Set::from_iter(vec![path_bytes])?;
-
-        let paths_fst = match self.main.get(INDEX_KEY)? {
-            Some(bytes) => Set::new(bytes.deref().into())?,
-            None => Set::default(),
-        };
+    /// Removes all paths that have not been visited for longer than max_age, pinned paths are
+    /// always kept. Returns the paths that were removed
+    pub fn expire(&self, max_age: Duration) -> Result<Vec<PathBuf>> {
+        tracing::debug!("Expiring paths older than: {:?}", max_age);
+        let now = SystemTime::now();
+        let expired = self
+            .list()?
+            .into_iter()
+            .filter(|entry| !entry.pinned)
+            .filter(|entry| {
+                now.duration_since(entry.timestamp)
+                    .is_ok_and(|age| age > max_age)
+            })
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+        self.delete_many(&expired.iter().map(PathBuf::as_path).collect::<Vec<_>>())?;
+        Ok(expired)
+    }
 
-        let new_fst = op(&paths_fst, &delta_fst)?;
+    // Runs expire with the configured max_age, unless it is disabled or already ran recently
+    // Only the time of the last run is read when it is not due, so this is cheap to call often
+    fn expire_if_due(&self) -> Result<()> {
+        if self.max_age.is_zero() {
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        if let Some(bytes) = self.main.get(LAST_EXPIRY_KEY)? {
+            let last_expiry: SystemTime = bincode::deserialize(&bytes)?;
+            if is_recent(last_expiry, now, EXPIRY_INTERVAL) {
+                return Ok(());
+            }
+        }
 
-        self.main.insert(INDEX_KEY, new_fst.as_fst().as_bytes())?;
+        let expired = self.expire(self.max_age)?;
+        tracing::debug!("Expired {} paths", expired.len());
+        self.main
+            .insert(LAST_EXPIRY_KEY, bincode::serialize(&now)?)?;
         Ok(())
     }
 
-    #[cfg(test)]
-    /// Helper function for use in testing that checks if
-    /// a particular path has been added to the index
-    fn has_path(&self, path_buf: &Path) -> bool {
-        match self.paths.get(path_buf.to_string_lossy().as_bytes()) {
-            Err(_) => false,
-            Ok(res) => res.is_some(),
-        }
+    /// Removes the lowest ranked paths until at most max paths remain
+    /// Paths are ranked by their frecency, the least recently visited path goes first on a tie.
+    /// Pinned paths are never removed, so more than max paths remain if more are pinned.
+    /// Returns the number of paths that were removed
+    pub fn evict_to(&self, max: usize) -> Result<usize> {
+        self.evict_to_keeping(max, None)
     }
+
+    // Runs evict_to, but never removes keep
+    fn evict_to_keeping(&self, max: usize, keep: Option<&Path>) -> Result<usize> {
+        let entries = self.list()?;
+        let excess = entries.len().saturating_sub(max);
+        if excess == 0 {
+            return Ok(0);
+        }
+        let now = SystemTime::now();
+        let mut candidates = entries
+            .into_iter()
+            .filter(|entry| !entry.pinned && Some(entry.path.as_path()) != keep)
+            .map(|entry| {
+                let record = PathRecord {
+                    timestamp: entry.timestamp,
+                    count: entry.count,
+                    pinned: entry.pinned,
+                    weight: entry.weight,
+                    hits: entry.hits,
+                };
+                (
+                    record.frecency(now, self.half_life),
+                    entry.timestamp,
+                    entry.path,
+                )
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+        tracing::debug!("Evicting {} paths", excess.min(candidates.len()));
+        let evicted = candidates
+            .iter()
+            .take(excess)
+            .map(|(_, _, path)| path.as_path())
+            .collect::<Vec<_>>();
+        self.delete_many(&evicted)
+    }
+
+    // Runs evict_to with the configured max_entries, unless there is no limit
+    // keep is never removed
+    fn enforce_max_entries(&self, keep: Option<&Path>) -> Result<()> {
+        if self.max_entries > 0 {
+            self.evict_to_keeping(self.max_entries, keep)?;
+        }
+        Ok(())
+    }
+
+    /// Removes all paths that are no longer a directory, pinned paths are always kept
+    /// If apply_ignores is set, paths matching the ignore patterns are removed as well
+    /// Returns the paths that were removed
+    pub fn clean(&self, apply_ignores: bool) -> Result<Vec<PathBuf>> {
+        tracing::debug!(
+            "Cleaning index, applying ignore patterns: {}",
+            apply_ignores
+        );
+        let stale = self
+            .list()?
+            .into_iter()
+            .filter(|entry| !entry.pinned)
+            .filter(|entry| !entry.path.is_dir() || (apply_ignores && self.is_ignored(&entry.path)))
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+        self.remove_paths(
+            &stale.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+            JournalOperation::Prune,
+        )?;
+        Ok(stale)
+    }
+
+    /// Removes all paths and indexes from the database
+    /// The removed paths are journaled, so a single undo brings all of them back
+    pub fn clear(&self) -> Result<()> {
+        tracing::debug!("Clearing index");
+        let changes = self
+            .paths
+            .iter()
+            .map(|item| {
+                let (key, value) = item?;
+                Ok((key.to_vec(), Some(value.to_vec())))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.paths.clear()?;
+        self.main.clear()?;
+        self.history.clear()?;
+        self.write_schema_version(SCHEMA_VERSION)?;
+        self.journal(JournalOperation::Clear, changes)
+    }
+
+    // Remembers the records the changed paths had before operation, so undo can restore them
+    // Only the last JOURNAL_MAX_LEN changes are kept
+    fn journal(&self, operation: JournalOperation, changes: Vec<JournalChange>) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let record = JournalRecord {
+            timestamp: SystemTime::now(),
+            operation,
+            changes,
+        };
+        let id = self.db.generate_id()?;
+        self.journal
+            .insert(id.to_be_bytes(), bincode::serialize(&record)?)?;
+        while self.journal.len() > JOURNAL_MAX_LEN {
+            self.journal.pop_min()?;
+        }
+        Ok(())
+    }
+
+    /// Reverts the most recent journaled change: removed paths get back the record they had
+    /// and added paths are removed again. Returns None if there is nothing left to undo
+    pub fn undo(&self) -> Result<Option<UndoReport>> {
+        let (id, value) = match self.journal.last()? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let record = bincode::deserialize::<JournalRecord>(&value)?;
+        tracing::debug!(
+            "Undoing {} of {} paths",
+            record.operation,
+            record.changes.len()
+        );
+
+        let removed = self.transaction(|paths, main| {
+            let mut restored = Vec::new();
+            let mut removed = Vec::new();
+            for (path_bytes, previous) in record.changes.iter() {
+                match previous {
+                    Some(bytes) => {
+                        if paths
+                            .insert(path_bytes.as_slice(), bytes.as_slice())?
+                            .is_none()
+                        {
+                            restored.push(path_bytes.clone());
+                        }
+                    }
+                    None => {
+                        if paths.remove(path_bytes.as_slice())?.is_some() {
+                            removed.push(path_bytes.clone());
+                        }
+                    }
+                }
+            }
+            removed.sort();
+            if !removed.is_empty() {
+                remove_from_fsts(main, &Set::from_iter(removed.iter()).map_err(abort)?)?;
+            }
+            index_new_paths(main, restored)?;
+            Ok(removed)
+        })?;
+        self.journal.remove(id)?;
+
+        Ok(Some(UndoReport {
+            operation: record.operation,
+            timestamp: record.timestamp,
+            restored: record
+                .changes
+                .iter()
+                .filter(|(_, previous)| previous.is_some())
+                .map(|(path_bytes, _)| path_from_bytes(path_bytes))
+                .collect(),
+            removed: removed
+                .iter()
+                .map(|path_bytes| path_from_bytes(path_bytes))
+                .collect(),
+        }))
+    }
+
+    /// Remembers that search took the user from `from` to `to`, for back and history
+    /// Only the last HISTORY_MAX_LEN jumps are kept. Processes take turns opening the
+    /// database, so the ids keep the jumps of different shells in order.
+    pub fn record_jump(&self, from: &Path, to: &Path) -> Result<()> {
+        tracing::debug!("Recording jump from {} to {}", from.display(), to.display());
+        let record = JumpRecord {
+            timestamp: SystemTime::now(),
+            from: path_to_bytes(&normalize_path(from)).into_owned(),
+            to: path_to_bytes(&normalize_path(to)).into_owned(),
+        };
+        let id = self.db.generate_id()?;
+        self.history
+            .insert(id.to_be_bytes(), bincode::serialize(&record)?)?;
+        while self.history.len() > HISTORY_MAX_LEN {
+            self.history.pop_min()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the remembered jumps, the most recent one first
+    pub fn history(&self) -> Result<Vec<Jump>> {
+        self.history
+            .iter()
+            .values()
+            .rev()
+            .map(|value| Ok(bincode::deserialize::<JumpRecord>(&value?)?.into()))
+            .collect()
+    }
+
+    /// Returns the directory the most recent jump started from
+    pub fn previous_location(&self) -> Result<Option<PathBuf>> {
+        match self.history.last()? {
+            Some((_, value)) => {
+                let record = bincode::deserialize::<JumpRecord>(&value)?;
+                Ok(Some(path_from_bytes(&record.from)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Moves the entry of a path that was renamed to its new location, keeping its visits
+    /// If the new path is already indexed, its entry is overwritten, only the most recent
+    /// visit of both is kept
+    pub fn rename(&self, old: &Path, new: &Path) -> Result<()> {
+        tracing::debug!("Renaming {} to {}", old.display(), new.display());
+        let (old, new) = (&normalize_path(old), &normalize_path(new));
+        validate_path(new)?;
+        if old == new {
+            return Ok(());
+        }
+        let moves = [(path_to_bytes(old).to_vec(), path_to_bytes(new).to_vec())];
+        match self.move_records(&moves, PathRecord::replace)? {
+            0 => Err(IndexError::NoResults(old.to_string_lossy().into_owned()).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the entries of a renamed directory and of every path below it to the new
+    /// location, overwriting existing entries like rename does. Returns the number of entries
+    /// that were moved.
+    pub fn rename_prefix(&self, old: &Path, new: &Path) -> Result<usize> {
+        tracing::debug!("Renaming {} and below to {}", old.display(), new.display());
+        let (old, new) = (&normalize_path(old), &normalize_path(new));
+        validate_path(new)?;
+        if old == new {
+            return Ok(0);
+        }
+        if new.starts_with(old) {
+            return Err(IndexError::MoveIntoSelf(old.to_string_lossy().into_owned()).into());
+        }
+
+        let mut moves = Vec::new();
+        for key in self.paths.scan_prefix(path_to_bytes(old)).keys() {
+            let key = key?;
+            let path = path_from_bytes(&key);
+            // The byte prefix also matches siblings like /foobar for /foo
+            let rest = match path.strip_prefix(old) {
+                Ok(rest) => rest,
+                Err(_) => continue,
+            };
+            let new_path = match rest.as_os_str().is_empty() {
+                true => new.to_path_buf(),
+                false => new.join(rest),
+            };
+            moves.push((key.to_vec(), path_to_bytes(&new_path).to_vec()));
+        }
+        self.move_records(&moves, PathRecord::replace)
+    }
+
+    /// Returns the number of paths and the size of the search index and the database
+    pub fn stats(&self) -> Result<IndexStats> {
+        let fst_bytes = [INDEX_KEY, DELTA_KEY]
+            .iter()
+            .map(|key| Ok(self.main.get(key)?.map_or(0, |bytes| bytes.len())))
+            .sum::<Result<usize>>()?;
+        Ok(IndexStats {
+            paths: self.paths.len(),
+            fst_bytes,
+            disk_bytes: self.db.size_on_disk()?,
+        })
+    }
+
+    /// Returns the indexed paths that are dir or below it, ordered by path
+    pub fn list_below(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let dir = normalize_path(dir);
+        let mut paths = Vec::new();
+        for key in self.paths.scan_prefix(path_to_bytes(&dir)).keys() {
+            let path = path_from_bytes(&key?);
+            // The byte prefix also matches siblings like /foobar for /foo
+            if path.starts_with(&dir) {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Removes dir and every path below it from the index, pinned paths included, updating
+    /// the indexes only once. Returns the paths that were removed
+    pub fn forget(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        tracing::debug!("Forgetting {} and below", dir.display());
+        let paths = self.list_below(dir)?;
+        self.delete_many(&paths.iter().map(PathBuf::as_path).collect::<Vec<_>>())?;
+        Ok(paths)
+    }
+
+    /// Finds the indexed paths that resolve to the same directory, through symlinks or because
+    /// they are spelled differently. Paths that can't be resolved, like dangling symlinks, are
+    /// left out. The groups are sorted by the path that is kept.
+    pub fn find_duplicates(&self, prefer: DedupePreference) -> Result<Vec<DuplicateGroup>> {
+        let mut by_real_path = BTreeMap::<PathBuf, Vec<PathIndexEntry>>::new();
+        for entry in self.list()? {
+            match fs::canonicalize(&entry.path) {
+                Ok(real_path) => by_real_path.entry(real_path).or_default().push(entry),
+                Err(e) => tracing::debug!("Can't resolve {}: {}", entry.path.display(), e),
+            }
+        }
+
+        Ok(by_real_path
+            .into_iter()
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(real_path, entries)| {
+                let kept = match prefer {
+                    DedupePreference::Canonical => real_path,
+                    DedupePreference::MostUsed => entries
+                        .iter()
+                        .max_by(|a, b| {
+                            a.count
+                                .cmp(&b.count)
+                                .then_with(|| a.timestamp.cmp(&b.timestamp))
+                                .then_with(|| (a.path == real_path).cmp(&(b.path == real_path)))
+                        })
+                        .map(|entry| entry.path.clone())
+                        .unwrap_or(real_path),
+                };
+                let merged = entries
+                    .into_iter()
+                    .map(|entry| entry.path)
+                    .filter(|path| *path != kept)
+                    .collect();
+                DuplicateGroup { kept, merged }
+            })
+            .collect())
+    }
+
+    /// Merges the indexed paths that resolve to the same directory into a single entry, like
+    /// rename merges them, in a single transaction. Returns the groups that were merged
+    pub fn dedupe(&self, prefer: DedupePreference) -> Result<Vec<DuplicateGroup>> {
+        tracing::debug!("Deduplicating index, preferring: {:?}", prefer);
+        let groups = self.find_duplicates(prefer)?;
+        let moves = groups
+            .iter()
+            .flat_map(|group| {
+                let new_key = path_to_bytes(&group.kept).into_owned();
+                group
+                    .merged
+                    .iter()
+                    .map(move |path| (path_to_bytes(path).into_owned(), new_key.clone()))
+            })
+            .collect::<Vec<_>>();
+        self.move_records(&moves, PathRecord::merge)?;
+        Ok(groups)
+    }
+
+    // Moves the record of every (old, new) pair of keys in a single transaction, old keys that
+    // are not stored are skipped. A record moved onto an indexed path is combined with the
+    // record that is there, like PathRecord::replace or PathRecord::merge do.
+    // Returns the number of records that were moved
+    fn move_records<C>(&self, moves: &[(Vec<u8>, Vec<u8>)], combine: C) -> Result<usize>
+    where
+        C: Fn(&mut PathRecord, &PathRecord),
+    {
+        self.transaction(|paths, main| {
+            let mut removed = Vec::new();
+            let mut added = Vec::new();
+            for (old_key, new_key) in moves {
+                let mut record = match paths.get(old_key.as_slice())? {
+                    Some(bytes) => PathRecord::from_bytes(&bytes).map_err(abort)?,
+                    None => continue,
+                };
+                let existing = paths.get(new_key.as_slice())?;
+                if let Some(bytes) = &existing {
+                    combine(&mut record, &PathRecord::from_bytes(bytes).map_err(abort)?);
+                }
+                paths.insert(new_key.as_slice(), record.to_bytes().map_err(abort)?)?;
+                paths.remove(old_key.as_slice())?;
+
+                removed.push(old_key.clone());
+                if existing.is_none() {
+                    added.push(new_key.clone());
+                }
+            }
+
+            removed.sort();
+            let moved = removed.len();
+            if moved > 0 {
+                remove_from_fsts(main, &Set::from_iter(removed).map_err(abort)?)?;
+            }
+            index_new_paths(main, added)?;
+            Ok(moved)
+        })
+    }
+
+    /// Pins a path, so it wins from any unpinned path whenever it matches a target
+    /// A path that is not indexed yet is added first
+    pub fn pin(&self, path_buf: &Path) -> Result<()> {
+        tracing::debug!("Pinning path: {}", path_buf.display());
+        let path_buf = normalize_path(path_buf);
+        if !self.contains(&path_buf)? {
+            self.add(&path_buf)?;
+        }
+        self.set_pinned(&path_buf, true)
+    }
+
+    /// Removes the pin from a path, the path remains in the index
+    pub fn unpin(&self, path_buf: &Path) -> Result<()> {
+        tracing::debug!("Unpinning path: {}", path_buf.display());
+        self.set_pinned(&normalize_path(path_buf), false)
+    }
+
+    fn set_pinned(&self, path_buf: &Path, pinned: bool) -> Result<()> {
+        self.update_record(path_buf, |record| record.pinned = pinned)?;
+        Ok(())
+    }
+
+    /// Adds delta to the manual weight of an indexed path, which is added to its score when
+    /// ranking matches. The weight is clamped to [-MAX_WEIGHT, MAX_WEIGHT].
+    /// Returns the new weight
+    pub fn adjust_weight(&self, path_buf: &Path, delta: i64) -> Result<i64> {
+        tracing::debug!(
+            "Adjusting weight of path: {} by {}",
+            path_buf.display(),
+            delta
+        );
+        let path_buf = normalize_path(path_buf);
+        let record = self.update_record(&path_buf, |record| {
+            record.weight = record
+                .weight
+                .saturating_add(delta)
+                .clamp(-MAX_WEIGHT, MAX_WEIGHT)
+        })?;
+        Ok(record.weight)
+    }
+
+    /// Replaces the manual weight of an indexed path, clamped to [-MAX_WEIGHT, MAX_WEIGHT]
+    /// Returns the new weight
+    pub fn set_weight(&self, path_buf: &Path, weight: i64) -> Result<i64> {
+        tracing::debug!(
+            "Setting weight of path: {} to {}",
+            path_buf.display(),
+            weight
+        );
+        let path_buf = normalize_path(path_buf);
+        let record = self.update_record(&path_buf, |record| {
+            record.weight = weight.clamp(-MAX_WEIGHT, MAX_WEIGHT)
+        })?;
+        Ok(record.weight)
+    }
+
+    // Applies update to the record of an indexed path and stores the result
+    fn update_record<F>(&self, path_buf: &Path, update: F) -> Result<PathRecord>
+    where
+        F: FnOnce(&mut PathRecord),
+    {
+        let mut record = self
+            .get_record(path_buf)?
+            .ok_or_else(|| IndexError::NotIndexed(path_buf.to_string_lossy().into_owned()))?;
+        update(&mut record);
+        self.paths
+            .insert(path_to_bytes(path_buf), record.to_bytes()?)?;
+        Ok(record)
+    }
+
+    // Checks if the path, or any of its parents, matches one of the ignore patterns
+    fn is_ignored(&self, path_buf: &Path) -> bool {
+        !self.ignore.is_empty()
+            && path_buf
+                .ancestors()
+                .any(|ancestor| self.ignore.is_match(ancestor))
+    }
+
+    fn get_record(&self, path: &Path) -> Result<Option<PathRecord>> {
+        let record_bytes = self.paths.get(path_to_bytes(path))?;
+        record_bytes
+            .map(|x| PathRecord::from_bytes(x.as_ref()))
+            .transpose()
+    }
+
+    // Consumes the vector and returns the item with the best score, the first one rank_scores
+    // would return
+    // Care is taken to minimize the amount of database lookups: the scores are visited from
+    // the best they could become to the worst, and a record is only read while the score
+    // can still beat or tie the best one so far
+    fn get_best_score(&self, mut results: Vec<Score>, cwd: Option<&Path>) -> Result<Option<Score>> {
+        results.sort_by_cached_key(|score| Reverse(self.best_case(score, cwd)));
+        let now = SystemTime::now();
+        let mut best: Option<Score> = None;
+        for mut score in results {
+            if best.as_ref().is_some_and(|best| {
+                (best.pinned, best.weighted_score()) > self.best_case(&score, cwd)
+            }) {
+                break;
+            }
+            self.complete_score(&mut score, cwd, now)?;
+            if best.as_ref().is_none_or(|best| score > *best) {
+                best = Some(score);
+            }
+        }
+        Ok(best)
+    }
+
+    // Returns whether a score could be pinned and the highest weighted score it could get, before
+    // its record is read. The frecency bonus has no upper bound
+    fn best_case(&self, score: &Score, cwd: Option<&Path>) -> (bool, i64) {
+        let pinnable = !self.fuzzy_scoring || score.score > PIN_SCORE_THRESHOLD;
+        if self.fuzzy_scoring && self.frecency_weight > 0 {
+            return (pinnable, i64::MAX);
+        }
+        let bonus = match cwd.is_some_and(|cwd| is_descendant(&score.path, cwd)) {
+            true => CWD_BONUS,
+            false => 0,
+        };
+        (
+            pinnable,
+            score.score.saturating_add(bonus).saturating_add(MAX_WEIGHT),
+        )
+    }
+
+    // Fills in the parts of the scores that come from the stored records and sorts them,
+    // the best score first
+    fn rank_scores(&self, mut results: Vec<Score>, cwd: Option<&Path>) -> Result<Vec<Score>> {
+        let now = SystemTime::now();
+        for score in results.iter_mut() {
+            self.complete_score(score, cwd, now)?;
+        }
+
+        results.sort_by(|a, b| b.cmp(a));
+        Ok(results)
+    }
+
+    // Fills in the parts of a score that come from its record and the current directory
+    // Pinned paths that match the target win from unpinned paths, the manual weight of a path
+    // is added to its fuzzy score and the visit count, decayed by the time since the last
+    // visit, is used as a tie-breaker
+    fn complete_score(&self, score: &mut Score, cwd: Option<&Path>, now: SystemTime) -> Result<()> {
+        if cwd.is_some_and(|cwd| is_descendant(&score.path, cwd)) {
+            score.bonus = CWD_BONUS;
+        }
+        if let Some(record) = self.get_record(&score.path)? {
+            // Without fuzzy scores every match counts as a good match
+            score.pinned =
+                record.pinned && (!self.fuzzy_scoring || score.score > PIN_SCORE_THRESHOLD);
+            score.weight = record.weight;
+            // Leaving out the frecency and count makes the timestamp break the ties
+            if self.fuzzy_scoring {
+                score.frecency = record.frecency(now, self.half_life);
+                score.frecency_bonus = (score.frecency * self.frecency_weight as f64) as i64;
+                score.count = record.count;
+            }
+            score.timestamp = Some(record.timestamp);
+        }
+        if self.missing_penalty != 0 && !score.path.is_dir() {
+            score.pinned = false;
+            score.penalty = self.missing_penalty;
+        }
+        Ok(())
+    }
+
+    /// Compares the keys in the paths tree with the paths in the fsts
+    pub fn verify(&self) -> Result<VerifyReport> {
+        tracing::debug!("Verifying index");
+        let stored = self.read_stored_paths()?;
+        let indexed = search_fsts(
+            &[self.read_fst(INDEX_KEY)?, self.read_fst(DELTA_KEY)?],
+            automaton::AlwaysMatch,
+        );
+
+        // Both lists are sorted, so they can be compared in a single pass
+        let mut report = VerifyReport {
+            invalid_paths: stored
+                .iter()
+                .filter(|key| !is_valid_key(key))
+                .map(|key| path_from_bytes(key))
+                .collect(),
+            ..Default::default()
+        };
+        let (mut stored, mut indexed) = (stored.iter().peekable(), indexed.iter().peekable());
+        loop {
+            match (stored.peek(), indexed.peek()) {
+                (Some(s), Some(i)) if s == i => {
+                    stored.next();
+                    indexed.next();
+                }
+                (Some(s), Some(i)) if s < i => {
+                    report.missing_from_fst.push(path_from_bytes(s));
+                    stored.next();
+                }
+                (Some(s), None) => {
+                    report.missing_from_fst.push(path_from_bytes(s));
+                    stored.next();
+                }
+                (_, Some(i)) => {
+                    report.missing_from_paths.push(path_from_bytes(i));
+                    indexed.next();
+                }
+                (None, None) => break,
+            }
+        }
+        Ok(report)
+    }
+
+    /// Replaces the fsts by one built from scratch from the keys in the paths tree
+    /// Invalid keys are removed from the paths tree instead
+    /// Returns the number of paths in the new fst
+    pub fn rebuild_fst(&self) -> Result<usize> {
+        tracing::debug!("Rebuilding fst");
+        // sled iterates keys in sorted order, which is what the fst requires
+        let mut builder = SetBuilder::memory();
+        let mut invalid = Vec::new();
+        for key in self.paths.iter().keys() {
+            let key = key?;
+            if is_valid_key(&key) {
+                builder.insert(&key)?;
+            } else {
+                invalid.push(key.to_vec());
+            }
+        }
+        let paths_fst = builder.into_set();
+        self.transaction(|paths, main| {
+            for key in &invalid {
+                paths.remove(key.as_slice())?;
+            }
+            main.insert(INDEX_KEY, paths_fst.as_fst().as_bytes())?;
+            main.remove(DELTA_KEY)?;
+            Ok(())
+        })?;
+        Ok(paths_fst.len())
+    }
+
+    // Returns the sorted keys of the paths tree
+    fn read_stored_paths(&self) -> Result<Vec<Vec<u8>>> {
+        self.paths
+            .iter()
+            .keys()
+            .map(|key| Ok(key?.to_vec()))
+            .collect()
+    }
+
+    // Reads one of the fsts stored in the main tree, an fst that was never written is empty
+    // A corrupt fst is rebuilt from the paths tree first
+    fn read_fst(&self, key: &str) -> Result<Set<Vec<u8>>> {
+        match self.read_stored_fst(key) {
+            Err(e) if is_corrupt_fst(&e) => {
+                tracing::warn!("The search index is corrupt, rebuilding it");
+                tracing::debug!("Reading the fst failed: {}", e);
+                self.rebuild_fst()?;
+                self.read_stored_fst(key)
+            }
+            result => result,
+        }
+    }
+
+    fn read_stored_fst(&self, key: &str) -> Result<Set<Vec<u8>>> {
+        Ok(match self.main.get(key)? {
+            Some(bytes) => {
+                Set::new(bytes.deref().into()).map_err(|e| IndexError::CorruptFst(e.to_string()))?
+            }
+            None => Set::default(),
+        })
+    }
+
+    // Runs f in a single transaction on the paths and the main tree, so the fsts can never
+    // disagree with the stored paths, even if the process is killed halfway
+    // f is run again if the transaction conflicts with a concurrent one, or after rebuilding
+    // the fsts if it found them corrupt
+    fn transaction<F, T>(&self, f: F) -> Result<T>
+    where
+        F: Fn(&TransactionalTree, &TransactionalTree) -> TransactionResult<T>,
+    {
+        let run = || {
+            (&self.paths, &self.main)
+                .transaction(|(paths, main)| f(paths, main))
+                .map_err(|e| match e {
+                    TransactionError::Abort(e) => e,
+                    TransactionError::Storage(e) => e.into(),
+                })
+        };
+        match run() {
+            Err(e) if is_corrupt_fst(&e) => {
+                tracing::warn!("The search index is corrupt, rebuilding it");
+                tracing::debug!("Reading the fst failed: {}", e);
+                self.rebuild_fst()?;
+                run()
+            }
+            result => result,
+        }
+    }
+
+    #[cfg(test)]
+    /// Helper function for use in testing that adds paths to the fsts, without storing them
+    fn insert_into_paths_index(&self, path_fst: Set<Vec<u8>>) -> Result<()> {
+        self.transaction(|_, main| insert_into_fsts(main, &path_fst))
+    }
+}
+
+/// Splits the search terms into the words they consist of, dropping empty terms
+fn split_words<'a>(terms: &[&'a str]) -> Vec<&'a str> {
+    terms
+        .iter()
+        .flat_map(|term| term.split_whitespace())
+        .collect()
+}
+
+/// Matches the keys that are matched by every word automaton, the words can be in any order
+#[derive(Clone, Debug)]
+struct Subsequences<A> {
+    words: Vec<A>,
+}
+
+impl<A: Automaton> Automaton for Subsequences<A> {
+    // The state of the automaton of each word
+    type State = Vec<A::State>;
+
+    fn start(&self) -> Vec<A::State> {
+        self.words.iter().map(|word| word.start()).collect()
+    }
+
+    fn is_match(&self, state: &Vec<A::State>) -> bool {
+        self.words
+            .iter()
+            .zip(state)
+            .all(|(word, state)| word.is_match(state))
+    }
+
+    fn can_match(&self, state: &Vec<A::State>) -> bool {
+        self.words
+            .iter()
+            .zip(state)
+            .all(|(word, state)| word.can_match(state))
+    }
+
+    fn will_always_match(&self, state: &Vec<A::State>) -> bool {
+        self.words
+            .iter()
+            .zip(state)
+            .all(|(word, state)| word.will_always_match(state))
+    }
+
+    fn accept(&self, state: &Vec<A::State>, byte: u8) -> Vec<A::State> {
+        self.words
+            .iter()
+            .zip(state)
+            .map(|(word, state)| word.accept(state, byte))
+            .collect()
+    }
+}
+
+/// Returns a regex that matches the keys containing the characters of 'word' in order, in
+/// their last component if 'basename' is set
+/// The characters of the word are matched literally, anything in between can be any byte,
+/// so keys that aren't valid UTF-8 still match. Build it with allow_invalid_utf8.
+fn subsequence_pattern(word: &str, basename: bool) -> String {
+    const ANY_BYTES: &str = "(?s-u:.)*";
+    const NAME_BYTES: &str = "(?-u:[^/])*";
+    let (mut pattern, gap) = match basename {
+        true => (format!("{}/{}", ANY_BYTES, NAME_BYTES), NAME_BYTES),
+        false => (ANY_BYTES.to_owned(), ANY_BYTES),
+    };
+    for c in word.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+        pattern.push_str(gap);
+    }
+    pattern
+}
+
+/// Computes the fuzzy matching score of each result against the target string
+/// Paths whose last component matches the last term get name_bonus on top of their score
+/// With 'basename' only the last component is scored
+fn score_results(
+    results: &[Vec<u8>],
+    terms: &[&str],
+    scorer: &dyn FuzzyMatcher,
+    name_bonus: i64,
+    basename: bool,
+) -> Vec<Score> {
+    results
+        .iter()
+        .map(|item| {
+            let path = path_from_bytes(item);
+            let item_str = match path.file_name() {
+                Some(name) if basename => name.to_string_lossy(),
+                _ => String::from_utf8_lossy(item),
+            };
+            let score = terms
+                .iter()
+                .map(|term| scorer.fuzzy_match(&item_str, term).unwrap_or_default())
+                .sum::<i64>();
+            // Paths that contain the words in the order they were given also score on the
+            // words joined together, so `proj srv` prefers /proj/srv over /srv/proj
+            let in_order = match terms.len() {
+                0 | 1 => 0,
+                _ => scorer
+                    .fuzzy_match(&item_str, &terms.concat())
+                    .unwrap_or_default(),
+            };
+            let name_matches = match (path.file_name(), terms.last()) {
+                (Some(name), Some(term)) => scorer
+                    .fuzzy_match(&name.to_string_lossy(), term)
+                    .is_some_and(|name_score| name_score > NAME_SCORE_THRESHOLD),
+                _ => false,
+            };
+            let bonus = if name_matches { name_bonus } else { 0 };
+            Score::new(path, score.saturating_add(in_order).saturating_add(bonus))
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Checks if the name of a directory, its last component, is the search term itself
+fn is_exact_match(path: &Path, term: &str, case_sensitive: bool) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if case_sensitive => name == term,
+        Some(name) => name.to_lowercase() == term.to_lowercase(),
+        None => false,
+    }
+}
+
+/// Compiles the glob patterns of ignored paths, see IndexBuilder::ignore_patterns
+fn build_ignore_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Returns the spelling of a path that is stored in the index: without a trailing separator,
+/// repeated separators or `.` components. Symlinks and `..` are kept as they are, like the
+/// working directory that the shell reports.
+pub fn normalize_path(path_buf: &Path) -> PathBuf {
+    path_buf.components().collect()
+}
+
+/// Checks that a path can be stored in the index: it must be an absolute path to a directory
+pub fn validate_path(path_buf: &Path) -> Result<(), IndexError> {
+    let path_string = path_buf.to_string_lossy();
+    if !path_buf.is_dir() {
+        return Err(IndexError::PathDoesNotExist(path_string.into_owned()));
+    }
+    if !path_buf.is_absolute() {
+        return Err(IndexError::RelativePath(path_string.into_owned()));
+    }
+    Ok(())
+}
+
+/// Schema version 0 to 1: rewrites every record that was stored with fewer fields than a
+/// PathRecord has (e.g. the bare timestamp of versions before 0.4.0) in the full encoding
+fn migrate_full_records(index: &Index) -> Result<()> {
+    let mut batch = sled::Batch::default();
+    for item in index.paths.iter() {
+        let (key, value) = item?;
+        let record = PathRecord::from_bytes(&value)?;
+        let bytes = record.to_bytes()?;
+        if bytes != value.as_ref() {
+            batch.insert(key, bytes);
+        }
+    }
+    index.paths.apply_batch(batch)?;
+    Ok(())
+}
+
+// The key and value of a path in the paths tree
+type EncodedEntry = (Vec<u8>, Vec<u8>);
+// A path that could not be stored, together with the reason
+type RejectedPath = (PathBuf, IndexError);
+
+// Encodes the entries into the keys and values of the paths tree, keeping their order
+// Entries with a path that is not valid are returned separately, together with the reason
+fn encode_entries(entries: &[PathIndexEntry]) -> Result<(Vec<EncodedEntry>, Vec<RejectedPath>)> {
+    let mut rejected = Vec::new();
+    let mut records = Vec::new();
+    for entry in entries {
+        if let Err(e) = validate_path(&entry.path) {
+            rejected.push((entry.path.clone(), e));
+            continue;
+        }
+        let record = PathRecord {
+            timestamp: entry.timestamp,
+            count: entry.count,
+            pinned: entry.pinned,
+            weight: entry.weight.clamp(-MAX_WEIGHT, MAX_WEIGHT),
+            hits: entry.hits,
+        };
+        let path_bytes = path_to_bytes(&normalize_path(&entry.path)).into_owned();
+        records.push((path_bytes, record.to_bytes()?));
+    }
+    Ok((records, rejected))
+}
+
+/// Waits until no other process has the database at db_path open
+/// sled refuses to open a database that is open in another process, which happens whenever
+/// the prompt hooks of several shells add a path at the same time. This makes them take turns.
+/// A process can keep the database open for long (e.g. while the user edits it), so this gives
+/// up after LOCK_TIMEOUT. The lock is released when the returned file is dropped.
+fn lock_database(db_path: &Path) -> Result<File> {
+    if let Some(parent) = db_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut lock_path = db_path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)?;
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        match lock_file.try_lock_exclusive() {
+            Ok(()) => return Ok(lock_file),
+            Err(e) if e.kind() != fs2::lock_contended_error().kind() => return Err(e.into()),
+            Err(_) if Instant::now() >= deadline => {
+                return Err(IndexError::DatabaseLocked(db_path.display().to_string()).into())
+            }
+            Err(_) => thread::sleep(LOCK_RETRY_INTERVAL),
+        }
+    }
+}
+
+/// Cleans up after a compaction that was interrupted, see Index::compact_path
+/// Has to be called while holding the lock on the database
+fn recover_compaction(db_path: &Path) -> Result<()> {
+    let old_path = sibling_path(db_path, COMPACTED_SUFFIX);
+    let new_path = sibling_path(db_path, COMPACTING_SUFFIX);
+    if old_path.exists() {
+        if db_path.exists() {
+            // Interrupted after the compacted database took its place
+            fs::remove_dir_all(&old_path)?;
+        } else {
+            // Interrupted before the compacted database took its place, it may be incomplete
+            tracing::warn!(
+                "Restoring {} after an interrupted compaction",
+                db_path.display()
+            );
+            fs::rename(&old_path, db_path)?;
+        }
+    }
+    if new_path.exists() {
+        fs::remove_dir_all(&new_path)?;
+    }
+    Ok(())
+}
+
+/// Returns the path next to the database with suffix appended to its name
+fn sibling_path(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Returns a path next to the database that does not exist yet, to move it aside
+fn incompatible_backup_path(db_path: &Path) -> PathBuf {
+    let mut backup_path = db_path.as_os_str().to_owned();
+    backup_path.push(".incompatible");
+    let mut candidate = PathBuf::from(&backup_path);
+    let mut suffix = 1;
+    while candidate.exists() {
+        candidate = PathBuf::from(format!("{}.{}", backup_path.to_string_lossy(), suffix));
+        suffix += 1;
+    }
+    candidate
+}
+
+/// Aborts a transaction with the given error
+fn abort<E: Into<anyhow::Error>>(error: E) -> ConflictableTransactionError<anyhow::Error> {
+    ConflictableTransactionError::Abort(error.into())
+}
+
+/// Adds paths that were newly inserted in the paths tree to the fsts
+fn index_new_paths(main: &TransactionalTree, mut new_paths: Vec<Vec<u8>>) -> TransactionResult<()> {
+    if new_paths.is_empty() {
+        return Ok(());
+    }
+    // The fst requires its input to be sorted and without duplicates
+    new_paths.sort();
+    new_paths.dedup();
+    insert_into_fsts(main, &Set::from_iter(new_paths).map_err(abort)?)
+}
+
+/// Adds paths to the delta fst, which is merged into the main fst once it grows past
+/// DELTA_MAX_LEN. This keeps adding a path cheap, no matter how large the index is.
+fn insert_into_fsts(main: &TransactionalTree, path_fst: &Set<Vec<u8>>) -> TransactionResult<()> {
+    tracing::debug!("Adding {} paths to path index", path_fst.len());
+    let delta_fst = merge_fst_sets(&read_fst_in(main, DELTA_KEY)?, path_fst).map_err(abort)?;
+
+    if delta_fst.len() <= DELTA_MAX_LEN {
+        main.insert(DELTA_KEY, delta_fst.as_fst().as_bytes())?;
+        return Ok(());
+    }
+
+    tracing::debug!("Merging delta fst into the main fst");
+    let paths_fst = merge_fst_sets(&read_fst_in(main, INDEX_KEY)?, &delta_fst).map_err(abort)?;
+    main.insert(INDEX_KEY, paths_fst.as_fst().as_bytes())?;
+    main.remove(DELTA_KEY)?;
+    Ok(())
+}
+
+/// Removes paths from whichever fst holds them, only rewriting the fsts that change
+fn remove_from_fsts(main: &TransactionalTree, path_fst: &Set<Vec<u8>>) -> TransactionResult<()> {
+    tracing::debug!("Removing {} paths from path index", path_fst.len());
+    for key in &[INDEX_KEY, DELTA_KEY] {
+        let paths_fst = read_fst_in(main, key)?;
+        let new_fst = remove_fst_set(&paths_fst, path_fst).map_err(abort)?;
+        if new_fst.len() != paths_fst.len() {
+            main.insert(*key, new_fst.as_fst().as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one of the fsts stored in the main tree in a transaction, like Index::read_fst
+fn read_fst_in(main: &TransactionalTree, key: &str) -> TransactionResult<Set<Vec<u8>>> {
+    Ok(match main.get(key)? {
+        Some(bytes) => {
+            Set::new(bytes.to_vec()).map_err(|e| abort(IndexError::CorruptFst(e.to_string())))?
+        }
+        None => Set::default(),
+    })
+}
+
+/// Checks if an error is caused by an fst that can't be read
+fn is_corrupt_fst(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<IndexError>(),
+        Some(IndexError::CorruptFst(_))
+    )
 }
 
-/// Computes the fuzzy matching score of each result against the target string
-fn score_results(results: &[String], target: &str) -> Vec<Score> {
-    let scorer = ClangdMatcher::default();
-    results
-        .iter()
-        .map(|item| Score {
-            path: PathBuf::from(item),
-            score: scorer.fuzzy_match(item, target).unwrap_or_default(),
-            timestamp: None,
-        })
-        .collect::<Vec<_>>()
-}
+/// Checks if path lies below dir, which is not the case for dir itself
+fn is_descendant(path: &Path, dir: &Path) -> bool {
+    path != dir && path.starts_with(dir)
+}
+
+/// Checks if timestamp lies less than interval before now
+fn is_recent(timestamp: SystemTime, now: SystemTime, interval: Duration) -> bool {
+    now.duration_since(timestamp)
+        .is_ok_and(|elapsed| elapsed < interval)
+}
+
+/// Decodes the next field of a PathRecord, returning None if the record was written before
+/// the field was added
+fn decode_appended_field<T: DeserializeOwned>(reader: &mut &[u8]) -> Result<Option<T>> {
+    if reader.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize_from(reader)?))
+}
+
+/// Runs the query against each of the fsts like search_fsts, leaving out the exclude paths
+/// The excluded paths are filtered out of the results after the fact: folding them into the
+/// query would nest the automaton type once for every path, which can't be known at compile
+/// time, and automaton::Str can only be built from valid UTF-8
+fn search_fsts_excluding<A>(
+    fst_indexes: &[Set<Vec<u8>>],
+    query: A,
+    exclude: &[&Path],
+) -> Vec<Vec<u8>>
+where
+    A: Automaton + Clone,
+{
+    let mut results = search_fsts(fst_indexes, query);
+    if !exclude.is_empty() {
+        let excluded = exclude
+            .iter()
+            .map(|path| path_to_bytes(&normalize_path(path)).into_owned())
+            .collect::<HashSet<_>>();
+        results.retain(|key| !excluded.contains(key.as_slice()));
+    }
+    results
+}
+
+/// Runs the query against each of the fsts and returns the sorted union of the matching keys
+fn search_fsts<A>(fst_indexes: &[Set<Vec<u8>>], query: A) -> Vec<Vec<u8>>
+where
+    A: Automaton + Clone,
+{
+    let mut op = OpBuilder::new();
+    for fst_index in fst_indexes {
+        op = op.add(fst_index.search(query.clone()));
+    }
+
+    let mut stream = op.union();
+    let mut results = Vec::new();
+    while let Some(key) = stream.next() {
+        results.push(key.to_vec());
+    }
+    results
+}
+
+/// Returns the raw bytes of a path, which is how it is stored in the database and the fst
+/// On unix paths are arbitrary bytes, so this round-trips with path_from_bytes for any path
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+/// Returns the bytes of a path, which is how it is stored in the database and the fst
+/// Other platforms don't expose the raw bytes, so we fall back to a (lossy) UTF-8 encoding
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+        Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+    }
+}
+
+/// Reconstructs a path from the bytes stored in the database or the fst
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+/// Reconstructs a path from the bytes stored in the database or the fst
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Checks that a key of the paths tree is an absolute path that survives a round trip through
+/// PathBuf, anything else can never be visited or removed through the cli
+fn is_valid_key(key: &[u8]) -> bool {
+    let path = path_from_bytes(key);
+    path.is_absolute() && path_to_bytes(&path).as_ref() == key
+}
+
+/// Merges (creates a union) between two fst::Set and returns the result as a newly allocated fst::Set
+fn merge_fst_sets<D>(paths_set: &Set<D>, delta_set: &Set<D>) -> fst::Result<Set<Vec<u8>>>
+where
+    D: AsRef<[u8]>,
+{
+    tracing::debug!("Merging fst set");
+    let stream = paths_set.op().add(delta_set.stream()).union();
+
+    let mut paths_builder = SetBuilder::memory();
+    paths_builder.extend_stream(stream)?;
+    paths_builder.into_inner().and_then(Set::new)
+}
+
+/// Removes the second fst::Set from the first and returns the result as a newly allocated fst::Set
+fn remove_fst_set<D>(paths_set: &Set<D>, delta_set: &Set<D>) -> fst::Result<Set<Vec<u8>>>
+where
+    D: AsRef<[u8]>,
+{
+    tracing::debug!("Removing fst set");
+    let stream = paths_set.op().add(delta_set.stream()).difference();
+    let mut paths_builder = SetBuilder::memory();
+    paths_builder.extend_stream(stream)?;
+    paths_builder.into_inner().and_then(Set::new)
+}
+
+/// How well a path matches a search, see the Ord implementation for how scores are ranked
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Score {
+    pub pinned: bool,
+    /// Whether the last search term is the name of the directory, ignoring case unless the
+    /// search is case sensitive
+    pub exact: bool,
+    /// The fuzzy match score of the path, including the bonus for matching its last component
+    pub score: i64,
+    pub weight: i64,
+    /// The bonus for being below the current directory
+    pub bonus: i64,
+    /// The penalty for no longer being a directory
+    pub penalty: i64,
+    pub frecency: f64,
+    /// The points for the frecency, see IndexBuilder::frecency_weight
+    pub frecency_bonus: i64,
+    /// The number of visits, breaks ties between paths with the same frecency
+    pub count: u64,
+    /// The last visit, paths that are only in the fst don't have one
+    pub timestamp: Option<SystemTime>,
+    pub path: PathBuf,
+}
+
+impl Score {
+    fn new(path: PathBuf, score: i64) -> Score {
+        Score {
+            pinned: false,
+            exact: false,
+            score,
+            weight: 0,
+            bonus: 0,
+            penalty: 0,
+            frecency: 0.0,
+            frecency_bonus: 0,
+            count: 0,
+            timestamp: None,
+            path,
+        }
+    }
+
+    /// The score that paths are ranked on, after pinned paths
+    pub fn weighted_score(&self) -> i64 {
+        self.score
+            .saturating_add(self.weight)
+            .saturating_add(self.bonus)
+            .saturating_add(self.frecency_bonus)
+            .saturating_sub(self.penalty)
+    }
+}
+
+// Orders entries from the most to the least recently visited, see Index::recent
+// Ties are broken by the path
+struct ByRecency(PathIndexEntry);
+
+impl Ord for ByRecency {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .0
+            .timestamp
+            .cmp(&self.0.timestamp)
+            .then_with(|| self.0.path.cmp(&other.0.path))
+    }
+}
+
+impl PartialOrd for ByRecency {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ByRecency {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ByRecency {}
+
+// Pinned scores come first, then they are ordered by their fuzzy score plus weight and
+// bonuses, minus the penalty
+// Ties are broken by frecency, then the number of visits and then timestamp
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.pinned
+            .cmp(&other.pinned)
+            .then_with(|| self.weighted_score().cmp(&other.weighted_score()))
+            .then_with(|| self.frecency.total_cmp(&other.frecency))
+            .then_with(|| self.count.cmp(&other.count))
+            .then_with(|| self.timestamp.cmp(&other.timestamp))
+            .then_with(|| self.path.cmp(&other.path))
+    }
+}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// frecency is never NaN, which makes the ordering total
+impl Eq for Score {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn merge_fst_sets_both_empty() {
+        let fst1 = Set::default();
+        let fst2 = Set::default();
+
+        let result = merge_fst_sets(&fst1, &fst2).unwrap();
+
+        assert!(result.is_empty())
+    }
+
+    #[test]
+    fn merge_fst_sets_one_empty() {
+        let fst1 = Set::default();
+        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+
+        let result = merge_fst_sets(&fst1, &fst2).unwrap();
+
+        assert_eq!(result.len(), fst2.len());
+        assert!(result.is_subset(fst2.stream()))
+    }
+
+    #[test]
+    fn merge_fst_sets_no_empty() {
+        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
+        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+
+        let result = merge_fst_sets(&fst1, &fst2).unwrap();
+
+        assert_eq!(
+            result.stream().into_strs().unwrap(),
+            vec!["abc", "bar", "def", "foo"]
+        )
+    }
+
+    #[test]
+    fn remove_fst_set_both_empty() {
+        let fst1 = Set::default();
+        let fst2 = Set::default();
+
+        let result = remove_fst_set(&fst1, &fst2).unwrap();
+
+        assert!(result.is_empty())
+    }
+
+    #[test]
+    fn remove_fst_set_second_empty() {
+        let fst1 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+        let fst2 = Set::default();
+
+        let result = remove_fst_set(&fst1, &fst2).unwrap();
+
+        assert_eq!(result.len(), fst1.len());
+        assert!(result.is_subset(fst1.stream()))
+    }
+
+    #[test]
+    fn remove_fst_set_first_empty() {
+        let fst1 = Set::default();
+        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+
+        let result = remove_fst_set(&fst1, &fst2).unwrap();
+
+        assert!(result.is_empty())
+    }
+
+    #[test]
+    fn remove_fst_set_no_empty_no_overlap() {
+        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
+        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+
+        let result = remove_fst_set(&fst1, &fst2).unwrap();
+
+        assert_eq!(result.stream().into_strs().unwrap(), vec!["abc", "def"])
+    }
+
+    #[test]
+    fn remove_fst_set_no_empty_some_overlap() {
+        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
+        let fst2 = Set::from_iter(vec!["abc"]).unwrap();
+
+        let result = remove_fst_set(&fst1, &fst2).unwrap();
+
+        assert_eq!(result.stream().into_strs().unwrap(), vec!["def"])
+    }
+
+    #[test]
+    fn remove_fst_set_no_empty_full_overlap() {
+        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
+        let fst2 = Set::from_iter(vec!["abc", "def"]).unwrap();
+
+        let result = remove_fst_set(&fst1, &fst2).unwrap();
+
+        assert!(result.is_empty())
+    }
+
+    #[test]
+    fn score_result_equal_length() {
+        let input = vec![b"foo".to_vec(), b"bar".to_vec()];
+        let pattern = "abc";
+
+        let result = score_results(
+            input.as_slice(),
+            &[pattern],
+            &ClangdMatcher::default(),
+            0,
+            false,
+        );
+
+        assert_eq!(result.len(), input.len())
+    }
+
+    #[test]
+    fn score_result_empty_input() {
+        let input = Vec::<Vec<u8>>::new();
+        let pattern = "abc";
+
+        let result = score_results(
+            input.as_slice(),
+            &[pattern],
+            &ClangdMatcher::default(),
+            0,
+            false,
+        );
+
+        assert!(result.is_empty())
+    }
+
+    #[test]
+    fn score_result_name_bonus() {
+        let input = vec![
+            b"/usr/local/share/prometheus-data/src".to_vec(),
+            b"/home/user/projects/myproject/prometheus".to_vec(),
+        ];
+
+        let without_bonus = score_results(&input, &["pro"], &ClangdMatcher::default(), 0, false);
+        let with_bonus = score_results(&input, &["pro"], &ClangdMatcher::default(), 100, false);
+
+        assert_eq!(with_bonus[0].score, without_bonus[0].score);
+        assert_eq!(with_bonus[1].score, without_bonus[1].score + 100)
+    }
+
+    #[test]
+    fn score_result_terms_in_order() {
+        let input = vec![b"/srv/proj/www".to_vec(), b"/proj/srv/www".to_vec()];
+        let matcher = ClangdMatcher::default();
+
+        let result = score_results(&input, &["proj", "srv"], &matcher, 0, false);
+        assert!(result[1].score > result[0].score);
+        let result = score_results(&input, &["srv", "proj"], &matcher, 0, false);
+        assert!(result[0].score > result[1].score);
+        // A single term scores the same as before
+        let result = score_results(&input, &["www"], &matcher, 0, false);
+        assert_eq!(
+            result[0].score,
+            matcher.fuzzy_match("/srv/proj/www", "www").unwrap()
+        )
+    }
+
+    #[test]
+    fn index_rank_name_bonus() {
+        let index = get_temporary_index();
+        let paths_fst = Set::from_iter(vec![
+            "/home/user/projects/myproject/prometheus",
+            "/usr/local/share/prometheus/src",
+        ])
+        .unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
+
+        assert_eq!(
+            index.find_one(&["pro"], &[], None).unwrap(),
+            Some(PathBuf::from("/home/user/projects/myproject/prometheus"))
+        );
+        assert_eq!(
+            index.find_one(&["pro", "src"], &[], None).unwrap(),
+            Some(PathBuf::from("/usr/local/share/prometheus/src"))
+        )
+    }
+
+    #[test]
+    fn is_exact_match_name() {
+        let path = Path::new("/home/me/Projects");
+        assert!(is_exact_match(path, "Projects", true));
+        assert!(is_exact_match(path, "projects", false));
+        assert!(!is_exact_match(path, "projects", true));
+        assert!(!is_exact_match(path, "proj", false));
+        assert!(!is_exact_match(path, "me", false));
+        assert!(!is_exact_match(Path::new("/"), "", false))
+    }
+
+    fn get_temporary_index() -> Index {
+        open_temporary_index(IndexBuilder::new())
+    }
+
+    fn open_temporary_index(builder: IndexBuilder) -> Index {
+        let db_config = sled::Config::new().temporary(true);
+        builder.open_config(db_config).unwrap()
+    }
+
+    #[test]
+    fn index_add_absolute_directory() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
+        assert!(index.add(input).is_ok());
+        assert!(index.contains(input).unwrap());
+        input_dir.close().unwrap();
+    }
+
+    #[test]
+    fn index_add_relative_directory() {
+        let index = get_temporary_index();
+        // This may be a bit flaky, but running tests in a reproducable env on CI should be fine
+        let input = PathBuf::from("src");
+        assert_eq!(
+            index.add(&input).unwrap_err().downcast_ref::<IndexError>(),
+            Some(&IndexError::RelativePath(
+                input.to_string_lossy().into_owned()
+            ))
+        );
+        assert!(!index.contains(&input).unwrap())
+    }
+
+    #[test]
+    fn index_add_file() {
+        let index = get_temporary_index();
+        // This may be a bit flaky, but running tests in a reproducable env on CI should be fine
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path().join("foo.txt");
+        File::create(&input).unwrap();
+        assert_eq!(
+            index.add(&input).unwrap_err().downcast_ref::<IndexError>(),
+            Some(&IndexError::PathDoesNotExist(
+                input.to_string_lossy().into_owned()
+            ))
+        );
+        assert!(!index.contains(&input).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_add_non_existing_directory() {
+        let index = get_temporary_index();
+        let input = PathBuf::from("foo");
+        assert_eq!(
+            index.add(&input).unwrap_err().downcast_ref::<IndexError>(),
+            Some(&IndexError::PathDoesNotExist("foo".to_owned()))
+        );
+        assert!(!index.contains(&input).unwrap())
+    }
+
+    #[test]
+    fn index_add_non_canonical_absolute_directory() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path().join("..");
+        assert!(index.add(&input).is_ok());
+        assert!(index.contains(&input).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_check_add() {
+        let root = tempdir().unwrap();
+        let ignored = root.path().join("node_modules");
+        fs::create_dir(&ignored).unwrap();
+        let index = open_temporary_index(
+            IndexBuilder::new().ignore_patterns(&["**/node_modules".to_owned()]),
+        );
+
+        assert_eq!(index.check_add(root.path()).unwrap(), AddAction::Insert);
+        index.add(root.path()).unwrap();
+        assert_eq!(index.check_add(root.path()).unwrap(), AddAction::Refresh);
+        assert_eq!(index.check_add(&ignored).unwrap(), AddAction::Ignore);
+        assert_eq!(
+            index.check_add(Path::new("foo")).unwrap(),
+            AddAction::Reject(IndexError::PathDoesNotExist("foo".to_owned()))
+        );
+        assert!(!index.contains(&ignored).unwrap());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_add_trailing_separator() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let mut with_separator = input_dir.path().as_os_str().to_owned();
+        with_separator.push("/");
+
+        index.add(input_dir.path()).unwrap();
+        index.add(Path::new(&with_separator)).unwrap();
+        index
+            .add_many(&[PathBuf::from(&with_separator), PathBuf::from("/")])
+            .unwrap();
+
+        assert_eq!(
+            index
+                .list()
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("/"), input_dir.path().to_owned()]
+        );
+        assert!(index.contains(Path::new(&with_separator)).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn normalize_path_separators() {
+        assert_eq!(normalize_path(Path::new("/")).as_os_str(), "/");
+        assert_eq!(normalize_path(Path::new("/a/")).as_os_str(), "/a");
+        assert_eq!(normalize_path(Path::new("/a")).as_os_str(), "/a");
+        assert_eq!(normalize_path(Path::new("//a//./b/")).as_os_str(), "/a/b");
+        assert_eq!(normalize_path(Path::new("/a/../b")).as_os_str(), "/a/../b")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn index_add_non_utf8_directory() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path().join(OsStr::from_bytes(b"foo\xffbar"));
+        // Not every filesystem accepts invalid UTF-8 in file names
+        if fs::create_dir(&input).is_err() {
+            return;
+        }
+
+        assert!(index.add(&input).is_ok());
+        assert!(index.contains(&input).unwrap());
+
+        let list = index.list().unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].path, input);
+        assert_eq!(index.find_all(&["bar"], &[]).unwrap(), vec![input.clone()]);
+        assert!(index.find_all(&["bar"], &[&input]).unwrap().is_empty());
+        assert_eq!(index.find_one(&["bar"], &[], None).unwrap(), Some(input));
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_add_many() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let path1 = input_dir_1.path().to_owned();
+        let path2 = input_dir_2.path().to_owned();
+
+        let summary = index
+            .add_many(&[path1.clone(), path2.clone(), path1.clone()])
+            .unwrap();
+
+        assert_eq!(
+            summary,
+            AddSummary {
+                added: 2,
+                ..Default::default()
+            }
+        );
+        assert!(index.contains(&path1).unwrap());
+        assert!(index.contains(&path2).unwrap());
+        assert_eq!(index.read_fst(DELTA_KEY).unwrap().len(), 2);
+        assert_eq!(index.get_record(&path1).unwrap().unwrap().count, 2);
+        assert_eq!(index.get_record(&path2).unwrap().unwrap().count, 1);
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    #[test]
+    fn index_add_many_partial_failure() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let valid = input_dir.path().to_owned();
+        let relative = PathBuf::from("src");
+        let missing = input_dir.path().join("missing");
+
+        let summary = index
+            .add_many(&[relative.clone(), valid.clone(), missing.clone()])
+            .unwrap();
+
+        assert_eq!(summary.added, 1);
+        assert_eq!(
+            summary.rejected,
+            vec![
+                (relative.clone(), IndexError::RelativePath("src".to_owned())),
+                (
+                    missing.clone(),
+                    IndexError::PathDoesNotExist(missing.to_string_lossy().into_owned())
+                ),
+            ]
+        );
+        assert!(index.contains(&valid).unwrap());
+        assert!(!index.contains(&relative).unwrap());
+        assert!(!index.contains(&missing).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_import() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let path1 = input_dir_1.path();
+        let path2 = input_dir_2.path();
+        let pattern = path2.file_name().unwrap().to_str().unwrap();
+        index.add(path1).unwrap();
+        let mut entries = vec![
+            PathIndexEntry {
+                timestamp: days_ago(3),
+                path: path1.to_owned(),
+                count: 7,
+                pinned: true,
+                weight: -3,
+                hits: 0,
+            },
+            PathIndexEntry {
+                timestamp: days_ago(5),
+                path: path2.to_owned(),
+                count: 2,
+                pinned: false,
+                weight: 1000,
+                hits: 0,
+            },
+            PathIndexEntry {
+                timestamp: days_ago(5),
+                path: PathBuf::from("foo"),
+                count: 2,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        ];
+
+        let rejected = index.import(&entries).unwrap();
+
+        assert_eq!(
+            rejected,
+            vec![(
+                PathBuf::from("foo"),
+                IndexError::PathDoesNotExist("foo".to_owned())
+            )]
+        );
+        let mut list = index.list().unwrap();
+        list.sort();
+        // The weight is clamped and entries sort on their timestamp first
+        entries.truncate(2);
+        entries[1].weight = MAX_WEIGHT;
+        entries.reverse();
+        assert_eq!(list, entries);
+        assert_eq!(
+            index.find_one(&[pattern], &[], None).unwrap(),
+            Some(path2.to_owned())
+        );
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    #[test]
+    fn index_merge() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let path1 = normalize_path(input_dir_1.path());
+        let path2 = normalize_path(input_dir_2.path());
+        let known = PathRecord {
+            timestamp: days_ago(1),
+            count: 3,
+            pinned: false,
+            weight: 5,
+            hits: 1,
+        };
+        insert_record(&index, &path1, &known);
+        let (ten_days_ago, eight_days_ago) = (days_ago(10), days_ago(8));
+        let entry = |path: &Path, timestamp, count| PathIndexEntry {
+            timestamp,
+            path: path.to_owned(),
+            count,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+
+        let rejected = index
+            .merge(&[
+                entry(&path1, ten_days_ago, 4),
+                entry(&path2, ten_days_ago, 2),
+                entry(&path2, eight_days_ago, 1),
+                entry(Path::new("/does/not/exist"), ten_days_ago, 1),
+            ])
+            .unwrap();
+
+        assert_eq!(rejected.len(), 1);
+        // The known path keeps its most recent visit and weight, the visits are added up
+        assert_eq!(
+            index.get_record(&path1).unwrap(),
+            Some(PathRecord { count: 7, ..known })
+        );
+        let record2 = index.get_record(&path2).unwrap().unwrap();
+        assert_eq!(record2.count, 3);
+        assert_eq!(record2.timestamp, eight_days_ago);
+        let pattern2 = path2.file_name().unwrap().to_str().unwrap();
+        assert_eq!(
+            index.find_one(&[pattern2], &[], None).unwrap(),
+            Some(path2.clone())
+        );
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    #[test]
+    fn index_import_like_add() {
+        let root = tempdir().unwrap();
+        let dirs = ["a", "b", "node_modules"].map(|name| root.path().join(name));
+        let entries = dirs
+            .iter()
+            .enumerate()
+            .map(|(age, dir)| {
+                fs::create_dir(dir).unwrap();
+                PathIndexEntry {
+                    timestamp: days_ago(age as u64),
+                    path: dir.clone(),
+                    count: 1,
+                    pinned: false,
+                    weight: 0,
+                    hits: 0,
+                }
+            })
+            .collect::<Vec<_>>();
+        let index = open_temporary_index(
+            IndexBuilder::new()
+                .max_entries(1)
+                .ignore_patterns(&["**/node_modules".to_owned()]),
+        );
+
+        let summary = index.import_with(&entries, ConflictResolution::KeepNewer);
+
+        assert_eq!(
+            summary.unwrap(),
+            ImportSummary {
+                added: 2,
+                ignored: 1,
+                ..Default::default()
+            }
+        );
+        // Only the most recent entry fits
+        let paths = index.list().unwrap().into_iter().map(|e| e.path);
+        assert_eq!(paths.collect::<Vec<_>>(), vec![normalize_path(&dirs[0])]);
+        // The first undo brings back the evicted entry, the second one removes the import
+        assert!(index.undo().unwrap().is_some());
+        assert_eq!(index.list().unwrap().len(), 2);
+        assert!(index.undo().unwrap().is_some());
+        assert!(index.list().unwrap().is_empty());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_import_with_conflict_resolution() {
+        let input_dirs = [tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
+        let record = |timestamp, count| PathRecord {
+            timestamp,
+            count,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+        let (five_days_ago, three_days_ago, one_day_ago) = (days_ago(5), days_ago(3), days_ago(1));
+        let import = |resolution| {
+            let index = get_temporary_index();
+            insert_record(&index, input_dirs[0].path(), &record(five_days_ago, 1));
+            insert_record(&index, input_dirs[1].path(), &record(one_day_ago, 1));
+            let entries = input_dirs
+                .iter()
+                .map(|dir| PathIndexEntry {
+                    timestamp: three_days_ago,
+                    path: dir.path().to_owned(),
+                    count: 2,
+                    pinned: false,
+                    weight: 0,
+                    hits: 0,
+                })
+                .collect::<Vec<_>>();
+            let summary = index.import_with(&entries, resolution).unwrap();
+            assert!(summary.rejected.is_empty());
+            let counts = input_dirs
+                .iter()
+                .map(|dir| index.get_record(dir.path()).unwrap().unwrap().count)
+                .collect::<Vec<_>>();
+            (counts, (summary.added, summary.replaced, summary.kept))
+        };
+
+        // New paths are always added
+        assert_eq!(
+            import(ConflictResolution::KeepLocal),
+            (vec![1, 1, 2], (1, 0, 2))
+        );
+        assert_eq!(
+            import(ConflictResolution::KeepRemote),
+            (vec![2, 2, 2], (1, 2, 0))
+        );
+        assert_eq!(
+            import(ConflictResolution::KeepNewer),
+            (vec![2, 1, 2], (1, 1, 1))
+        );
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn index_replace() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let path1 = input_dir_1.path();
+        let path2 = input_dir_2.path();
+        let pattern1 = path1.file_name().unwrap().to_str().unwrap();
+        index.add(path1).unwrap();
+        let timestamp = days_ago(5);
+        let entry = |count| PathIndexEntry {
+            timestamp,
+            path: path2.to_owned(),
+            count,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+
+        let rejected = index
+            .replace(&[
+                entry(1),
+                entry(2),
+                PathIndexEntry {
+                    path: PathBuf::from("foo"),
+                    ..entry(1)
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(rejected.len(), 1);
+        // The last entry of a path wins
+        assert_eq!(index.list().unwrap(), vec![entry(2)]);
+        assert!(index.verify().unwrap().is_consistent());
+        assert_eq!(index.find_one(&[pattern1], &[], None).unwrap(), None);
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    #[test]
+    fn index_touch_existing() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let record = PathRecord {
+            timestamp: days_ago(3),
+            count: 4,
+            pinned: true,
+            weight: 0,
+            hits: 0,
+        };
+        index
+            .import(&[PathIndexEntry {
+                timestamp: record.timestamp,
+                path: input_dir.path().to_owned(),
+                count: record.count,
+                pinned: record.pinned,
+                weight: record.weight,
+                hits: record.hits,
+            }])
+            .unwrap();
+
+        index.touch(input_dir.path(), false).unwrap();
+
+        let touched = index.get_record(input_dir.path()).unwrap().unwrap();
+        assert!(touched.timestamp > record.timestamp);
+        assert_eq!((touched.count, touched.pinned), (4, true));
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_touch_not_indexed() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let name = input_dir.path().file_name().unwrap().to_str().unwrap();
+
+        let result = index.touch(input_dir.path(), false);
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<IndexError>(),
+            Some(IndexError::NotIndexed(_))
+        ));
+        assert!(!index.contains(input_dir.path()).unwrap());
+
+        index.touch(input_dir.path(), true).unwrap();
+
+        assert_eq!(
+            index.find_one(&[name], &[], None).unwrap(),
+            Some(input_dir.path().to_owned())
+        );
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_delete_empty_index() {
+        let index = get_temporary_index();
+
+        let input = PathBuf::from("foo");
+
+        assert!(index.delete(&input).is_ok());
+        assert!(!index.contains(&input).unwrap())
+    }
+
+    #[test]
+    fn index_delete_indexed_path() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
+
+        index.add(input).unwrap();
+
+        assert!(index.delete(input).is_ok());
+        assert!(!index.contains(input).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_delete_non_indexed_path() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let path1 = input_dir_1.path();
+        let path2 = input_dir_2.path();
+        let input = PathBuf::from("foo");
+
+        index.add(path1).unwrap();
+        index.add(path2).unwrap();
+
+        assert!(index.delete(&input).is_ok());
+        assert!(!index.contains(&input).unwrap());
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    #[test]
+    fn index_delete_many() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let input_dir_3 = tempdir().unwrap();
+        let path1 = input_dir_1.path();
+        let path2 = input_dir_2.path();
+        let path3 = input_dir_3.path();
+        index
+            .add_many(&[path1.into(), path2.into(), path3.into()])
+            .unwrap();
+
+        let removed = index
+            .delete_many(&[path1, Path::new("/foo"), path3, path1])
+            .unwrap();
+
+        assert_eq!(removed, 2);
+        assert!(!index.contains(path1).unwrap());
+        assert!(index.contains(path2).unwrap());
+        assert!(!index.contains(path3).unwrap());
+        assert_eq!(
+            index.read_fst(DELTA_KEY).unwrap().stream().into_bytes(),
+            vec![path_to_bytes(path2).into_owned()]
+        );
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap();
+        input_dir_3.close().unwrap()
+    }
+
+    #[test]
+    fn index_delete_many_empty() {
+        let index = get_temporary_index();
+
+        assert_eq!(index.delete_many(&[]).unwrap(), 0);
+        assert_eq!(index.delete_many(&[Path::new("/foo")]).unwrap(), 0)
+    }
+
+    #[test]
+    fn index_clean() {
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let dirs = ["kept", "removed", "pinned"]
+            .iter()
+            .map(|name| {
+                let dir = root.path().join(name);
+                fs::create_dir(&dir).unwrap();
+                index.add(&dir).unwrap();
+                dir
+            })
+            .collect::<Vec<_>>();
+        index.pin(&dirs[2]).unwrap();
+        fs::remove_dir(&dirs[1]).unwrap();
+        fs::remove_dir(&dirs[2]).unwrap();
+
+        let removed = index.clean(false).unwrap();
+
+        assert_eq!(removed, vec![dirs[1].clone()]);
+        assert!(index.contains(&dirs[0]).unwrap());
+        assert!(!index.contains(&dirs[1]).unwrap());
+        assert!(index.contains(&dirs[2]).unwrap());
+        assert!(index.find_all(&["removed"], &[]).unwrap().is_empty());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_add_ignored() {
+        let root = tempdir().unwrap();
+        let ignored = root.path().join("node_modules").join("foo");
+        fs::create_dir_all(&ignored).unwrap();
+        let index = open_temporary_index(
+            IndexBuilder::new().ignore_patterns(&["**/node_modules".to_owned()]),
+        );
+
+        index.add(&ignored).unwrap();
+        index.add(root.path()).unwrap();
+        let summary = index
+            .add_many(&[ignored.clone(), ignored.parent().unwrap().to_path_buf()])
+            .unwrap();
+
+        assert_eq!(
+            summary,
+            AddSummary {
+                ignored: 2,
+                ..Default::default()
+            }
+        );
+        assert!(!index.contains(&ignored).unwrap());
+        assert!(!index.contains(ignored.parent().unwrap()).unwrap());
+        assert!(index.contains(root.path()).unwrap());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_ignore_patterns_component_aware() {
+        let index = open_temporary_index(
+            IndexBuilder::new()
+                .ignore_patterns(&["**/target/debug".to_owned(), "/tmp/*".to_owned()]),
+        );
+
+        assert!(index.is_ignored(Path::new("/src/foo/target/debug")));
+        assert!(index.is_ignored(Path::new("/src/foo/target/debug/deps")));
+        assert!(!index.is_ignored(Path::new("/src/foo/target/debugger")));
+        assert!(!index.is_ignored(Path::new("/src/foo/target")));
+        assert!(index.is_ignored(Path::new("/tmp/foo/bar")));
+        assert!(!index.is_ignored(Path::new("/tmpfoo")));
+        assert!(!get_temporary_index().is_ignored(Path::new("/tmp/foo")))
+    }
+
+    #[test]
+    fn index_ignore_patterns_invalid() {
+        assert!(IndexBuilder::new()
+            .ignore_patterns(&["foo/[".to_owned()])
+            .open_config(sled::Config::new().temporary(true))
+            .is_err())
+    }
+
+    #[test]
+    fn index_clean_apply_ignores() {
+        let root = tempdir().unwrap();
+        let ignored = root.path().join(".git");
+        let pinned = root.path().join("pinned").join(".git");
+        fs::create_dir_all(&ignored).unwrap();
+        fs::create_dir_all(&pinned).unwrap();
+        let mut index = get_temporary_index();
+        index
+            .add_many(&[root.path().to_path_buf(), ignored.clone(), pinned.clone()])
+            .unwrap();
+        index.pin(&pinned).unwrap();
+        index.ignore = build_ignore_set(&["**/.git".to_owned()]).unwrap();
+
+        assert!(index.clean(false).unwrap().is_empty());
+        assert_eq!(index.clean(true).unwrap(), vec![ignored.clone()]);
+        assert!(index.contains(root.path()).unwrap());
+        assert!(!index.contains(&ignored).unwrap());
+        assert!(index.contains(&pinned).unwrap());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_all_words_any_order() {
+        let index = get_temporary_index();
+        let paths_fst = Set::from_iter(vec![
+            "/home/user/projects/rust-book",
+            "/home/user/rust/projects",
+            "/home/user/src/bar",
+        ])
+        .unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
+        let both = vec![
+            PathBuf::from("/home/user/projects/rust-book"),
+            PathBuf::from("/home/user/rust/projects"),
+        ];
+
+        assert_eq!(index.find_all(&["pro rust"], &[]).unwrap(), both);
+        assert_eq!(index.find_all(&["rust", "pro"], &[]).unwrap(), both);
+        assert_eq!(
+            index.find_all(&["  rst  ", "bk"], &[]).unwrap(),
+            vec![PathBuf::from("/home/user/projects/rust-book")]
+        );
+        assert!(index.find_all(&["pro bar"], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_find_one_words_scored_separately() {
+        let index = get_temporary_index();
+        let paths_fst =
+            Set::from_iter(vec!["/home/user/projects/rust-book", "/home/user/rst"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
+
+        let scores = index.rank(&["proj rust"], &[], None).unwrap();
+
+        assert_eq!(scores.len(), 1);
+        assert!(scores[0].score > 0);
+        // Upper case words match regardless of case, in any order
+        assert_eq!(
+            index.find_one(&["PROJECTS", "Rust"], &[], None).unwrap(),
+            Some(PathBuf::from("/home/user/projects/rust-book"))
+        );
+        assert_eq!(
+            index.find_one(&["RUST", "PROJECTS"], &[], None).unwrap(),
+            Some(PathBuf::from("/home/user/projects/rust-book"))
+        )
+    }
+
+    #[test]
+    fn index_find_one_empty_terms() {
+        let index = get_temporary_index();
+        index.add(&std::env::temp_dir()).unwrap();
+
+        assert!(index.find_one(&[], &[], None).unwrap().is_none());
+        assert!(index.find_one(&["", ""], &[], None).unwrap().is_none())
+    }
+
+    #[test]
+    fn index_expire() {
+        let index = get_temporary_index();
+        for (path, days, pinned) in [
+            ("/old", 200, false),
+            ("/new", 10, false),
+            ("/pin", 200, true),
+        ] {
+            insert_record(
+                &index,
+                Path::new(path),
+                &PathRecord {
+                    timestamp: days_ago(days),
+                    count: 1,
+                    pinned,
+                    weight: 0,
+                    hits: 0,
+                },
+            );
+        }
+        index
+            .insert_into_paths_index(Set::from_iter(vec!["/new", "/old", "/pin"]).unwrap())
+            .unwrap();
+
+        let expired = index
+            .expire(Duration::from_secs(180 * 24 * 60 * 60))
+            .unwrap();
+
+        assert_eq!(expired, vec![PathBuf::from("/old")]);
+        assert!(!index.contains(Path::new("/old")).unwrap());
+        assert!(index.contains(Path::new("/new")).unwrap());
+        assert!(index.contains(Path::new("/pin")).unwrap());
+        assert!(index.find_all(&["old"], &[]).unwrap().is_empty())
+    }
+
+    #[test]
+    fn index_add_expires_once_per_interval() {
+        let index =
+            open_temporary_index(IndexBuilder::new().max_age(Duration::from_secs(24 * 60 * 60)));
+        let old = PathBuf::from("/old");
+        let old_record = PathRecord {
+            timestamp: days_ago(2),
+            count: 1,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+        insert_record(&index, &old, &old_record);
+        let input_dir = tempdir().unwrap();
+
+        index.add(input_dir.path()).unwrap();
+        assert!(!index.contains(&old).unwrap());
+        assert!(index.contains(input_dir.path()).unwrap());
+        assert!(index.main.get(LAST_EXPIRY_KEY).unwrap().is_some());
+
+        // The next run is only due after EXPIRY_INTERVAL
+        insert_record(&index, &old, &old_record);
+        index.add(input_dir.path()).unwrap();
+        assert!(index.contains(&old).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_add_no_expiry_by_default() {
+        let index = get_temporary_index();
+        let old = PathBuf::from("/old");
+        insert_record(
+            &index,
+            &old,
+            &PathRecord {
+                timestamp: days_ago(10_000),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        let input_dir = tempdir().unwrap();
+
+        index.add(input_dir.path()).unwrap();
+
+        assert!(index.contains(&old).unwrap());
+        assert!(index.main.get(LAST_EXPIRY_KEY).unwrap().is_none());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_evict_to() {
+        let index = get_temporary_index();
+        for (path, days, count, pinned) in [
+            ("/stale", 100, 1, false),
+            ("/frequent", 100, 50, false),
+            ("/recent", 1, 1, false),
+            ("/older", 2, 1, false),
+            ("/pinned", 300, 1, true),
+        ] {
+            insert_record(
+                &index,
+                Path::new(path),
+                &PathRecord {
+                    timestamp: days_ago(days),
+                    count,
+                    pinned,
+                    weight: 0,
+                    hits: 0,
+                },
+            );
+        }
+
+        assert_eq!(index.evict_to(5).unwrap(), 0);
+        assert_eq!(index.evict_to(3).unwrap(), 2);
+
+        let mut remaining = index
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            vec![
+                PathBuf::from("/frequent"),
+                PathBuf::from("/pinned"),
+                PathBuf::from("/recent")
+            ]
+        );
+
+        // Pinned paths are kept even if that exceeds the limit
+        assert_eq!(index.evict_to(0).unwrap(), 2);
+        assert!(index.contains(Path::new("/pinned")).unwrap())
+    }
+
+    #[test]
+    fn index_add_past_max_entries() {
+        let index = open_temporary_index(IndexBuilder::new().max_entries(2));
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
+        insert_record(
+            &index,
+            input_dirs[0].path(),
+            &PathRecord {
+                timestamp: days_ago(10),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        index.add(input_dirs[1].path()).unwrap();
+        index.add(input_dirs[2].path()).unwrap();
+
+        assert!(!index.contains(input_dirs[0].path()).unwrap());
+        assert!(index.contains(input_dirs[1].path()).unwrap());
+        assert!(index.contains(input_dirs[2].path()).unwrap());
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn index_add_to_full_index_keeps_new_path() {
+        let index = open_temporary_index(IndexBuilder::new().max_entries(2));
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
+        for (dir, days) in input_dirs.iter().zip([2, 1]) {
+            let record = PathRecord {
+                timestamp: days_ago(days),
+                count: 20,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            };
+            insert_record(&index, dir.path(), &record);
+        }
+
+        // The new path ranks lowest, the lowest ranked old one goes instead
+        index.add(input_dirs[2].path()).unwrap();
+
+        assert!(!index.contains(input_dirs[0].path()).unwrap());
+        assert!(index.contains(input_dirs[1].path()).unwrap());
+        assert!(index.contains(input_dirs[2].path()).unwrap());
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn index_add_past_max_entries_keeps_pinned() {
+        let index = open_temporary_index(IndexBuilder::new().max_entries(2));
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
+        index.pin(input_dirs[0].path()).unwrap();
+        index.add(input_dirs[1].path()).unwrap();
+        index.add(input_dirs[2].path()).unwrap();
+
+        // The pinned path was the least recently visited, but the unpinned one goes instead
+        assert!(index.contains(input_dirs[0].path()).unwrap());
+        assert!(!index.contains(input_dirs[1].path()).unwrap());
+        assert!(index.contains(input_dirs[2].path()).unwrap());
+
+        // Once unpinned it is evicted like any other path
+        index.unpin(input_dirs[0].path()).unwrap();
+        assert_eq!(index.evict_to(1).unwrap(), 1);
+        assert!(!index.contains(input_dirs[0].path()).unwrap());
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn index_clear() {
+        let index = open_temporary_index(IndexBuilder::new().max_age(Duration::from_secs(60)));
+        let input_dir = tempdir().unwrap();
+        index.add(input_dir.path()).unwrap();
+
+        index.record_jump(Path::new("/"), input_dir.path()).unwrap();
+
+        index.clear().unwrap();
+
+        assert!(index.list().unwrap().is_empty());
+        assert!(index.history().unwrap().is_empty());
+        assert!(index.main.get(INDEX_KEY).unwrap().is_none());
+        assert!(index.main.get(LAST_EXPIRY_KEY).unwrap().is_none());
+        assert_eq!(index.read_schema_version().unwrap(), Some(SCHEMA_VERSION));
+        assert!(index.find_all(&["tmp"], &[]).unwrap().is_empty());
+        index.add(input_dir.path()).unwrap();
+        assert!(index.contains(input_dir.path()).unwrap());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_record_jump() {
+        let index = get_temporary_index();
+        assert_eq!(index.previous_location().unwrap(), None);
+
+        index
+            .record_jump(Path::new("/home"), Path::new("/src/project/"))
+            .unwrap();
+        index
+            .record_jump(Path::new("/src/project"), Path::new("/tmp"))
+            .unwrap();
+
+        let history = index.history().unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|jump| (jump.from.as_path(), jump.to.as_path()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("/src/project"), Path::new("/tmp")),
+                (Path::new("/home"), Path::new("/src/project")),
+            ]
+        );
+        assert!(history[0].timestamp >= history[1].timestamp);
+        assert_eq!(
+            index.previous_location().unwrap(),
+            Some(PathBuf::from("/src/project"))
+        )
+    }
+
+    #[test]
+    fn index_record_jump_forgets_oldest() {
+        let index = get_temporary_index();
+
+        for i in 0..=HISTORY_MAX_LEN {
+            index
+                .record_jump(Path::new("/"), Path::new(&format!("/{}", i)))
+                .unwrap();
+        }
+
+        let history = index.history().unwrap();
+        assert_eq!(history.len(), HISTORY_MAX_LEN);
+        assert_eq!(
+            history[0].to,
+            PathBuf::from(format!("/{}", HISTORY_MAX_LEN))
+        );
+        assert_eq!(history[HISTORY_MAX_LEN - 1].to, PathBuf::from("/1"))
+    }
+
+    // Stores a record for each of the paths and indexes them, the paths don't have to exist
+    fn insert_indexed_records(index: &Index, paths: &[&str]) -> PathRecord {
+        let record = PathRecord {
+            timestamp: days_ago(2),
+            count: 7,
+            pinned: false,
+            weight: 3,
+            hits: 1,
+        };
+        for path in paths {
+            insert_record(index, Path::new(path), &record);
+        }
+        index.rebuild_fst().unwrap();
+        record
+    }
+
+    #[test]
+    fn index_undo_delete() {
+        let index = get_temporary_index();
+        let path = PathBuf::from("/src/project");
+        let record = insert_indexed_records(&index, &["/src/project"]);
+
+        index.delete(&path).unwrap();
+        assert!(index.find_all(&["project"], &[]).unwrap().is_empty());
+
+        let report = index.undo().unwrap().unwrap();
+        assert_eq!(report.operation, JournalOperation::Delete);
+        assert_eq!(report.restored, vec![path.clone()]);
+        assert!(report.removed.is_empty());
+        assert_eq!(index.get_record(&path).unwrap(), Some(record));
+        assert_eq!(index.find_all(&["project"], &[]).unwrap(), vec![path]);
+        assert_eq!(index.undo().unwrap(), None)
+    }
+
+    #[test]
+    fn index_undo_add() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        index.add(input_dir.path()).unwrap();
+        // Visiting a known path is not journaled
+        index.add(input_dir.path()).unwrap();
+
+        let report = index.undo().unwrap().unwrap();
+        assert_eq!(report.operation, JournalOperation::Add);
+        assert_eq!(report.removed, vec![normalize_path(input_dir.path())]);
+        assert!(!index.contains(input_dir.path()).unwrap());
+        assert!(index.find_all(&["tmp"], &[]).unwrap().is_empty());
+        assert_eq!(index.undo().unwrap(), None);
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_undo_clear() {
+        let index = get_temporary_index();
+        insert_indexed_records(&index, &["/a/foo", "/b/foo"]);
+        let entries = index.list().unwrap();
+
+        index.clear().unwrap();
+        let report = index.undo().unwrap().unwrap();
+
+        assert_eq!(report.operation, JournalOperation::Clear);
+        assert_eq!(report.restored.len(), 2);
+        assert_eq!(index.list().unwrap(), entries);
+        assert_eq!(index.find_all(&["foo"], &[]).unwrap().len(), 2)
+    }
+
+    #[test]
+    fn index_undo_prune() {
+        let index = get_temporary_index();
+        insert_indexed_records(&index, &["/does/not/exist", "/does/not/exist/either"]);
+        let entries = index.list().unwrap();
+
+        assert_eq!(index.clean(false).unwrap().len(), 2);
+        let report = index.undo().unwrap().unwrap();
+
+        assert_eq!(report.operation, JournalOperation::Prune);
+        assert_eq!(index.list().unwrap(), entries)
+    }
+
+    #[test]
+    fn index_journal_forgets_oldest() {
+        let index = get_temporary_index();
+        for i in 0..=JOURNAL_MAX_LEN {
+            let path = format!("/{}", i);
+            insert_indexed_records(&index, &[&path]);
+            index.delete(Path::new(&path)).unwrap();
+        }
+
+        assert_eq!(index.journal.len(), JOURNAL_MAX_LEN);
+        for _ in 0..JOURNAL_MAX_LEN {
+            index.undo().unwrap().unwrap();
+        }
+        assert_eq!(index.undo().unwrap(), None);
+        // The first delete was forgotten
+        assert!(!index.contains(Path::new("/0")).unwrap());
+        assert_eq!(index.list().unwrap().len(), JOURNAL_MAX_LEN)
+    }
+
+    #[test]
+    fn index_rename() {
+        let index = get_temporary_index();
+        let old = PathBuf::from("/old/project");
+        let record = PathRecord {
+            timestamp: days_ago(3),
+            count: 7,
+            pinned: true,
+            weight: 5,
+            hits: 0,
+        };
+        insert_record(&index, &old, &record);
+        index
+            .insert_into_paths_index(Set::from_iter(vec![path_to_bytes(&old)]).unwrap())
+            .unwrap();
+        let new_dir = tempdir().unwrap();
+
+        index.rename(&old, new_dir.path()).unwrap();
+
+        assert!(!index.contains(&old).unwrap());
+        assert_eq!(index.get_record(new_dir.path()).unwrap(), Some(record));
+        assert!(index.find_all(&["project"], &[]).unwrap().is_empty());
+        assert_eq!(
+            index
+                .find_all(&[new_dir.path().to_str().unwrap()], &[])
+                .unwrap(),
+            vec![new_dir.path().to_owned()]
+        );
+        new_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_rename_overwrites_existing() {
+        let index = get_temporary_index();
+        let old = PathBuf::from("/old/project");
+        let new_dir = tempdir().unwrap();
+        insert_record(
+            &index,
+            &old,
+            &PathRecord {
+                timestamp: days_ago(3),
+                count: 7,
+                pinned: false,
+                weight: 5,
+                hits: 0,
+            },
+        );
+        index.add(new_dir.path()).unwrap();
+        let new_record = index.get_record(new_dir.path()).unwrap().unwrap();
+
+        index.rename(&old, new_dir.path()).unwrap();
+
+        assert!(!index.contains(&old).unwrap());
+        assert_eq!(
+            index.get_record(new_dir.path()).unwrap(),
+            Some(PathRecord {
+                timestamp: new_record.timestamp,
+                count: 7,
+                pinned: false,
+                weight: 5,
+                hits: 0,
+            })
+        );
+        new_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_rename_not_indexed() {
+        let index = get_temporary_index();
+        let new_dir = tempdir().unwrap();
+
+        let result = index.rename(Path::new("/old"), new_dir.path());
+
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<IndexError>(),
+            Some(&IndexError::NoResults("/old".to_owned()))
+        );
+        assert!(!index.contains(new_dir.path()).unwrap());
+        new_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_rename_prefix() {
+        let index = get_temporary_index();
+        let new_dir = tempdir().unwrap();
+        let timestamp = days_ago(3);
+        let record = |count| PathRecord {
+            timestamp,
+            count,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+        let old_paths = ["/foo", "/foo/bar", "/foo/bar/baz", "/foobar"];
+        for (count, path) in old_paths.iter().enumerate() {
+            insert_record(&index, Path::new(path), &record(count as u64));
+        }
+        index
+            .insert_into_paths_index(Set::from_iter(old_paths).unwrap())
+            .unwrap();
+
+        let moved = index
+            .rename_prefix(Path::new("/foo"), new_dir.path())
+            .unwrap();
+
+        assert_eq!(moved, 3);
+        assert_eq!(
+            index
+                .list()
+                .unwrap()
+                .iter()
+                .map(|e| &e.path)
+                .collect::<Vec<_>>(),
+            vec![
+                &PathBuf::from("/foobar"),
+                &new_dir.path().to_owned(),
+                &new_dir.path().join("bar"),
+                &new_dir.path().join("bar/baz"),
+            ]
+        );
+        assert_eq!(
+            index.get_record(&new_dir.path().join("bar/baz")).unwrap(),
+            Some(record(2))
+        );
+        assert!(index.verify().unwrap().is_consistent());
+        new_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_rename_prefix_into_itself() {
+        let index = get_temporary_index();
+        let old_dir = tempdir().unwrap();
+        let new_dir = old_dir.path().join("sub");
+        fs::create_dir(&new_dir).unwrap();
+        index.add(old_dir.path()).unwrap();
+
+        let result = index.rename_prefix(old_dir.path(), &new_dir);
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<IndexError>(),
+            Some(IndexError::MoveIntoSelf(_))
+        ));
+        assert!(index.contains(old_dir.path()).unwrap());
+        old_dir.close().unwrap()
+    }
+
+    // Indexes a directory, a symlink to it and a dangling symlink, returns the real path of
+    // the directory and the symlink
+    #[cfg(unix)]
+    fn index_symlinked_dir(index: &Index, root: &Path) -> (PathBuf, PathBuf) {
+        let real = root.join("real");
+        let link = root.join("link");
+        fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let dangling = root.join("dangling");
+        fs::create_dir(root.join("missing")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), &dangling).unwrap();
+        let entry = |path: &Path, count, pinned| PathIndexEntry {
+            timestamp: days_ago(count),
+            path: path.to_owned(),
+            count,
+            pinned,
+            weight: 0,
+            hits: 0,
+        };
+        index
+            .import(&[
+                entry(&real, 2, false),
+                entry(&link, 5, true),
+                entry(&dangling, 1, false),
+            ])
+            .unwrap();
+        fs::remove_dir(root.join("missing")).unwrap();
+        (fs::canonicalize(&real).unwrap(), link)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn index_dedupe_canonical() {
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let (real, link) = index_symlinked_dir(&index, root.path());
+
+        let groups = index.dedupe(DedupePreference::Canonical).unwrap();
+
+        assert_eq!(
+            groups,
+            vec![DuplicateGroup {
+                kept: real.clone(),
+                merged: vec![link.clone()],
+            }]
+        );
+        let record = index.get_record(&real).unwrap().unwrap();
+        assert_eq!((record.count, record.pinned), (7, true));
+        assert!(!index.contains(&link).unwrap());
+        assert!(index.contains(&root.path().join("dangling")).unwrap());
+        assert!(index.verify().unwrap().is_consistent());
+        root.close().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn index_dedupe_most_used() {
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let (real, link) = index_symlinked_dir(&index, root.path());
+
+        let groups = index.find_duplicates(DedupePreference::MostUsed).unwrap();
+        assert_eq!(groups[0].kept, link);
+        index.dedupe(DedupePreference::MostUsed).unwrap();
+
+        assert_eq!(index.get_record(&link).unwrap().unwrap().count, 7);
+        assert!(!index.contains(&real).unwrap());
+        assert!(index.verify().unwrap().is_consistent());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_stats() {
+        let index = get_temporary_index();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+
+        assert_eq!(index.stats().unwrap().paths, 0);
+        index.add(input_dir_1.path()).unwrap();
+        index.add(input_dir_2.path()).unwrap();
+
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.paths, 2);
+        assert!(stats.fst_bytes > 0);
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    #[test]
+    fn index_forget() {
+        let index = get_temporary_index();
+        let paths = ["/foo", "/foo/bar", "/foo/bar/baz", "/foobar"];
+        for path in paths {
+            insert_record(
+                &index,
+                Path::new(path),
+                &PathRecord {
+                    timestamp: days_ago(1),
+                    count: 1,
+                    pinned: path == "/foo/bar",
+                    weight: 0,
+                    hits: 0,
+                },
+            );
+        }
+        index
+            .insert_into_paths_index(Set::from_iter(paths).unwrap())
+            .unwrap();
+
+        assert_eq!(
+            index.list_below(Path::new("/foo/bar/")).unwrap(),
+            vec![PathBuf::from("/foo/bar"), PathBuf::from("/foo/bar/baz")]
+        );
+        let forgotten = index.forget(Path::new("/foo")).unwrap();
+
+        assert_eq!(forgotten.len(), 3);
+        assert_eq!(index.list().unwrap()[0].path, PathBuf::from("/foobar"));
+        assert_eq!(index.list().unwrap().len(), 1);
+        assert!(index.verify().unwrap().is_consistent())
+    }
+
+    #[test]
+    fn index_open_new_database() {
+        let index = get_temporary_index();
+
+        assert_eq!(index.read_schema_version().unwrap(), Some(SCHEMA_VERSION))
+    }
+
+    #[test]
+    fn index_migrate_bare_timestamps() {
+        let index = get_temporary_index();
+        let timestamp = days_ago(1);
+        index
+            .paths
+            .insert("/old", bincode::serialize(&timestamp).unwrap())
+            .unwrap();
+        index.main.remove(SCHEMA_VERSION_KEY).unwrap();
+
+        index.migrate().unwrap();
+
+        let expected = PathRecord {
+            timestamp,
+            count: 1,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+        assert_eq!(
+            index.paths.get("/old").unwrap().unwrap().as_ref(),
+            expected.to_bytes().unwrap().as_slice()
+        );
+        assert_eq!(index.read_schema_version().unwrap(), Some(SCHEMA_VERSION))
+    }
+
+    #[test]
+    fn index_migrate_current_schema_untouched() {
+        let index = get_temporary_index();
+        let timestamp = days_ago(1);
+        let bare_timestamp = bincode::serialize(&timestamp).unwrap();
+        index.paths.insert("/old", bare_timestamp.clone()).unwrap();
+
+        index.migrate().unwrap();
+
+        assert_eq!(
+            index.paths.get("/old").unwrap().unwrap().as_ref(),
+            bare_timestamp.as_slice()
+        )
+    }
+
+    #[test]
+    fn index_migrate_newer_schema() {
+        let index = get_temporary_index();
+        index.write_schema_version(SCHEMA_VERSION + 1).unwrap();
+
+        let result = index.migrate();
+
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<IndexError>(),
+            Some(&IndexError::UnsupportedSchema(SCHEMA_VERSION + 1))
+        )
+    }
+
+    // Writes a database on disk like a version of scotty before the schema was versioned did
+    fn write_unversioned_database(db_path: &Path, timestamp: SystemTime) {
+        let db = sled::open(db_path).unwrap();
+        db.open_tree(PATHS_TREE)
+            .unwrap()
+            .insert("/old", bincode::serialize(&timestamp).unwrap())
+            .unwrap();
+        db.open_tree(MAIN_TREE).unwrap();
+        db.flush().unwrap();
+    }
+
+    #[test]
+    fn index_open_migrates_old_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        let timestamp = days_ago(1);
+        write_unversioned_database(&db_path, timestamp);
+
+        let index = Index::open_path(&db_path).unwrap();
+
+        assert_eq!(index.read_schema_version().unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(
+            index.list().unwrap(),
+            vec![PathIndexEntry {
+                timestamp,
+                path: PathBuf::from("/old"),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            }]
+        );
+        drop(index);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_open_newer_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        let index = Index::open_path(&db_path).unwrap();
+        index.write_schema_version(SCHEMA_VERSION + 1).unwrap();
+        index.paths.insert("/new", "future record").unwrap();
+        drop(index);
+
+        let error = Index::open_path(&db_path).err().unwrap();
+
+        assert_eq!(
+            error.downcast_ref::<IndexError>(),
+            Some(&IndexError::UnsupportedSchema(SCHEMA_VERSION + 1))
+        );
+        assert!(error.to_string().contains("upgrade scotty"));
+        // The data is left alone
+        let db = sled::open(&db_path).unwrap();
+        assert_eq!(
+            db.open_tree(PATHS_TREE)
+                .unwrap()
+                .get("/new")
+                .unwrap()
+                .unwrap()
+                .as_ref(),
+            b"future record"
+        );
+        drop(db);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn incompatible_backup_path_unique() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+
+        assert_eq!(
+            incompatible_backup_path(&db_path),
+            dir.path().join("scotty.db.incompatible")
+        );
+        fs::create_dir(dir.path().join("scotty.db.incompatible")).unwrap();
+        assert_eq!(
+            incompatible_backup_path(&db_path),
+            dir.path().join("scotty.db.incompatible.1")
+        );
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_open_locked_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        let index = Index::open_path(&db_path).unwrap();
+
+        let error = Index::open_path(&db_path).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<IndexError>(),
+            Some(IndexError::DatabaseLocked(_))
+        ));
+
+        drop(index);
+        assert!(Index::open_path(&db_path).is_ok());
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_compact_path() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        let index = Index::open_path(&db_path).unwrap();
+        index.add(dir.path()).unwrap();
+        index.pin(dir.path()).unwrap();
+        let entries = index.list().unwrap();
+        drop(index);
+
+        let report = Index::compact_path(&db_path).unwrap();
+
+        assert!(report.after_bytes > 0);
+        let index = Index::open_path(&db_path).unwrap();
+        assert_eq!(index.list().unwrap(), entries);
+        assert!(!sibling_path(&db_path, COMPACTING_SUFFIX).exists());
+        assert!(!sibling_path(&db_path, COMPACTED_SUFFIX).exists());
+        drop(index);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn compact_report_reclaimed_bytes() {
+        let report = |before_bytes, after_bytes| CompactReport {
+            before_bytes,
+            after_bytes,
+        };
+
+        assert_eq!(report(100, 40).reclaimed_bytes(), 60);
+        assert_eq!(report(40, 100).reclaimed_bytes(), 0)
+    }
+
+    #[test]
+    fn recover_compaction_restores_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        let index = Index::open_path(&db_path).unwrap();
+        index.add(dir.path()).unwrap();
+        drop(index);
+        // Interrupted between moving the database aside and moving the copy in place
+        fs::rename(&db_path, sibling_path(&db_path, COMPACTED_SUFFIX)).unwrap();
+        fs::create_dir(sibling_path(&db_path, COMPACTING_SUFFIX)).unwrap();
+
+        let index = Index::open_path(&db_path).unwrap();
+
+        assert_eq!(index.list().unwrap().len(), 1);
+        assert!(!sibling_path(&db_path, COMPACTING_SUFFIX).exists());
+        assert!(!sibling_path(&db_path, COMPACTED_SUFFIX).exists());
+        drop(index);
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn recover_compaction_removes_old_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        fs::create_dir(&db_path).unwrap();
+        fs::create_dir(sibling_path(&db_path, COMPACTED_SUFFIX)).unwrap();
+
+        recover_compaction(&db_path).unwrap();
+
+        assert!(db_path.exists());
+        assert!(!sibling_path(&db_path, COMPACTED_SUFFIX).exists());
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_verify_consistent() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        index.add(input_dir.path()).unwrap();
+
+        assert!(index.verify().unwrap().is_consistent());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_verify_and_rebuild() {
+        let index = get_temporary_index();
+        insert_record(
+            &index,
+            Path::new("/stored"),
+            &PathRecord {
+                timestamp: days_ago(1),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        index
+            .insert_into_paths_index(Set::from_iter(vec!["/a", "/indexed"]).unwrap())
+            .unwrap();
+        index.paths.insert("/a", vec![]).unwrap();
+        index.paths.insert("/z", vec![]).unwrap();
+
+        assert_eq!(
+            index.verify().unwrap(),
+            VerifyReport {
+                missing_from_fst: vec![PathBuf::from("/stored"), PathBuf::from("/z")],
+                missing_from_paths: vec![PathBuf::from("/indexed")],
+                invalid_paths: vec![],
+            }
+        );
+
+        assert_eq!(index.rebuild_fst().unwrap(), 3);
+
+        assert!(index.verify().unwrap().is_consistent());
+        assert!(index.main.get(DELTA_KEY).unwrap().is_none());
+        assert_eq!(index.read_fst(INDEX_KEY).unwrap().len(), 3)
+    }
+
+    #[test]
+    fn index_verify_invalid_paths() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        index.add(input_dir.path()).unwrap();
+        index.paths.insert("relative", vec![]).unwrap();
+        index
+            .insert_into_paths_index(Set::from_iter(vec!["relative"]).unwrap())
+            .unwrap();
+
+        let report = index.verify().unwrap();
+
+        assert_eq!(report.invalid_paths, vec![PathBuf::from("relative")]);
+        assert!(report.missing_from_fst.is_empty());
+        assert!(!report.is_consistent());
+
+        // The invalid path is not counted
+        assert_eq!(index.rebuild_fst().unwrap(), 1);
+
+        assert!(index.verify().unwrap().is_consistent());
+        assert!(!index.contains(Path::new("relative")).unwrap());
+        assert_eq!(
+            index.read_stored_paths().unwrap(),
+            vec![path_to_bytes(input_dir.path()).to_vec()]
+        );
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_transaction_interrupted() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let path_fst = Set::from_iter(vec![path_to_bytes(input_dir.path())]).unwrap();
+
+        // Fail after the path is stored, but before the fst is updated
+        let result: Result<()> = index.transaction(|paths, _| {
+            index.upsert_record(paths, input_dir.path(), SystemTime::now())?;
+            Err(abort(anyhow::anyhow!("interrupted")))
+        });
+
+        assert!(result.is_err());
+        assert!(!index.contains(input_dir.path()).unwrap());
+        assert!(index.verify().unwrap().is_consistent());
+
+        // Fail after the fst is updated, but before the path is removed
+        index.add(input_dir.path()).unwrap();
+        let result: Result<()> = index.transaction(|_, main| {
+            remove_from_fsts(main, &path_fst)?;
+            Err(abort(anyhow::anyhow!("interrupted")))
+        });
+
+        assert!(result.is_err());
+        assert!(index.contains(input_dir.path()).unwrap());
+        assert!(index.verify().unwrap().is_consistent());
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_writes_rebuild_corrupt_fst() {
+        let index = get_temporary_index();
+        let stored = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        index.add(stored.path()).unwrap();
+        // Every write to the fsts reads the delta fst
+        let corrupt = || index.main.insert(DELTA_KEY, "garbage").unwrap();
+
+        corrupt();
+        index.add(new.path()).unwrap();
+        assert!(index.contains(new.path()).unwrap());
+        corrupt();
+        index.delete_many(&[new.path()]).unwrap();
+        assert!(!index.contains(new.path()).unwrap());
+        corrupt();
+        index.add_many(&[new.path().to_owned()]).unwrap();
+        corrupt();
+        index.delete(stored.path()).unwrap();
+
+        assert!(index.verify().unwrap().is_consistent());
+        assert_eq!(
+            index.find_all(&["/"], &[]).unwrap(),
+            vec![new.path().to_owned()]
+        );
+        stored.close().unwrap();
+        new.close().unwrap()
+    }
+
+    #[test]
+    fn index_rebuild_fst_after_corruption() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let pattern = input_dir.path().file_name().unwrap().to_str().unwrap();
+        index.add(input_dir.path()).unwrap();
+        index.main.clear().unwrap();
+
+        assert!(index.find_one(&[pattern], &[], None).unwrap().is_none());
+
+        index.rebuild_fst().unwrap();
+
+        assert_eq!(
+            index.find_one(&[pattern], &[], None).unwrap(),
+            Some(input_dir.path().to_owned())
+        );
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_rebuild_fst_after_garbage() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let pattern = input_dir.path().file_name().unwrap().to_str().unwrap();
+        index.add(input_dir.path()).unwrap();
+        index.main.insert(INDEX_KEY, "garbage").unwrap();
+
+        assert!(index.read_stored_fst(INDEX_KEY).is_err());
+        // The search rebuilds the corrupt fst
+        assert_eq!(
+            index.find_one(&[pattern], &[], None).unwrap(),
+            Some(input_dir.path().to_owned())
+        );
+        assert_eq!(index.read_stored_fst(INDEX_KEY).unwrap().len(), 1);
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_concurrent_adds() {
+        let index = std::sync::Arc::new(get_temporary_index());
+        let input_dirs = (0..40).map(|_| tempdir().unwrap()).collect::<Vec<_>>();
+        let paths = input_dirs
+            .iter()
+            .map(|dir| dir.path().to_owned())
+            .collect::<Vec<_>>();
+
+        std::thread::scope(|scope| {
+            for chunk in paths.chunks(20) {
+                let index = index.clone();
+                scope.spawn(move || {
+                    for path in chunk {
+                        index.add(path).unwrap();
+                    }
+                });
+            }
+        });
+
+        for path in &paths {
+            assert!(index.contains(path).unwrap());
+        }
+        assert_eq!(index.read_fst(DELTA_KEY).unwrap().len(), paths.len());
+        assert!(index.verify().unwrap().is_consistent());
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn index_open_waits_for_other_process() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("scotty.db");
+        let first = Index::open_path(&db_path).unwrap();
+
+        let second = std::thread::spawn({
+            let db_path = db_path.clone();
+            move || Index::open_path(&db_path).map(|_| ())
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!second.is_finished());
+        drop(first);
+
+        second.join().unwrap().unwrap();
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_one_empty_index() {
+        let index = get_temporary_index();
+        let pattern = "abcd";
+
+        assert!(index.find_one(&[pattern], &[], None).unwrap().is_none())
+    }
+    #[test]
+    fn index_find_one_empty_index_empty_pattern() {
+        let index = get_temporary_index();
+        let pattern = "";
+
+        assert!(index.find_one(&[pattern], &[], None).unwrap().is_none())
+    }
+
+    #[test]
+    fn index_find_one_non_empty_index_found() {
+        let index = get_temporary_index();
+        let indexed_dir = tempdir().unwrap();
+        let path_buf = indexed_dir.path();
+        let pattern = path_buf.file_name().unwrap().to_str().unwrap();
+
+        index.add(path_buf).unwrap();
+
+        assert_eq!(
+            index.find_one(&[pattern], &[], None).unwrap(),
+            Some(PathBuf::from(path_buf))
+        );
+
+        indexed_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_one_non_empty_index_not_found() {
+        let index = get_temporary_index();
+        let indexed_dir = tempdir().unwrap();
+        let path_buf = indexed_dir.path();
+        let pattern = "#!";
+
+        index.add(path_buf).unwrap();
+
+        assert!(index.find_one(&[pattern], &[], None).unwrap().is_none());
+
+        indexed_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_one_non_empty_index_empty_pattern() {
+        let index = get_temporary_index();
+        let indexed_dir = tempdir().unwrap();
+        let path_buf = indexed_dir.path();
+        let pattern = "";
+
+        index.add(path_buf).unwrap();
+
+        assert!(index.find_one(&[pattern], &[], None).unwrap().is_none());
+
+        indexed_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_one_non_empty_index_found_excluded() {
+        let index = get_temporary_index();
+        let indexed_dir = tempdir().unwrap();
+        let path_buf = indexed_dir.path();
+        let pattern = path_buf.file_name().unwrap().to_str().unwrap();
+
+        index.add(path_buf).unwrap();
+
+        assert!(index
+            .find_one(&[pattern], &[path_buf], None)
+            .unwrap()
+            .is_none());
+
+        indexed_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_all_excludes() {
+        let index = get_temporary_index();
+        let paths = ["/src/foo", "/src/foo/bar", "/tmp/foo", "/var/foo"];
+        insert_indexed_records(&index, &paths);
+        let find_all = |exclude: &[&str]| {
+            let exclude = exclude.iter().map(Path::new).collect::<Vec<_>>();
+            index.find_all(&["foo"], &exclude).unwrap()
+        };
+        let expected = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        assert_eq!(find_all(&[]), expected(&paths));
+        assert_eq!(
+            find_all(&["/src/foo"]),
+            expected(&["/src/foo/bar", "/tmp/foo", "/var/foo"])
+        );
+        // Paths below an excluded path and paths that match nothing are not affected
+        assert_eq!(
+            find_all(&["/tmp/foo", "/src/foo", "/nowhere"]),
+            expected(&["/src/foo/bar", "/var/foo"])
+        );
+        assert_eq!(find_all(&["/nowhere"]), expected(&paths))
+    }
+
+    #[test]
+    fn index_find_one_detailed() {
+        let index = get_temporary_index();
+        let indexed_dir = tempdir().unwrap();
+        let path_buf = indexed_dir.path();
+        let pattern = path_buf.file_name().unwrap().to_str().unwrap();
+        index.add(path_buf).unwrap();
+        index.pin(path_buf).unwrap();
+
+        let result = index
+            .find_one_detailed(&[pattern], &[], None)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(result.path, path_buf);
+        assert!(result.pinned);
+        assert!(result.exact);
+        assert!(result.score > 0);
+        assert_eq!(result.count, 1);
+        assert!(result.timestamp.is_some());
+        assert!(index
+            .find_one_detailed(&[pattern], &[path_buf], None)
+            .unwrap()
+            .is_none());
+        indexed_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_find_one_except_current() {
+        let index = get_temporary_index();
+        let current_dir = std::env::current_dir().unwrap();
+        let pattern = current_dir.file_name().unwrap().to_str().unwrap();
+
+        index.add(&current_dir).unwrap();
+
+        assert_eq!(
+            index.find_one(&[pattern], &[], None).unwrap(),
+            Some(current_dir.clone())
+        );
+        assert!(index
+            .find_one_except_current(&[pattern], None)
+            .unwrap()
+            .is_none())
+    }
+
+    #[test]
+    fn index_find_all_excludes_current_and_given() {
+        let index = get_temporary_index();
+        let current_dir = std::env::current_dir().unwrap();
+        let name = current_dir.file_name().unwrap().to_str().unwrap();
+        let root = tempdir().unwrap();
+        let (sibling, other) = (root.path().join(name), root.path().join(name).join(name));
+        fs::create_dir_all(&other).unwrap();
+        for path in [&current_dir, &sibling, &other] {
+            index.add(path).unwrap();
+        }
+
+        assert_eq!(index.find_all(&[name], &[]).unwrap().len(), 3);
+        assert_eq!(
+            index
+                .find_all(&[name], &[&current_dir, &normalize_path(&sibling)])
+                .unwrap(),
+            vec![normalize_path(&other)]
+        );
+        root.close().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn index_find_one_excludes_logical_path() {
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let (real, link) = (root.path().join("realproj"), root.path().join("proj"));
+        fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        index.add(&link).unwrap();
 
-/// Merges (creates a union) between two fst::Set and returns the result as a newly allocated fst::Set
-fn merge_fst_sets<D>(paths_set: &Set<D>, delta_set: &Set<D>) -> fst::Result<Set<Vec<u8>>>
-where
-    D: AsRef<[u8]>,
-{
-    log::debug!("Merging fst set");
-    let stream = paths_set.op().add(delta_set.stream()).union();
+        // The physical path of a symlinked directory is not the one that was stored
+        let physical = fs::canonicalize(&link).unwrap();
+        assert_eq!(
+            index.find_one(&["proj"], &[&physical], None).unwrap(),
+            Some(link.clone())
+        );
+        // The logical one is left out however it is spelled
+        assert_eq!(index.find_one(&["proj"], &[&link], None).unwrap(), None);
+        assert_eq!(
+            index.find_one(&["proj"], &[&link.join(".")], None).unwrap(),
+            None
+        );
+        root.close().unwrap()
+    }
 
-    let mut paths_builder = SetBuilder::memory();
-    paths_builder.extend_stream(stream)?;
-    paths_builder.into_inner().and_then(Set::new)
-}
+    #[test]
+    fn index_add_past_delta_capacity() {
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let dirs = (0..=DELTA_MAX_LEN + 1)
+            .map(|i| {
+                let dir = root.path().join(format!("dir{}", i));
+                fs::create_dir(&dir).unwrap();
+                index.add(&dir).unwrap();
+                dir
+            })
+            .collect::<Vec<_>>();
 
-/// Removes the second fst::Set from the first and returns the result as a newly allocated fst::Set
-fn remove_fst_set<D>(paths_set: &Set<D>, delta_set: &Set<D>) -> fst::Result<Set<Vec<u8>>>
-where
-    D: AsRef<[u8]>,
-{
-    log::debug!("Removing fst set");
-    let stream = paths_set.op().add(delta_set.stream()).difference();
-    let mut paths_builder = SetBuilder::memory();
-    paths_builder.extend_stream(stream)?;
-    paths_builder.into_inner().and_then(Set::new)
-}
+        assert_eq!(index.read_fst(INDEX_KEY).unwrap().len(), DELTA_MAX_LEN + 1);
+        assert_eq!(index.read_fst(DELTA_KEY).unwrap().len(), 1);
+        assert_eq!(index.find_all(&["dir"], &[]).unwrap().len(), dirs.len());
 
-#[derive(Ord, PartialOrd, PartialEq, Eq, Debug)]
-struct Score {
-    score: i64,
-    timestamp: Option<SystemTime>,
-    path: PathBuf,
-}
+        // Delete one path from the main fst and one from the delta fst
+        index.delete(&dirs[0]).unwrap();
+        index.delete(dirs.last().unwrap()).unwrap();
+        assert!(index.read_fst(DELTA_KEY).unwrap().is_empty());
+        assert_eq!(index.find_all(&["dir"], &[]).unwrap(), {
+            let mut expected = dirs[1..dirs.len() - 1].to_vec();
+            expected.sort();
+            expected
+        });
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use tempfile::tempdir;
+        root.close().unwrap()
+    }
 
     #[test]
-    fn merge_fst_sets_both_empty() {
-        let fst1 = Set::default();
-        let fst2 = Set::default();
+    fn index_list_empty() {
+        let index = get_temporary_index();
 
-        let result = merge_fst_sets(&fst1, &fst2).unwrap();
+        let list = index.list().unwrap();
 
-        assert!(result.is_empty())
+        assert!(list.is_empty());
     }
 
     #[test]
-    fn merge_fst_sets_one_empty() {
-        let fst1 = Set::default();
-        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+    fn index_list_not_empty() {
+        let index = get_temporary_index();
 
-        let result = merge_fst_sets(&fst1, &fst2).unwrap();
+        let input_dir_1 = tempdir().unwrap();
+        let input_dir_2 = tempdir().unwrap();
+        let path1 = input_dir_1.path();
+        let path2 = input_dir_2.path();
 
-        assert_eq!(result.len(), fst2.len());
-        assert!(result.is_subset(fst2.stream()))
+        index.add(path1).unwrap();
+        index.add(path2).unwrap();
+
+        let mut list = index.list().unwrap();
+        list.sort();
+
+        // This works because we ensured the IndexEntries are sorted by timestamp first
+        assert_eq!(list[0].path, path1);
+        assert_eq!(list[1].path, path2);
+
+        input_dir_1.close().unwrap();
+        input_dir_2.close().unwrap()
+    }
+
+    fn insert_record(index: &Index, path: &Path, record: &PathRecord) {
+        index
+            .paths
+            .insert(path_to_bytes(path), record.to_bytes().unwrap())
+            .unwrap();
+    }
+
+    fn days_ago(days: u64) -> SystemTime {
+        SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60)
     }
 
     #[test]
-    fn merge_fst_sets_no_empty() {
-        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
-        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+    fn index_recent() {
+        let index = get_temporary_index();
+        let timestamp = SystemTime::now();
+        for (path, secs) in [("/c", 3), ("/b", 1), ("/d", 2), ("/a", 1)] {
+            let record = PathRecord {
+                timestamp: timestamp - Duration::from_secs(secs),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            };
+            insert_record(&index, Path::new(path), &record);
+        }
 
-        let result = merge_fst_sets(&fst1, &fst2).unwrap();
+        let paths = |count| {
+            index
+                .recent(count)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(paths(3), ["/a", "/b", "/d"].map(PathBuf::from));
+        assert_eq!(paths(10), ["/a", "/b", "/d", "/c"].map(PathBuf::from));
+        assert_eq!(paths(usize::MAX).len(), 4);
+        assert!(paths(0).is_empty())
+    }
+
+    #[test]
+    fn path_record_roundtrip() {
+        let record = PathRecord {
+            timestamp: SystemTime::now(),
+            count: 42,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+
+        let result = PathRecord::from_bytes(&record.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(result, record)
+    }
+
+    #[test]
+    fn path_record_from_legacy_timestamp() {
+        let timestamp = SystemTime::now();
+        let bytes = bincode::serialize(&timestamp).unwrap();
+
+        let result = PathRecord::from_bytes(&bytes).unwrap();
 
         assert_eq!(
-            result.stream().into_strs().unwrap(),
-            vec!["abc", "bar", "def", "foo"]
+            result,
+            PathRecord {
+                timestamp,
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            }
         )
     }
 
     #[test]
-    fn remove_fst_set_both_empty() {
-        let fst1 = Set::default();
-        let fst2 = Set::default();
+    fn path_record_frecency_halves_every_half_life() {
+        let now = SystemTime::now();
+        let half_life = Duration::from_secs(60);
+        let record = PathRecord {
+            timestamp: now - Duration::from_secs(120),
+            count: 8,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
 
-        let result = remove_fst_set(&fst1, &fst2).unwrap();
+        assert_eq!(record.frecency(now, half_life), 2.0);
+        assert_eq!(record.frecency(now, Duration::ZERO), 8.0)
+    }
 
-        assert!(result.is_empty())
+    #[test]
+    fn path_record_from_record_without_hits() {
+        let timestamp = SystemTime::now();
+        let bytes = bincode::serialize(&(timestamp, 3u64, true, 5i64)).unwrap();
+
+        let result = PathRecord::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            result,
+            PathRecord {
+                timestamp,
+                count: 3,
+                pinned: true,
+                weight: 5,
+                hits: 0,
+            }
+        )
     }
 
     #[test]
-    fn remove_fst_set_second_empty() {
-        let fst1 = Set::from_iter(vec!["bar", "foo"]).unwrap();
-        let fst2 = Set::default();
+    fn path_record_frecency_weighs_hits() {
+        let now = SystemTime::now();
+        let record = PathRecord {
+            timestamp: now,
+            count: 3,
+            pinned: false,
+            weight: 0,
+            hits: 2,
+        };
 
-        let result = remove_fst_set(&fst1, &fst2).unwrap();
+        assert_eq!(record.frecency(now, Duration::ZERO), 11.0)
+    }
 
-        assert_eq!(result.len(), fst1.len());
-        assert!(result.is_subset(fst1.stream()))
+    #[test]
+    fn path_record_frecency_future_timestamp() {
+        let now = SystemTime::now();
+        let record = PathRecord {
+            timestamp: now + Duration::from_secs(120),
+            count: 3,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        };
+
+        assert_eq!(record.frecency(now, DEFAULT_HALF_LIFE), 3.0)
     }
 
     #[test]
-    fn remove_fst_set_first_empty() {
-        let fst1 = Set::default();
-        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+    fn index_hit() {
+        let index = get_temporary_index();
+        let hit_dir = tempdir().unwrap();
+        let visited_dir = tempdir().unwrap();
+        index.add(visited_dir.path()).unwrap();
+        index.add(visited_dir.path()).unwrap();
 
-        let result = remove_fst_set(&fst1, &fst2).unwrap();
+        index.hit(hit_dir.path()).unwrap();
 
-        assert!(result.is_empty())
+        let record = index.get_record(hit_dir.path()).unwrap().unwrap();
+        assert_eq!((record.count, record.hits), (1, 1));
+        // One jump outweighs two plain visits
+        let scores = index.rank(&["tmp"], &[], None).unwrap();
+        assert_eq!(scores[0].path, hit_dir.path());
+
+        hit_dir.close().unwrap();
+        visited_dir.close().unwrap()
     }
 
     #[test]
-    fn remove_fst_set_no_empty_no_overlap() {
-        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
-        let fst2 = Set::from_iter(vec!["bar", "foo"]).unwrap();
+    fn index_add_increments_count() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
 
-        let result = remove_fst_set(&fst1, &fst2).unwrap();
+        index.add(input).unwrap();
+        index.add(input).unwrap();
 
-        assert_eq!(result.stream().into_strs().unwrap(), vec!["abc", "def"])
+        assert_eq!(index.list().unwrap()[0].count, 2);
+        input_dir.close().unwrap()
     }
 
     #[test]
-    fn remove_fst_set_no_empty_some_overlap() {
-        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
-        let fst2 = Set::from_iter(vec!["abc"]).unwrap();
+    fn index_add_debounced() {
+        let index = open_temporary_index(IndexBuilder::new().debounce(Duration::from_secs(60)));
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
 
-        let result = remove_fst_set(&fst1, &fst2).unwrap();
+        index.add(input).unwrap();
+        let first = index.get_record(input).unwrap().unwrap();
+        index.add(input).unwrap();
 
-        assert_eq!(result.stream().into_strs().unwrap(), vec!["def"])
+        assert_eq!(index.get_record(input).unwrap().unwrap(), first);
+        input_dir.close().unwrap()
     }
 
     #[test]
-    fn remove_fst_set_no_empty_full_overlap() {
-        let fst1 = Set::from_iter(vec!["abc", "def"]).unwrap();
-        let fst2 = Set::from_iter(vec!["abc", "def"]).unwrap();
+    fn index_add_after_debounce_interval() {
+        let index = open_temporary_index(IndexBuilder::new().debounce(Duration::from_secs(60)));
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
+        index.add(input).unwrap();
+        insert_record(
+            &index,
+            input,
+            &PathRecord {
+                timestamp: SystemTime::now() - Duration::from_secs(61),
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
 
-        let result = remove_fst_set(&fst1, &fst2).unwrap();
+        index.add(input).unwrap();
 
-        assert!(result.is_empty())
+        assert_eq!(index.get_record(input).unwrap().unwrap().count, 2);
+        input_dir.close().unwrap()
     }
 
     #[test]
-    fn score_result_equal_length() {
-        let input = vec!["foo".to_owned(), "bar".to_owned()];
-        let pattern = "abc";
+    fn is_recent_interval() {
+        let now = SystemTime::now();
+        let interval = Duration::from_secs(10);
+
+        assert!(is_recent(now - Duration::from_secs(9), now, interval));
+        assert!(!is_recent(now - Duration::from_secs(10), now, interval));
+        assert!(!is_recent(now, now, Duration::ZERO));
+        // A timestamp in the future (e.g. after a clock change) is not considered recent
+        assert!(!is_recent(now + Duration::from_secs(1), now, interval))
+    }
+
+    #[test]
+    fn get_best_score_stale_frequent_loses_to_recent() {
+        let index = get_temporary_index();
+        let stale = PathBuf::from("/stale");
+        let recent = PathBuf::from("/recent");
+        insert_record(
+            &index,
+            &stale,
+            &PathRecord {
+                timestamp: days_ago(180),
+                count: 50,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        insert_record(
+            &index,
+            &recent,
+            &PathRecord {
+                timestamp: days_ago(2),
+                count: 5,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        let input = vec![Score::new(stale, 20), Score::new(recent.clone(), 20)];
+
+        let result = index.get_best_score(input, None).unwrap().unwrap();
+
+        assert_eq!(result.path, recent)
+    }
+
+    #[test]
+    fn get_best_score_frequent_wins_without_decay() {
+        let index = open_temporary_index(IndexBuilder::new().half_life(Duration::ZERO));
+        let stale = PathBuf::from("/stale");
+        let recent = PathBuf::from("/recent");
+        insert_record(
+            &index,
+            &stale,
+            &PathRecord {
+                timestamp: days_ago(180),
+                count: 50,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        insert_record(
+            &index,
+            &recent,
+            &PathRecord {
+                timestamp: days_ago(2),
+                count: 5,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        let input = vec![Score::new(stale.clone(), 20), Score::new(recent, 20)];
+
+        let result = index.get_best_score(input, None).unwrap().unwrap();
+
+        assert_eq!(result.path, stale)
+    }
+
+    #[test]
+    fn index_pin_existing_path() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
+        index.add(input).unwrap();
+        index.add(input).unwrap();
+
+        index.pin(input).unwrap();
+
+        let list = index.list().unwrap();
+        assert!(list[0].pinned);
+        assert_eq!(list[0].count, 2);
+
+        // Visiting a pinned path keeps it pinned
+        index.add(input).unwrap();
+        assert!(index.list().unwrap()[0].pinned);
+
+        index.unpin(input).unwrap();
+        let list = index.list().unwrap();
+        assert!(!list[0].pinned);
+        assert_eq!(list[0].count, 3);
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_pin_adds_unknown_path() {
+        let index = get_temporary_index();
+        let input_dir = tempdir().unwrap();
+        let input = input_dir.path();
+        let pattern = input.file_name().unwrap().to_str().unwrap();
+
+        index.pin(input).unwrap();
+
+        assert!(index.contains(input).unwrap());
+        assert!(index.list().unwrap()[0].pinned);
+        assert_eq!(
+            index.find_one(&[pattern], &[], None).unwrap(),
+            Some(input.to_owned())
+        );
+        input_dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_pin_invalid_path() {
+        let index = get_temporary_index();
+        let input = PathBuf::from("foo");
+
+        assert_eq!(
+            index.pin(&input).unwrap_err().downcast_ref::<IndexError>(),
+            Some(&IndexError::PathDoesNotExist("foo".to_owned()))
+        );
+        assert!(!index.contains(&input).unwrap())
+    }
+
+    #[test]
+    fn index_trailing_separator_arguments() {
+        let index = get_temporary_index();
+        let dir = tempdir().unwrap();
+        let path = normalize_path(dir.path());
+        // Joining an empty component appends a separator
+        let spelled = dir.path().join("");
+
+        index.pin(&spelled).unwrap();
+        assert_eq!(index.adjust_weight(&spelled, 5).unwrap(), 5);
+        index.unpin(&spelled).unwrap();
+
+        let entries = index.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((&entries[0].path, entries[0].pinned), (&path, false));
+        index.delete(&spelled).unwrap();
+        assert!(index.list().unwrap().is_empty());
+        dir.close().unwrap()
+    }
+
+    #[test]
+    fn index_rename_trailing_separator() {
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let (old, new) = (root.path().join("old"), root.path().join("new"));
+        fs::create_dir(&old).unwrap();
+        fs::create_dir_all(new.join("sub")).unwrap();
+        index.add(&old).unwrap();
+
+        index.rename(&old.join(""), &new.join("")).unwrap();
+        assert_eq!(index.list_below(root.path()).unwrap(), vec![new.clone()]);
+
+        index.add(&new.join("sub")).unwrap();
+        assert_eq!(
+            index.rename_prefix(&new.join(""), &old.join("")).unwrap(),
+            2
+        );
+        assert_eq!(
+            index.list_below(root.path()).unwrap(),
+            vec![old.clone(), old.join("sub")]
+        );
+        assert!(index.verify().unwrap().is_consistent());
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn index_unpin_unknown_path() {
+        let index = get_temporary_index();
+        let input = PathBuf::from("/foo");
+
+        assert_eq!(
+            index
+                .unpin(&input)
+                .unwrap_err()
+                .downcast_ref::<IndexError>(),
+            Some(&IndexError::NotIndexed("/foo".to_owned()))
+        );
+    }
+
+    #[test]
+    fn get_best_score_pinned_wins() {
+        let index = get_temporary_index();
+        let pinned = PathBuf::from("/pinned");
+        insert_record(
+            &index,
+            &pinned,
+            &PathRecord {
+                timestamp: days_ago(180),
+                count: 1,
+                pinned: true,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        let input = vec![
+            Score::new(PathBuf::from("/foo"), 135),
+            Score::new(pinned.clone(), 20),
+        ];
 
-        let result = score_results(input.as_slice(), pattern);
+        let result = index.get_best_score(input, None).unwrap().unwrap();
 
-        assert_eq!(result.len(), input.len())
+        assert_eq!(result.path, pinned)
     }
 
     #[test]
-    fn score_result_empty_input() {
-        let input = Vec::<String>::new();
-        let pattern = "abc";
-
-        let result = score_results(input.as_slice(), pattern);
+    fn get_best_score_pinned_below_threshold() {
+        let index = get_temporary_index();
+        let pinned = PathBuf::from("/pinned");
+        insert_record(
+            &index,
+            &pinned,
+            &PathRecord {
+                timestamp: days_ago(1),
+                count: 1,
+                pinned: true,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        let input = vec![
+            Score::new(PathBuf::from("/foo"), 20),
+            Score::new(pinned, PIN_SCORE_THRESHOLD),
+        ];
 
-        assert!(result.is_empty())
-    }
+        let result = index.get_best_score(input, None).unwrap().unwrap();
 
-    fn get_temporary_index() -> Index {
-        let db_config = sled::Config::new().temporary(true);
-        Index::open(db_config).unwrap()
+        assert_eq!(result.path, PathBuf::from("/foo"))
     }
 
     #[test]
-    fn index_add_absolute_directory() {
+    fn index_adjust_weight() {
         let index = get_temporary_index();
         let input_dir = tempdir().unwrap();
         let input = input_dir.path();
-        assert!(index.add(input).is_ok());
-        assert!(index.has_path(input));
-        input_dir.close().unwrap();
+        index.add(input).unwrap();
+
+        assert_eq!(index.adjust_weight(input, 10).unwrap(), 10);
+        assert_eq!(index.adjust_weight(input, -15).unwrap(), -5);
+        assert_eq!(index.list().unwrap()[0].weight, -5);
+        assert_eq!(index.adjust_weight(input, i64::MAX).unwrap(), MAX_WEIGHT);
+        assert_eq!(index.adjust_weight(input, i64::MIN).unwrap(), -MAX_WEIGHT);
+
+        // Visiting the path keeps its weight
+        index.add(input).unwrap();
+        assert_eq!(index.list().unwrap()[0].weight, -MAX_WEIGHT);
+        input_dir.close().unwrap()
     }
 
     #[test]
-    fn index_add_relative_directory() {
+    fn index_adjust_weight_unknown_path() {
         let index = get_temporary_index();
-        // This may be a bit flaky, but running tests in a reproducable env on CI should be fine
-        let input = PathBuf::from("src");
+        let input = PathBuf::from("/foo");
+
         assert_eq!(
-            index.add(&input).unwrap_err().downcast_ref::<IndexError>(),
-            Some(&IndexError::RelativePath(
-                input.to_string_lossy().into_owned()
-            ))
+            index
+                .adjust_weight(&input, 10)
+                .unwrap_err()
+                .downcast_ref::<IndexError>(),
+            Some(&IndexError::NotIndexed("/foo".to_owned()))
         );
-        assert!(!index.has_path(&input))
+        assert!(!index.contains(&input).unwrap())
     }
 
     #[test]
-    fn index_add_file() {
+    fn get_best_score_demoted_loses_tie() {
         let index = get_temporary_index();
-        // This may be a bit flaky, but running tests in a reproducable env on CI should be fine
-        let input_dir = tempdir().unwrap();
-        let input = input_dir.path().join("foo.txt");
-        File::create(&input).unwrap();
-        assert_eq!(
-            index.add(&input).unwrap_err().downcast_ref::<IndexError>(),
-            Some(&IndexError::PathDoesNotExist(
-                input.to_string_lossy().into_owned()
-            ))
-        );
-        assert!(!index.has_path(&input));
-        input_dir.close().unwrap()
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap()];
+        for dir in input_dirs.iter() {
+            index.add(dir.path()).unwrap();
+        }
+        let input = || {
+            input_dirs
+                .iter()
+                .map(|dir| Score::new(dir.path().to_owned(), 20))
+                .collect::<Vec<_>>()
+        };
+
+        // The most recently added path wins the tie
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, input_dirs[1].path());
+
+        index.adjust_weight(input_dirs[1].path(), -1).unwrap();
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, input_dirs[0].path());
+
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
     }
 
     #[test]
-    fn index_add_non_existing_directory() {
+    fn get_best_score_weighted_wins_tie() {
         let index = get_temporary_index();
-        let input = PathBuf::from("foo");
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap()];
+        for dir in input_dirs.iter().rev() {
+            index.add(dir.path()).unwrap();
+        }
+        let input = || {
+            input_dirs
+                .iter()
+                .map(|dir| Score::new(dir.path().to_owned(), 20))
+                .collect::<Vec<_>>()
+        };
+
+        // The most recently added path wins the tie
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, input_dirs[0].path());
+
+        assert_eq!(index.set_weight(input_dirs[1].path(), 5).unwrap(), 5);
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, input_dirs[1].path());
+        assert_eq!(result.weighted_score(), 25);
+
+        // The weight is replaced, not added to
         assert_eq!(
-            index.add(&input).unwrap_err().downcast_ref::<IndexError>(),
-            Some(&IndexError::PathDoesNotExist("foo".to_owned()))
+            index.set_weight(input_dirs[1].path(), i64::MAX).unwrap(),
+            MAX_WEIGHT
         );
-        assert!(!index.has_path(&input))
+        assert_eq!(index.set_weight(input_dirs[1].path(), -1).unwrap(), -1);
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, input_dirs[0].path());
+
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
     }
 
     #[test]
-    fn index_add_non_canonical_absolute_directory() {
+    fn get_best_score_boosted_beats_higher_score() {
         let index = get_temporary_index();
-        let input_dir = tempdir().unwrap();
-        let input = input_dir.path().join("..");
-        assert!(index.add(&input).is_ok());
-        assert!(index.has_path(&input));
-        input_dir.close().unwrap()
+        let boosted = PathBuf::from("/boosted");
+        insert_record(
+            &index,
+            &boosted,
+            &PathRecord {
+                timestamp: days_ago(1),
+                count: 1,
+                pinned: false,
+                weight: 20,
+                hits: 0,
+            },
+        );
+        let input = vec![
+            Score::new(PathBuf::from("/foo"), 30),
+            Score::new(boosted.clone(), 20),
+        ];
+
+        let result = index.get_best_score(input, None).unwrap().unwrap();
+
+        assert_eq!(result.path, boosted)
     }
 
     #[test]
-    fn index_delete_empty_index() {
+    fn get_best_score_skips_records_that_cant_win() {
         let index = get_temporary_index();
+        let unreadable = PathBuf::from("/unreadable");
+        index
+            .paths
+            .insert(path_to_bytes(&unreadable), &[0xff][..])
+            .unwrap();
+        let input = || {
+            vec![
+                Score::new(unreadable.clone(), PIN_SCORE_THRESHOLD),
+                Score::new(
+                    PathBuf::from("/foo"),
+                    PIN_SCORE_THRESHOLD + 2 * MAX_WEIGHT + 1,
+                ),
+            ]
+        };
 
-        let input = PathBuf::from("foo");
-
-        assert!(index.delete(&input).is_ok());
-        assert!(!index.has_path(&input))
+        // It can't be pinned and even the largest weight can't make up the difference, so its
+        // record is never read
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, PathBuf::from("/foo"));
+        assert!(index.rank_scores(input(), None).is_err())
     }
 
     #[test]
-    fn index_delete_indexed_path() {
+    fn get_best_score_cwd_descendant_wins_tie() {
         let index = get_temporary_index();
-        let input_dir = tempdir().unwrap();
-        let input = input_dir.path();
+        let cwd = PathBuf::from("/home/me/project");
+        let inside = cwd.join("tests");
+        let outside = PathBuf::from("/home/me/other/tests");
+        let input = || {
+            vec![
+                Score::new(inside.clone(), 20),
+                Score::new(outside.clone(), 20),
+            ]
+        };
 
-        index.add(input).unwrap();
+        let result = index.get_best_score(input(), Some(&cwd)).unwrap().unwrap();
+        assert_eq!(result.path, inside);
 
-        assert!(index.delete(input).is_ok());
-        assert!(!index.has_path(input));
-        input_dir.close().unwrap()
+        let result = index
+            .get_best_score(input(), Some(Path::new("/home/me/other")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.path, outside)
     }
 
     #[test]
-    fn index_delete_non_indexed_path() {
+    fn get_best_score_cwd_itself_no_bonus() {
         let index = get_temporary_index();
-        let input_dir_1 = tempdir().unwrap();
-        let input_dir_2 = tempdir().unwrap();
-        let path1 = input_dir_1.path();
-        let path2 = input_dir_2.path();
-        let input = PathBuf::from("foo");
-
-        index.add(path1).unwrap();
-        index.add(path2).unwrap();
+        let cwd = PathBuf::from("/home/me/project");
+        let input = vec![
+            Score::new(cwd.clone(), 20),
+            Score::new(PathBuf::from("/home/me/projects"), 21),
+        ];
 
-        assert!(index.delete(&input).is_ok());
-        assert!(!index.has_path(&input));
+        let result = index.get_best_score(input, Some(&cwd)).unwrap().unwrap();
 
-        input_dir_1.close().unwrap();
-        input_dir_2.close().unwrap()
+        assert_eq!(result.path, Path::new("/home/me/projects"))
     }
 
     #[test]
-    fn index_find_one_empty_index() {
-        let index = get_temporary_index();
-        let pattern = "abcd";
+    fn index_find_all_prefix() {
+        let index = open_temporary_index(IndexBuilder::new().match_mode(MatchMode::Prefix));
+        let paths_fst = Set::from_iter(vec![
+            "/home/desktop/temp",
+            "/home/dt-stuff",
+            "/home/dtool/sub",
+            "/src/DTool",
+        ])
+        .unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
 
-        assert!(index.find_one(pattern, None).unwrap().is_none())
+        assert_eq!(
+            index.find_all(&["dt"], &[]).unwrap(),
+            vec![PathBuf::from("/home/dt-stuff"), PathBuf::from("/src/DTool")]
+        );
+        assert_eq!(
+            index.find_all(&["dt", "s"], &[]).unwrap(),
+            vec![PathBuf::from("/home/dtool/sub")]
+        );
+        assert_eq!(
+            index.find_all(&["home", "dt-stuff"], &[]).unwrap(),
+            vec![PathBuf::from("/home/dt-stuff")]
+        );
+        assert!(index.find_all(&["tool"], &[]).unwrap().is_empty());
     }
+
     #[test]
-    fn index_find_one_empty_index_empty_pattern() {
+    fn index_rank() {
         let index = get_temporary_index();
-        let pattern = "";
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap()];
+        for dir in input_dirs.iter() {
+            index.add(dir.path()).unwrap();
+        }
+        index.adjust_weight(input_dirs[0].path(), 50).unwrap();
+
+        let result = index.rank(&["tmp"], &[], None).unwrap();
 
-        assert!(index.find_one(pattern, None).unwrap().is_none())
+        assert_eq!(
+            result.iter().map(|s| s.path.as_path()).collect::<Vec<_>>(),
+            vec![input_dirs[0].path(), input_dirs[1].path()]
+        );
+        assert_eq!(result[0].weight, 50);
+        assert_eq!(result[0].weighted_score(), result[0].score + 50);
+        assert!(result.iter().all(|s| s.timestamp.is_some()));
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
     }
 
     #[test]
-    fn index_find_one_non_empty_index_found() {
-        let index = get_temporary_index();
-        let indexed_dir = tempdir().unwrap();
-        let path_buf = indexed_dir.path();
-        let pattern = path_buf.file_name().unwrap().to_str().unwrap();
-
-        index.add(path_buf).unwrap();
+    fn index_find_all_case_sensitive() {
+        let index = open_temporary_index(IndexBuilder::new().case_sensitive(true));
+        let paths_fst = Set::from_iter(vec!["/home/Projects", "/home/projects-old"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
 
         assert_eq!(
-            index.find_one(pattern, None).unwrap(),
-            Some(PathBuf::from(path_buf))
+            index.find_all(&["Proj"], &[]).unwrap(),
+            vec![PathBuf::from("/home/Projects")]
         );
 
-        indexed_dir.close().unwrap()
+        // Searches ignore case by default
+        let index = get_temporary_index();
+        let paths_fst = Set::from_iter(vec!["/home/Projects", "/home/projects-old"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
+        assert_eq!(index.find_all(&["Proj"], &[]).unwrap().len(), 2)
     }
 
     #[test]
-    fn index_find_one_non_empty_index_not_found() {
-        let index = get_temporary_index();
-        let indexed_dir = tempdir().unwrap();
-        let path_buf = indexed_dir.path();
-        let pattern = "#!";
+    fn case_sensitivity_is_sensitive() {
+        let cases = [
+            (CaseSensitivity::Smart, "foo", false),
+            (CaseSensitivity::Smart, "Foo", true),
+            (CaseSensitivity::Smart, "FOO", true),
+            (CaseSensitivity::Smart, "fOo", true),
+            (CaseSensitivity::Smart, "élan", false),
+            (CaseSensitivity::Smart, "Élan", true),
+            (CaseSensitivity::Smart, "straße", false),
+            (CaseSensitivity::Smart, "src-2_0", false),
+            (CaseSensitivity::Sensitive, "foo", true),
+            (CaseSensitivity::Insensitive, "Foo", false),
+        ];
+        for (case_sensitivity, term, expected) in cases {
+            assert_eq!(
+                case_sensitivity.is_sensitive(&[term]),
+                expected,
+                "{:?} {}",
+                case_sensitivity,
+                term
+            );
+        }
+        // Smart case looks at every term
+        assert!(CaseSensitivity::Smart.is_sensitive(&["foo", "Bar"]));
+        assert!(!CaseSensitivity::Smart.is_sensitive(&[]))
+    }
 
-        index.add(path_buf).unwrap();
+    #[test]
+    fn index_rank_smart_case() {
+        let index =
+            open_temporary_index(IndexBuilder::new().case_sensitivity(CaseSensitivity::Smart));
+        let paths_fst = Set::from_iter(vec!["/src/API", "/src/api"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
 
-        assert!(index.find_one(pattern, None).unwrap().is_none());
+        let scores = index.rank(&["API"], &[], None).unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].path, PathBuf::from("/src/API"));
+        assert!(scores[0].score > 0);
+        // Lowercase terms are matched and scored ignoring case
+        let scores = index.rank(&["api"], &[], None).unwrap();
+        assert_eq!(scores.len(), 2);
+        assert!(scores.iter().all(|score| score.score > 0))
+    }
 
-        indexed_dir.close().unwrap()
+    #[test]
+    fn index_find_all_case_sensitivity_subsequence() {
+        let find_all = |case_sensitivity, term| {
+            let index =
+                open_temporary_index(IndexBuilder::new().case_sensitivity(case_sensitivity));
+            let paths_fst = Set::from_iter(vec!["/src/API", "/src/api"]).unwrap();
+            index.insert_into_paths_index(paths_fst).unwrap();
+            index.find_all(&[term], &[]).unwrap()
+        };
+
+        // "ai" only matches as a subsequence
+        assert_eq!(find_all(CaseSensitivity::Insensitive, "AI").len(), 2);
+        assert_eq!(
+            find_all(CaseSensitivity::Sensitive, "AI"),
+            vec![PathBuf::from("/src/API")]
+        );
+        assert_eq!(
+            find_all(CaseSensitivity::Sensitive, "ai"),
+            vec![PathBuf::from("/src/api")]
+        );
+        assert_eq!(find_all(CaseSensitivity::Smart, "ai").len(), 2);
+        assert_eq!(
+            find_all(CaseSensitivity::Smart, "AI"),
+            vec![PathBuf::from("/src/API")]
+        )
     }
 
     #[test]
-    fn index_find_one_non_empty_index_empty_pattern() {
-        let index = get_temporary_index();
-        let indexed_dir = tempdir().unwrap();
-        let path_buf = indexed_dir.path();
-        let pattern = "";
+    fn index_find_all_basename() {
+        let paths = vec!["/etc/nginx-cfg", "/home/cfg/projects/app", "/home/config"];
+        let index = open_temporary_index(IndexBuilder::new().basename(true));
+        index
+            .insert_into_paths_index(Set::from_iter(paths.clone()).unwrap())
+            .unwrap();
 
-        index.add(path_buf).unwrap();
+        // The match in a parent component doesn't count
+        assert_eq!(
+            index.find_all(&["cfg"], &[]).unwrap(),
+            vec![
+                PathBuf::from("/etc/nginx-cfg"),
+                PathBuf::from("/home/config")
+            ]
+        );
+        assert!(index.find_all(&["home", "cfg"], &[]).unwrap().is_empty());
+        assert!(index.find_all(&["cfgapp"], &[]).unwrap().is_empty());
 
-        assert!(index.find_one(pattern, None).unwrap().is_none());
+        let index = get_temporary_index();
+        index
+            .insert_into_paths_index(Set::from_iter(paths).unwrap())
+            .unwrap();
+        assert_eq!(index.find_all(&["cfg"], &[]).unwrap().len(), 3)
+    }
 
-        indexed_dir.close().unwrap()
+    #[test]
+    fn index_find_all_basename_prefix() {
+        let index = open_temporary_index(
+            IndexBuilder::new()
+                .basename(true)
+                .match_mode(MatchMode::Prefix),
+        );
+        let paths_fst = Set::from_iter(vec!["/config/app", "/home/config"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
+
+        assert_eq!(
+            index.find_all(&["con"], &[]).unwrap(),
+            vec![PathBuf::from("/home/config")]
+        );
+        assert!(index.find_all(&["con", "app"], &[]).unwrap().is_empty())
     }
 
     #[test]
-    fn index_find_one_non_empty_index_found_excluded() {
-        let index = get_temporary_index();
-        let indexed_dir = tempdir().unwrap();
-        let path_buf = indexed_dir.path();
-        let pattern = path_buf.file_name().unwrap().to_str().unwrap();
+    fn index_rank_basename_scores_name() {
+        let index = open_temporary_index(IndexBuilder::new().basename(true));
+        let paths_fst = Set::from_iter(vec!["/config", "/src/cfg/deep/config"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
 
-        index.add(path_buf).unwrap();
+        let scores = index.rank(&["cfg"], &[], None).unwrap();
 
-        assert!(index.find_one(pattern, Some(path_buf)).unwrap().is_none());
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[0].score, scores[1].score)
+    }
 
-        indexed_dir.close().unwrap()
+    #[test]
+    fn index_rank_skim_matcher() {
+        let index = open_temporary_index(IndexBuilder::new().matcher(Matcher::Skim));
+        let paths_fst = Set::from_iter(vec!["/src/foo-bar", "/src/fxoxo"]).unwrap();
+        index.insert_into_paths_index(paths_fst).unwrap();
+
+        let result = index.rank(&["foo"], &[], None).unwrap();
+
+        assert_eq!(result[0].path, PathBuf::from("/src/foo-bar"));
+        assert!(result[0].score > result[1].score)
     }
 
     #[test]
-    fn index_list_empty() {
-        let index = get_temporary_index();
+    fn index_rank_frecency_weight() {
+        let index = open_temporary_index(IndexBuilder::new().frecency_weight(10));
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap()];
+        for dir in input_dirs.iter() {
+            index.add(dir.path()).unwrap();
+        }
+        for _ in 0..4 {
+            index.add(input_dirs[1].path()).unwrap();
+        }
 
-        let list = index.list().unwrap();
+        let result = index.rank(&["tmp"], &[], None).unwrap();
 
-        assert!(list.is_empty());
+        assert_eq!(result[0].path, input_dirs[1].path());
+        assert!(result[0].frecency_bonus > result[1].frecency_bonus);
+        assert_eq!(
+            result[0].weighted_score(),
+            result[0].score + result[0].frecency_bonus
+        );
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
     }
 
     #[test]
-    fn index_list_not_empty() {
+    fn index_rank_without_fuzzy_scoring() {
+        let index = open_temporary_index(IndexBuilder::new().fuzzy_scoring(false));
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
+        for (age, dir) in input_dirs.iter().enumerate() {
+            insert_record(
+                &index,
+                dir.path(),
+                &PathRecord {
+                    timestamp: days_ago(age as u64),
+                    count: 10 * age as u64,
+                    pinned: false,
+                    weight: 0,
+                    hits: 0,
+                },
+            );
+        }
+        let mut keys = input_dirs
+            .iter()
+            .map(|dir| path_to_bytes(dir.path()).into_owned())
+            .collect::<Vec<_>>();
+        keys.sort();
+        index
+            .insert_into_paths_index(Set::from_iter(keys).unwrap())
+            .unwrap();
+
+        // The most recent visit wins, even from paths that were visited more often
+        let result = index.rank(&["tmp"], &[], None).unwrap();
+        assert_eq!(
+            result.iter().map(|s| s.path.as_path()).collect::<Vec<_>>(),
+            input_dirs.iter().map(|dir| dir.path()).collect::<Vec<_>>()
+        );
+        assert!(result.iter().all(|s| s.score == 0 && s.frecency == 0.0));
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn index_find_n() {
         let index = get_temporary_index();
+        let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
+        for dir in input_dirs.iter() {
+            index.add(dir.path()).unwrap();
+        }
+        index.adjust_weight(input_dirs[2].path(), 50).unwrap();
 
-        let input_dir_1 = tempdir().unwrap();
-        let input_dir_2 = tempdir().unwrap();
-        let path1 = input_dir_1.path();
-        let path2 = input_dir_2.path();
+        assert_eq!(
+            index.find_n(&["tmp"], 1, &[], None).unwrap(),
+            vec![input_dirs[2].path().to_owned()]
+        );
+        assert_eq!(index.find_n(&["tmp"], 2, &[], None).unwrap().len(), 2);
+        assert_eq!(index.find_n(&["tmp"], 10, &[], None).unwrap().len(), 3);
+        assert!(index.find_n(&["tmp"], 0, &[], None).unwrap().is_empty());
+        for dir in input_dirs {
+            dir.close().unwrap();
+        }
+    }
 
-        index.add(path1).unwrap();
-        index.add(path2).unwrap();
+    #[test]
+    fn get_best_score_missing_penalized() {
+        let mut index = open_temporary_index(IndexBuilder::new().missing_penalty(1000));
+        let existing = tempdir().unwrap();
+        let missing = PathBuf::from("/missing");
+        insert_record(
+            &index,
+            &missing,
+            &PathRecord {
+                timestamp: days_ago(1),
+                count: 100,
+                pinned: true,
+                weight: MAX_WEIGHT,
+                hits: 0,
+            },
+        );
+        let input = || {
+            vec![
+                Score::new(missing.clone(), 135),
+                Score::new(existing.path().to_owned(), 1),
+            ]
+        };
 
-        let mut list = index.list().unwrap();
-        list.sort();
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, existing.path());
 
-        // This works because we ensured the IndexEntries are sorted by timestamp first
-        assert_eq!(list[0].path, path1);
-        assert_eq!(list[1].path, path2);
+        // A missing path is still returned when nothing else matches
+        let result = index.get_best_score(vec![input().remove(0)], None);
+        assert_eq!(result.unwrap().unwrap().penalty, 1000);
 
-        input_dir_1.close().unwrap();
-        input_dir_2.close().unwrap()
+        // Without a penalty the missing path wins
+        index.missing_penalty = 0;
+        let result = index.get_best_score(input(), None).unwrap().unwrap();
+        assert_eq!(result.path, missing);
+        existing.close().unwrap()
     }
 
     #[test]
@@ -648,53 +5971,29 @@ mod tests {
         let index = get_temporary_index();
         let input = vec![];
 
-        assert!(index.get_best_score(input).unwrap().is_none())
+        assert!(index.get_best_score(input, None).unwrap().is_none())
     }
 
     #[test]
     fn get_best_score_single_result() {
         let index = get_temporary_index();
-        let input = vec![Score {
-            path: PathBuf::from("/foo"),
-            score: 20,
-            timestamp: None,
-        }];
-        let expected = Some(Score {
-            path: PathBuf::from("/foo"),
-            score: 20,
-            timestamp: None,
-        });
+        let input = vec![Score::new(PathBuf::from("/foo"), 20)];
+        let expected = Some(Score::new(PathBuf::from("/foo"), 20));
 
-        assert_eq!(index.get_best_score(input).unwrap(), expected)
+        assert_eq!(index.get_best_score(input, None).unwrap(), expected)
     }
 
     #[test]
     fn get_best_score_clear_winner() {
         let index = get_temporary_index();
         let input = vec![
-            Score {
-                path: PathBuf::from("/foo"),
-                score: 20,
-                timestamp: None,
-            },
-            Score {
-                path: PathBuf::from("/bar"),
-                score: 135,
-                timestamp: None,
-            },
-            Score {
-                path: PathBuf::from("/baz"),
-                score: 1,
-                timestamp: None,
-            },
+            Score::new(PathBuf::from("/foo"), 20),
+            Score::new(PathBuf::from("/bar"), 135),
+            Score::new(PathBuf::from("/baz"), 1),
         ];
-        let expected = Some(Score {
-            path: PathBuf::from("/bar"),
-            score: 135,
-            timestamp: None,
-        });
+        let expected = Some(Score::new(PathBuf::from("/bar"), 135));
 
-        assert_eq!(index.get_best_score(input).unwrap(), expected)
+        assert_eq!(index.get_best_score(input, None).unwrap(), expected)
     }
 
     #[test]
@@ -702,35 +6001,20 @@ mod tests {
         let index = get_temporary_index();
         let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
         let input = vec![
-            Score {
-                path: input_dirs[0].path().to_owned(),
-                score: 20,
-                timestamp: None,
-            },
-            Score {
-                path: input_dirs[1].path().to_owned(),
-                score: 20,
-                timestamp: None,
-            },
-            Score {
-                path: input_dirs[2].path().to_owned(),
-                score: 1,
-                timestamp: None,
-            },
+            Score::new(input_dirs[0].path().to_owned(), 20),
+            Score::new(input_dirs[1].path().to_owned(), 20),
+            Score::new(input_dirs[2].path().to_owned(), 1),
         ];
         for score in input.iter() {
             index.add(&score.path).unwrap();
         }
-        let expected = Score {
-            path: input[1].path.clone(),
-            score: input[1].score,
-            timestamp: None,
-        };
+        let expected = Score::new(input[1].path.clone(), input[1].score);
 
-        let result = index.get_best_score(input).unwrap();
+        let result = index.get_best_score(input, None).unwrap();
         assert!(result.is_some());
         let result_score = result.unwrap();
         assert_eq!(result_score.score, expected.score);
+        assert_eq!(result_score.count, 1);
         assert_eq!(result_score.path, expected.path);
 
         for pb in input_dirs {
@@ -738,35 +6022,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_best_score_count_tiebreaker() {
+        let index = get_temporary_index();
+        let timestamp = days_ago(1);
+        let jumped = PathBuf::from("/jumped");
+        let visited = PathBuf::from("/visited");
+        // A jump counts as many visits, so both paths have the same frecency
+        insert_record(
+            &index,
+            &jumped,
+            &PathRecord {
+                timestamp,
+                count: 1,
+                pinned: false,
+                weight: 0,
+                hits: 1,
+            },
+        );
+        insert_record(
+            &index,
+            &visited,
+            &PathRecord {
+                timestamp,
+                count: 1 + HIT_WEIGHT,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            },
+        );
+        let input = vec![Score::new(jumped, 20), Score::new(visited.clone(), 20)];
+
+        let result = index.rank_scores(input, None).unwrap();
+
+        assert_eq!(result[0].frecency, result[1].frecency);
+        assert_eq!(result[0].path, visited);
+        assert_eq!(result[0].count, 1 + HIT_WEIGHT)
+    }
+
     #[test]
     fn get_best_score_timestamp_tiebreaker_none() {
         let index = get_temporary_index();
         let input_dirs = vec![tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap()];
         let input = vec![
-            Score {
-                path: input_dirs[0].path().to_owned(),
-                score: 20,
-                timestamp: None,
-            },
-            Score {
-                path: input_dirs[1].path().to_owned(),
-                score: 20,
-                timestamp: None,
-            },
-            Score {
-                path: input_dirs[2].path().to_owned(),
-                score: 1,
-                timestamp: None,
-            },
+            Score::new(input_dirs[0].path().to_owned(), 20),
+            Score::new(input_dirs[1].path().to_owned(), 20),
+            Score::new(input_dirs[2].path().to_owned(), 1),
         ];
         index.add(&input.first().unwrap().path).unwrap();
-        let expected = Score {
-            path: input[0].path.clone(),
-            score: input[0].score,
-            timestamp: None,
-        };
+        let expected = Score::new(input[0].path.clone(), input[0].score);
 
-        let result = index.get_best_score(input).unwrap();
+        let result = index.get_best_score(input, None).unwrap();
         assert!(result.is_some());
         let result_score = result.unwrap();
         assert_eq!(result_score.score, expected.score);
@@ -776,4 +6082,47 @@ mod tests {
             pb.close().unwrap();
         }
     }
+
+    // Run with `cargo test --release -- --ignored --nocapture bench_add`
+    #[test]
+    #[ignore]
+    fn bench_add_large_index() {
+        use std::time::Instant;
+
+        let index = get_temporary_index();
+        let root = tempdir().unwrap();
+        let dirs = (0..10_000)
+            .map(|i| {
+                let dir = root.path().join(format!("directory-{:05}", i));
+                fs::create_dir(&dir).unwrap();
+                dir
+            })
+            .collect::<Vec<_>>();
+        let (seed, measured) = dirs.split_at(9_900);
+        for dir in seed {
+            index.add(dir).unwrap();
+        }
+
+        let start = Instant::now();
+        for dir in measured {
+            index.add(dir).unwrap();
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "add of new paths on a 10k entry index: {:?} per add",
+            elapsed / measured.len() as u32
+        );
+
+        let start = Instant::now();
+        for dir in measured {
+            index.add(dir).unwrap();
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "add of known paths on a 10k entry index: {:?} per add",
+            elapsed / measured.len() as u32
+        );
+
+        root.close().unwrap()
+    }
 }
@@ -3,7 +3,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::convert::TryFrom;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::path::Path;
 use std::{env, fmt, io};
 use thiserror::Error;
@@ -20,14 +20,17 @@ use thiserror::Error;
 pub enum Shell {
     Zsh,
     Bash,
+    Elvish,
 }
 
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum ShellError {
     #[error(
-        "`{name}` is not a supported shell string representation. Must be one of: [bash, zsh]"
+        "`{name}` is not a supported shell string representation. Must be one of: [bash, elvish, zsh]"
     )]
     UnknownShellName { name: String },
+    #[error("Could not detect your shell, pass it explicitly: scotty init <SHELL>")]
+    UndetectableShell,
 }
 
 impl TryFrom<&str> for Shell {
@@ -37,6 +40,7 @@ impl TryFrom<&str> for Shell {
         match value.to_lowercase().trim() {
             "zsh" => Ok(Shell::Zsh),
             "bash" => Ok(Shell::Bash),
+            "elvish" => Ok(Shell::Elvish),
             _ => Err(ShellError::UnknownShellName {
                 name: value.to_owned(),
             }),
@@ -57,28 +61,71 @@ impl fmt::Debug for Shell {
         match self {
             Shell::Zsh => write!(f, "zsh"),
             Shell::Bash => write!(f, "bash"),
+            Shell::Elvish => write!(f, "elvish"),
         }
     }
 }
 
 impl Shell {
     pub fn all_variants() -> &'static [Shell] {
-        &[Shell::Bash, Shell::Zsh]
+        &[Shell::Bash, Shell::Elvish, Shell::Zsh]
     }
+
+    /// Detects the shell of the user from $SHELL, or else from the name of the parent process
+    /// (only on Linux)
+    pub fn from_env() -> Result<Shell, ShellError> {
+        detect_shell([env::var_os("SHELL"), parent_process_name()])
+    }
+}
+
+// Returns the first candidate that names a supported shell
+fn detect_shell<I>(candidates: I) -> Result<Shell, ShellError>
+where
+    I: IntoIterator<Item = Option<OsString>>,
+{
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|candidate| shell_from_program(&candidate).ok())
+        .ok_or(ShellError::UndetectableShell)
+}
+
+// Turns the path of a shell program (/bin/zsh) or the name of a login shell (-zsh) into a Shell
+fn shell_from_program(program: &OsStr) -> Result<Shell, ShellError> {
+    let name = Path::new(program)
+        .file_name()
+        .unwrap_or(program)
+        .to_string_lossy();
+    Shell::try_from(name.trim_start_matches('-'))
+}
+
+#[cfg(target_os = "linux")]
+fn parent_process_name() -> Option<OsString> {
+    let parent_id = std::os::unix::process::parent_id();
+    std::fs::read_to_string(format!("/proc/{}/comm", parent_id))
+        .ok()
+        .map(OsString::from)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn parent_process_name() -> Option<OsString> {
+    None
 }
 
 const ZSH_INIT: &str = include_str!("scotty.zsh");
 const BASH_INIT: &str = include_str!("scotty.bash");
+const ELVISH_INIT: &str = include_str!("scotty.elv");
 
 /// Returns the bootstrap script for a specific shell
 pub fn init_shell(shell: &Shell) -> io::Result<()> {
     let setup_script = match shell {
         Shell::Zsh => ZSH_INIT,
         Shell::Bash => BASH_INIT,
+        Shell::Elvish => ELVISH_INIT,
     };
 
     let scotty_path = env::current_exe()?;
-    log::debug!("Detected scotty_path: {}", scotty_path.display());
+    tracing::debug!("Detected scotty_path: {}", scotty_path.display());
 
     print!("{}", interpolate_scotty_path(setup_script, &scotty_path));
 
@@ -121,6 +168,15 @@ mod tests {
         assert_eq!(output, expected)
     }
 
+    #[test]
+    fn shell_try_from_elvish() {
+        let input = "elvish";
+        let output = Shell::try_from(input).unwrap();
+        let expected = Shell::Elvish;
+
+        assert_eq!(output, expected)
+    }
+
     #[test]
     fn shell_try_from_unknown_shell() {
         let input = "foo";
@@ -134,6 +190,37 @@ mod tests {
         )
     }
 
+    #[test]
+    fn detect_shell_from_path() {
+        let output = detect_shell([Some(OsString::from("/usr/bin/zsh")), None]);
+
+        assert_eq!(output, Ok(Shell::Zsh))
+    }
+
+    #[test]
+    fn detect_shell_login_shell() {
+        let output = detect_shell([None, Some(OsString::from("-bash\n"))]);
+
+        assert_eq!(output, Ok(Shell::Bash))
+    }
+
+    #[test]
+    fn detect_shell_skips_unknown_shell() {
+        let output = detect_shell([
+            Some(OsString::from("/bin/fish")),
+            Some(OsString::from("elvish")),
+        ]);
+
+        assert_eq!(output, Ok(Shell::Elvish))
+    }
+
+    #[test]
+    fn detect_shell_nothing_found() {
+        let output = detect_shell([None, Some(OsString::from("sh"))]);
+
+        assert_eq!(output, Err(ShellError::UndetectableShell))
+    }
+
     #[test]
     fn only_replaces_specific_token() {
         let script = "I am just a normal string";
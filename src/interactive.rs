@@ -0,0 +1,263 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use thiserror::Error;
+
+use scotty::Index;
+
+use crate::printer::{self, SortKey};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum InteractiveError {
+    #[error("No directory was selected")]
+    Cancelled,
+}
+
+/// Lets the user pick a path from the index, the matches are updated as the query is typed
+/// open_index is called for every search, so the database isn't kept open while the user types
+/// The interface is drawn on stderr, so the shell function can still capture stdout
+/// Returns None if the user cancels with Escape or Ctrl-C
+pub fn run_interactive<F>(open_index: F, initial_query: &str) -> Result<Option<PathBuf>>
+where
+    F: Fn() -> Result<Index>,
+{
+    let search = |query: &str| find_matches(&open_index()?, query);
+    let mut state = State::new(initial_query);
+    state.set_matches(search(&state.query)?);
+
+    let mut stderr = io::stderr();
+    terminal::enable_raw_mode()?;
+    let selected = execute!(stderr, EnterAlternateScreen)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| event_loop(&search, &mut state, &mut stderr));
+    // Always give the terminal back, also when something went wrong
+    let restored = execute!(stderr, LeaveAlternateScreen).and(terminal::disable_raw_mode());
+
+    let selected = selected?;
+    restored?;
+    Ok(selected)
+}
+
+fn event_loop<S>(search: &S, state: &mut State, out: &mut impl Write) -> Result<Option<PathBuf>>
+where
+    S: Fn(&str) -> Result<Vec<PathBuf>>,
+{
+    loop {
+        draw(state, out)?;
+        if let Event::Key(key) = event::read()? {
+            match state.handle_key(key) {
+                Action::None => {}
+                // A query that is still being typed can be an invalid regex, which just
+                // doesn't match anything yet
+                Action::QueryChanged => state.set_matches(search(&state.query).unwrap_or_default()),
+                Action::Accept => return Ok(state.selected_path()),
+                Action::Cancel => return Ok(None),
+            }
+        }
+    }
+}
+
+// Returns the paths matching the query, best match first
+// An empty query matches every path, the most recently visited first
+fn find_matches(index: &Index, query: &str) -> Result<Vec<PathBuf>> {
+    let terms = query.split_whitespace().collect::<Vec<_>>();
+    if terms.is_empty() {
+        let mut entries = index.list()?;
+        printer::sort_entries(&mut entries, SortKey::Time, false);
+        return Ok(entries.into_iter().map(|entry| entry.path).collect());
+    }
+
+    let cwd = env::current_dir().ok();
+    Ok(index
+        .rank(&terms, &[], cwd.as_deref())?
+        .into_iter()
+        .map(|score| score.path)
+        .collect())
+}
+
+// Draws the matches, with the selected one highlighted, above a line with the query
+fn draw(state: &State, out: &mut impl Write) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let list_rows = rows.saturating_sub(1) as usize;
+    let line_width = (columns as usize).saturating_sub(2);
+    // Scroll just far enough to keep the selected match in view
+    let offset = (state.selected + 1).saturating_sub(list_rows);
+
+    queue!(out, Clear(ClearType::All))?;
+    for (row, path) in state
+        .matches
+        .iter()
+        .skip(offset)
+        .take(list_rows)
+        .enumerate()
+    {
+        let line = path
+            .to_string_lossy()
+            .chars()
+            .take(line_width)
+            .collect::<String>();
+        queue!(out, MoveTo(0, row as u16))?;
+        if offset + row == state.selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", line)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(format!("  {}", line)))?;
+        }
+    }
+    queue!(
+        out,
+        MoveTo(0, rows.saturating_sub(1)),
+        Print(format!("{} > {}", state.matches.len(), state.query))
+    )?;
+    out.flush()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    None,
+    QueryChanged,
+    Accept,
+    Cancel,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    query: String,
+    matches: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl State {
+    fn new(query: &str) -> State {
+        State {
+            query: query.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn set_matches(&mut self, matches: Vec<PathBuf>) {
+        self.matches = matches;
+        self.selected = 0;
+    }
+
+    fn selected_path(&self) -> Option<PathBuf> {
+        self.matches.get(self.selected).cloned()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.kind == KeyEventKind::Release {
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Cancel,
+            KeyCode::Esc => Action::Cancel,
+            KeyCode::Enter => Action::Accept,
+            KeyCode::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                Action::None
+            }
+            KeyCode::Down => {
+                if self.selected + 1 < self.matches.len() {
+                    self.selected += 1;
+                }
+                Action::None
+            }
+            KeyCode::Backspace => match self.query.pop() {
+                Some(_) => Action::QueryChanged,
+                None => Action::None,
+            },
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                Action::QueryChanged
+            }
+            _ => Action::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn state_with_matches(count: usize) -> State {
+        let mut state = State::new("foo");
+        state.set_matches(
+            (0..count)
+                .map(|i| PathBuf::from(format!("/{}", i)))
+                .collect(),
+        );
+        state
+    }
+
+    #[test]
+    fn handle_key_typing() {
+        let mut state = State::new("fo");
+
+        assert_eq!(
+            state.handle_key(key(KeyCode::Char('o'))),
+            Action::QueryChanged
+        );
+        assert_eq!(state.query, "foo");
+        assert_eq!(
+            state.handle_key(key(KeyCode::Backspace)),
+            Action::QueryChanged
+        );
+        assert_eq!(state.query, "fo")
+    }
+
+    #[test]
+    fn handle_key_backspace_empty_query() {
+        let mut state = State::new("");
+
+        assert_eq!(state.handle_key(key(KeyCode::Backspace)), Action::None)
+    }
+
+    #[test]
+    fn handle_key_navigation_stays_in_bounds() {
+        let mut state = state_with_matches(2);
+
+        state.handle_key(key(KeyCode::Up));
+        assert_eq!(state.selected, 0);
+        state.handle_key(key(KeyCode::Down));
+        state.handle_key(key(KeyCode::Down));
+        assert_eq!(state.selected, 1);
+        assert_eq!(state.selected_path(), Some(PathBuf::from("/1")));
+
+        // New matches select the best one again
+        state.set_matches(vec![PathBuf::from("/2")]);
+        assert_eq!(state.selected_path(), Some(PathBuf::from("/2")))
+    }
+
+    #[test]
+    fn handle_key_accept_and_cancel() {
+        let mut state = state_with_matches(0);
+
+        assert_eq!(state.handle_key(key(KeyCode::Enter)), Action::Accept);
+        assert_eq!(state.selected_path(), None);
+        assert_eq!(state.handle_key(key(KeyCode::Esc)), Action::Cancel);
+        assert_eq!(
+            state.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Action::Cancel
+        );
+        assert_eq!(state.query, "foo")
+    }
+}
@@ -0,0 +1,37 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The indexing and search engine behind the scotty command line tool
+//!
+//! An [`Index`] stores the directories that were visited, with how often and how recently.
+//! [`Index::find_one`] returns the best match for a list of search terms, [`Index::rank`]
+//! returns every match with its [`Score`]. The storage (sled) and the search index (fst) are
+//! internals of `Index`, which is the only way to read or change the data. An
+//! [`IndexBuilder`] configures the ranking and the upkeep of the index before opening it.
+//!
+//! The items exported here are the supported API. Only one process can open a database at a
+//! time, [`Index::open_path`] waits until other processes have closed it.
+//!
+//! ```
+//! use scotty::Index;
+//!
+//! let data_dir = tempfile::tempdir()?;
+//! let project = tempfile::tempdir()?;
+//! let index = Index::open_path(&data_dir.path().join("scotty.db"))?;
+//!
+//! index.add(project.path())?;
+//!
+//! let name = project.path().file_name().unwrap().to_str().unwrap();
+//! assert_eq!(index.find_one(&[name], &[], None)?, Some(project.path().to_owned()));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod index;
+
+pub use index::{
+    normalize_path, validate_path, AddAction, AddSummary, CaseSensitivity, CompactReport,
+    ConflictResolution, DedupePreference, DuplicateGroup, ImportSummary, Index, IndexBuilder,
+    IndexError, IndexStats, JournalOperation, Jump, MatchMode, Matcher, PathIndexEntry, Score,
+    UndoReport, VerifyReport, MAX_WEIGHT,
+};
@@ -2,29 +2,578 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use anyhow::Result;
-use clap::{command, Arg, ArgAction, Command};
+use anyhow::{anyhow, Result};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{command, Arg, ArgAction, ArgGroup, ArgMatches, Command, ValueHint};
+use globset::{Glob, GlobMatcher};
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
 
-use crate::index::{Index, IndexError};
+use scotty::{
+    AddAction, CaseSensitivity, ConflictResolution, DedupePreference, Index, IndexBuilder,
+    IndexError, MatchMode, PathIndexEntry, MAX_WEIGHT,
+};
+
+use crate::date::TimeRange;
+use crate::export::ExportEntry;
 use crate::init::Shell;
+use crate::interactive::InteractiveError;
+use crate::printer::{ListFormat, SortKey};
+use crate::shell_history::{HistoryFormat, ShellHistoryError};
 
+mod backup;
+mod bench;
+mod completions;
 mod config;
-mod index;
+mod date;
+mod duration;
+mod edit;
+mod expand;
+mod export;
 mod init;
+mod interactive;
+mod manpage;
 mod printer;
+mod shell_history;
+mod sync;
+mod walk;
 
 fn main() -> Result<()> {
-    pretty_env_logger::init();
+    let matches = build_cli().get_matches();
+    init_logger(matches.get_flag("quiet"), matches.get_count("verbose"));
+
+    match run(&matches) {
+        Err(e) if wants_json_errors(&matches) => {
+            printer::print_json_error(&e)?;
+            process::exit(1)
+        }
+        // Finding nothing is an answer rather than a failure, so shell scripts can test for
+        // it with the exit code alone, like grep. Other subcommands report it as an error
+        Err(e)
+            if matches.subcommand_name() == Some("search")
+                && matches!(e.downcast_ref(), Some(IndexError::NoResults(_))) =>
+        {
+            tracing::info!("{}", e);
+            process::exit(1)
+        }
+        result => result,
+    }
+}
+
+// Errors are printed as json with --json-errors, or when the output itself is json
+fn wants_json_errors(matches: &ArgMatches) -> bool {
+    matches.get_flag("json-errors")
+        || matches
+            .subcommand()
+            .is_some_and(|(name, sub_m)| name == "rank" && sub_m.get_flag("json"))
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let db_path = matches.get_one::<PathBuf>("db-path").map(PathBuf::as_path);
+    match matches.subcommand() {
+        Some(("add", sub_m)) => {
+            let paths = sub_m
+                .get_many::<String>("path")
+                .expect("Path is missing")
+                .map(PathBuf::from)
+                .collect::<Vec<_>>();
+            let ignore = sub_m
+                .get_many::<String>("ignore")
+                .unwrap_or_default()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let from_stdin = paths == [Path::new("-")];
+            let if_exists = sub_m.get_flag("if-exists");
+            let paths = if if_exists && !from_stdin {
+                existing_dirs(paths)
+            } else {
+                paths
+            };
+            if paths.is_empty() {
+                return Ok(());
+            }
+            let delimiter = if sub_m.get_flag("null") { b'\0' } else { b'\n' };
+            let recursive = sub_m.get_flag("recursive");
+            let max_depth = sub_m.get_one::<usize>("max-depth").copied();
+            let hidden = sub_m.get_flag("hidden");
+            let weight = sub_m.get_one::<i64>("weight").copied();
+
+            let result = if sub_m.get_flag("dry-run") {
+                let paths = if from_stdin {
+                    read_paths(io::stdin().lock(), delimiter)?
+                } else if recursive {
+                    collect_subdirectories(&paths, max_depth, hidden)?
+                } else {
+                    paths
+                };
+
+                run_add_dry_run(db_path, &paths, &ignore)
+            } else if from_stdin {
+                run_add_stdin(db_path, &ignore, delimiter, weight)
+            } else if recursive {
+                run_add_recursive(db_path, &paths, &ignore, max_depth, hidden)
+            } else {
+                run_add(db_path, &paths, &ignore, weight)
+            };
+            match result {
+                // The prompt hook would rather miss a visit than hang the prompt
+                Err(e) if if_exists && is_database_locked(&e) => {
+                    tracing::debug!("Skipped adding paths: {}", e);
+                    Ok(())
+                }
+                result => result,
+            }
+        }
+        Some(("search", sub_m)) => {
+            let terms = sub_m
+                .get_many::<String>("target")
+                .into_iter()
+                .flatten()
+                .map(|target| expand::expand_target(target))
+                .collect::<Vec<_>>();
+            let terms = terms.iter().map(String::as_str).collect::<Vec<_>>();
+            let canonical = sub_m.get_flag("canonical");
+            if sub_m.get_flag("interactive") {
+                return run_search_interactive(db_path, &terms.join(" "), canonical);
+            }
+            let limit = sub_m.get_one::<usize>("limit").copied();
+            let from = sub_m.get_one::<String>("from").map(Path::new);
+            let results = if sub_m.get_flag("all") {
+                SearchResults::All { limit }
+            } else {
+                if limit.is_some() {
+                    tracing::warn!("--limit is ignored without --all");
+                }
+                SearchResults::Best { from }
+            };
+            let options = SearchOptions::from_matches(sub_m);
+            let cwd_boost = !sub_m.get_flag("no-cwd-boost");
+            // Jumping to the current directory does nothing, so it is left out on top of the
+            // excluded paths. The shell passes its logical directory with --from, which is
+            // how the index stores it, current_dir() has its symlinks resolved. If it can't be
+            // determined nothing is left out for it
+            let current_dir = match (sub_m.get_flag("include-cwd"), from) {
+                (true, _) => None,
+                (false, Some(from)) => std::path::absolute(from).ok(),
+                (false, None) => std::env::current_dir().ok(),
+            };
+            // The physical directory only differs from --from inside a symlinked directory
+            let physical_dir = match sub_m.get_flag("skip-current") && from.is_some() {
+                true => std::env::current_dir().ok(),
+                false => None,
+            };
+            let exclude = excluded_paths(sub_m)
+                .chain(current_dir.as_deref())
+                .chain(physical_dir.as_deref())
+                .collect::<Vec<_>>();
+
+            Ok(run_search(
+                db_path, &terms, &exclude, results, options, cwd_boost, canonical,
+            )?)
+        }
+        Some(("back", sub_m)) => {
+            let from = sub_m.get_one::<String>("from").map(Path::new);
+
+            Ok(run_back(db_path, from)?)
+        }
+        Some(("history", sub_m)) => {
+            let count = *sub_m.get_one::<usize>("count").expect("Count is missing");
+
+            Ok(run_history(db_path, count)?)
+        }
+        Some(("rank", sub_m)) => {
+            let terms = sub_m
+                .get_many::<String>("target")
+                .expect("Target is missing")
+                .map(|target| expand::expand_target(target))
+                .collect::<Vec<_>>();
+            let terms = terms.iter().map(String::as_str).collect::<Vec<_>>();
+            let exclude = excluded_paths(sub_m).collect::<Vec<_>>();
+            let options = SearchOptions::from_matches(sub_m);
+            let cwd_boost = !sub_m.get_flag("no-cwd-boost");
+            let is_json = sub_m.get_flag("json");
+
+            Ok(run_rank(
+                db_path, &terms, &exclude, options, cwd_boost, is_json,
+            )?)
+        }
+        Some(("remove", sub_m)) => {
+            if let Some(pattern) = sub_m.get_one::<Glob>("pattern") {
+                let dry_run = sub_m.get_flag("dry-run");
+
+                Ok(run_remove_pattern(db_path, pattern, dry_run)?)
+            } else {
+                let older_than = sub_m
+                    .get_one::<Duration>("older-than")
+                    .expect("Duration is missing");
+
+                Ok(run_remove_older_than(db_path, *older_than)?)
+            }
+        }
+        Some(("prune", sub_m)) => Ok(run_prune(db_path, sub_m.get_flag("apply-ignores"))?),
+        Some(("move", sub_m)) => {
+            let old = sub_m.get_one::<String>("old").expect("Old path is missing");
+            let new = sub_m.get_one::<String>("new").expect("New path is missing");
+            let prefix = sub_m.get_flag("prefix");
+
+            Ok(run_move(db_path, Path::new(old), Path::new(new), prefix)?)
+        }
+        Some(("edit", sub_m)) => Ok(run_edit(db_path, sub_m.get_flag("dry-run"))?),
+        Some(("doctor", sub_m)) => Ok(run_doctor(db_path, sub_m.get_flag("fix"))?),
+        Some(("dedupe", sub_m)) => {
+            let prefer = *sub_m
+                .get_one::<DedupePreference>("prefer")
+                .expect("Preference is missing");
+
+            Ok(run_dedupe(db_path, prefer, sub_m.get_flag("dry-run"))?)
+        }
+        Some(("rebuild-index", _)) => Ok(run_rebuild_index(db_path)?),
+        Some(("compact", _)) => Ok(run_compact(db_path)?),
+        Some(("db-path", _)) => Ok(run_db_path(db_path)?),
+        Some(("clear", sub_m)) => Ok(run_clear(db_path, sub_m.get_flag("yes"))?),
+        Some(("undo", _)) => Ok(run_undo(db_path)?),
+        Some(("init", sub_m)) => {
+            let shell = match sub_m.get_one::<Shell>("shell") {
+                Some(shell) => shell.clone(),
+                None => Shell::from_env()?,
+            };
+
+            Ok(run_init(&shell)?)
+        }
+        Some(("completions", sub_m)) => {
+            let shell = sub_m
+                .get_one::<clap_complete::Shell>("shell")
+                .expect("Shell is missing");
+
+            Ok(run_completions(*shell)?)
+        }
+        Some(("mangen", sub_m)) => {
+            let subcommand = sub_m.get_one::<String>("subcommand");
+
+            Ok(run_mangen(subcommand.map(String::as_str))?)
+        }
+        Some(("list", sub_m)) => {
+            let format = if sub_m.get_flag("json") {
+                tracing::warn!("--json is deprecated, use --format ndjson instead");
+                ListFormat::LegacyNdjson
+            } else {
+                *sub_m
+                    .get_one::<ListFormat>("format")
+                    .unwrap_or(&ListFormat::Table)
+            };
+            let range = TimeRange {
+                since: sub_m.get_one::<SystemTime>("since").copied(),
+                before: sub_m.get_one::<SystemTime>("before").copied(),
+            };
+            let sort_key = *sub_m
+                .get_one::<SortKey>("sort")
+                .expect("Sort key is missing");
+            let reverse = sub_m.get_flag("reverse");
+            let filter = match sub_m.get_one::<String>("pattern") {
+                Some(pattern) if sub_m.get_flag("glob") => {
+                    Some(ListFilter::Glob(Glob::new(pattern)?.compile_matcher()))
+                }
+                Some(pattern) => Some(ListFilter::Search(pattern.clone())),
+                None => None,
+            };
+
+            let limit = sub_m.get_one::<usize>("limit").copied();
+
+            Ok(run_list(
+                db_path, format, range, sort_key, reverse, filter, limit,
+            )?)
+        }
+        Some(("top", sub_m)) => {
+            let count = *sub_m.get_one::<usize>("count").expect("Count is missing");
+
+            Ok(run_top(db_path, count, sub_m.get_flag("json"))?)
+        }
+        Some(("recent", sub_m)) => {
+            let count = *sub_m.get_one::<usize>("count").expect("Count is missing");
+
+            let format = if sub_m.get_flag("json") {
+                RecentFormat::Json
+            } else if sub_m.get_flag("long") {
+                RecentFormat::Table
+            } else {
+                RecentFormat::Paths
+            };
+
+            Ok(run_recent(db_path, count, format)?)
+        }
+        Some(("bench", sub_m)) => {
+            let query = sub_m.get_one::<String>("query").expect("Query is missing");
+            let iterations = *sub_m
+                .get_one::<usize>("iterations")
+                .expect("Iterations is missing");
+
+            Ok(run_bench(db_path, query, iterations)?)
+        }
+        Some(("forget", sub_m)) => {
+            let dir = sub_m
+                .get_one::<String>("dir")
+                .expect("Directory is missing");
+
+            Ok(run_forget(
+                db_path,
+                Path::new(dir),
+                sub_m.get_flag("dry-run"),
+            )?)
+        }
+        Some(("hit", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+
+            Ok(run_hit(db_path, Path::new(path))?)
+        }
+        Some(("touch", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+            let create = *sub_m.get_one::<bool>("create").unwrap_or(&true);
+
+            Ok(run_touch(db_path, Path::new(path), create)?)
+        }
+        Some(("pin", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+
+            Ok(run_pin(db_path, Path::new(path), true)?)
+        }
+        Some(("unpin", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+
+            Ok(run_pin(db_path, Path::new(path), false)?)
+        }
+        Some(("boost", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+            let amount = sub_m.get_one::<i64>("amount").expect("Amount is missing");
+
+            Ok(run_adjust_weight(db_path, Path::new(path), *amount)?)
+        }
+        Some(("demote", sub_m)) => {
+            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+            let amount = sub_m.get_one::<i64>("amount").expect("Amount is missing");
+
+            Ok(run_adjust_weight(db_path, Path::new(path), -amount)?)
+        }
+        Some(("export", sub_m)) => {
+            let file = sub_m.get_one::<String>("file").map(Path::new);
+            let range = TimeRange {
+                since: sub_m.get_one::<SystemTime>("from").copied(),
+                before: sub_m.get_one::<SystemTime>("to").copied(),
+            };
+
+            Ok(run_export(db_path, file, range)?)
+        }
+        Some(("import", sub_m)) => {
+            let file = sub_m.get_one::<String>("file").map(Path::new);
+            let format = *sub_m
+                .get_one::<ImportFormat>("format")
+                .expect("Format is missing");
+
+            let resolution = sub_m.get_one::<ConflictResolution>("merge").copied();
+
+            match (format, resolution) {
+                (ImportFormat::Export, resolution) => Ok(run_import(
+                    db_path,
+                    file,
+                    resolution.unwrap_or(ConflictResolution::KeepRemote),
+                )?),
+                (ImportFormat::History(format), None) => {
+                    Ok(run_import_history(db_path, file, format)?)
+                }
+                // A shell history always adds its visits to the indexed entries
+                (ImportFormat::History(_), Some(_)) => {
+                    Err(anyhow!("--merge can only be used to import an export"))
+                }
+            }
+        }
+        Some(("sync", sub_m)) => {
+            let file = sub_m.get_one::<String>("file").expect("File is missing");
+
+            Ok(run_sync(db_path, Path::new(file), sub_m.get_flag("write"))?)
+        }
+        Some(("backup", sub_m)) => {
+            let output = sub_m.get_one::<String>("output").map(Path::new);
+
+            match sub_m.get_flag("list") {
+                true => Ok(run_list_backups(db_path, output)?),
+                false => Ok(run_backup(db_path, output)?),
+            }
+        }
+        Some(("restore", sub_m)) => {
+            let file = sub_m.get_one::<String>("file").expect("File is missing");
+
+            Ok(run_restore(
+                db_path,
+                Path::new(file),
+                sub_m.get_flag("yes"),
+            )?)
+        }
+        _ => Ok(()), // Unreachable
+    }
+}
+
+// Defines the command line interface, which is also used to generate the shell completions
+fn build_cli() -> Command {
     let path_arg = Arg::new("path")
         .value_name("PATH")
-        .help("The path to add into the index")
+        .help("The paths to add into the index, use - to read paths from stdin, one per line")
+        .num_args(1..)
+        .required(true);
+
+    let ignore_arg = Arg::new("ignore")
+        .value_name("PATTERN")
+        .long("ignore")
+        .action(ArgAction::Append)
+        .help("Skip paths matching this glob, on top of the ignore patterns in the config file");
+
+    let recursive_arg = Arg::new("recursive")
+        .long("recursive")
+        .short('r')
+        .action(ArgAction::SetTrue)
+        .help("Also add all directories below the given paths");
+
+    let max_depth_arg = Arg::new("max-depth")
+        .value_name("N")
+        .long("max-depth")
+        .requires("recursive")
+        .value_parser(clap::value_parser!(usize))
+        .help("Only add directories up to N levels below the given paths");
+
+    let null_arg = Arg::new("null")
+        .long("null")
+        .short('0')
+        .action(ArgAction::SetTrue)
+        .conflicts_with("recursive")
+        .help("Paths read from stdin are separated by NUL instead of newline characters");
+
+    let if_exists_arg = Arg::new("if-exists")
+        .long("if-exists")
+        .action(ArgAction::SetTrue)
+        .help("Skip paths that are not an existing directory, or everything when the database is in use, instead of failing, for shell hooks");
+
+    let add_weight_arg = Arg::new("weight")
+        .long("weight")
+        .value_name("N")
+        .allow_negative_numbers(true)
+        .value_parser(clap::value_parser!(i64).range(-MAX_WEIGHT..=MAX_WEIGHT))
+        .conflicts_with_all(["recursive", "dry-run"])
+        .help(format!(
+            "Set the weight of the paths, which is added to their score when they match. Between -{0} and {0}",
+            MAX_WEIGHT
+        ));
+
+    let add_dry_run_arg = Arg::new("dry-run")
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .help("Only print what would happen to every path, without changing the index");
+
+    let hidden_arg = Arg::new("hidden")
+        .long("hidden")
+        .requires("recursive")
+        .action(ArgAction::SetTrue)
+        .help("Also add hidden directories (names starting with a `.`)");
+
+    let apply_ignores_arg = Arg::new("apply-ignores")
+        .long("apply-ignores")
+        .action(ArgAction::SetTrue)
+        .help("Also remove paths matching the ignore patterns in the config file");
+
+    let pin_path_arg = Arg::new("path")
+        .value_name("PATH")
+        .help("The path to pin, relative paths are resolved against the current directory")
+        .required(true);
+
+    let unpin_path_arg = Arg::new("path")
+        .value_name("PATH")
+        .help("The path to unpin, relative paths are resolved against the current directory")
         .required(true);
 
+    let weight_path_arg = Arg::new("path")
+        .value_name("PATH")
+        .help(
+            "The indexed path to adjust, relative paths are resolved against the current directory",
+        )
+        .required(true);
+
+    let amount_arg = Arg::new("amount")
+        .value_name("AMOUNT")
+        .help(format!(
+            "How much to adjust the weight by. The weight is kept between -{0} and {0}",
+            MAX_WEIGHT
+        ))
+        .value_parser(clap::value_parser!(i64).range(1..=MAX_WEIGHT))
+        .default_value("10");
+
+    let export_file_arg = Arg::new("file")
+        .value_name("FILE")
+        .help("The file to write the export to, defaults to stdout");
+
+    let import_file_arg = Arg::new("file")
+        .value_name("FILE")
+        .help("The file to read from, defaults to stdin for an export and ~/.bash_history or ~/.zsh_history for a shell history");
+
+    let import_format_arg = Arg::new("format")
+        .value_name("FORMAT")
+        .long("format")
+        .value_parser(
+            PossibleValuesParser::new(["export", "bash-history", "zsh-history"]).map(|format| {
+                match format.as_str() {
+                    "bash-history" => ImportFormat::History(HistoryFormat::Bash),
+                    "zsh-history" => ImportFormat::History(HistoryFormat::Zsh),
+                    _ => ImportFormat::Export,
+                }
+            }),
+        )
+        .default_value("export")
+        .help("Read an export of scotty, or the directories that were changed into with cd or pushd from a shell history");
+
+    let import_merge_arg = Arg::new("merge")
+        .value_name("POLICY")
+        .long("merge")
+        .num_args(0..=1)
+        .require_equals(true)
+        .default_missing_value("keep-newer")
+        .value_parser(
+            PossibleValuesParser::new(["keep-local", "keep-remote", "keep-newer"]).map(|policy| {
+                match policy.as_str() {
+                    "keep-local" => ConflictResolution::KeepLocal,
+                    "keep-remote" => ConflictResolution::KeepRemote,
+                    _ => ConflictResolution::KeepNewer,
+                }
+            }),
+        )
+        .help("Keep the indexed entry of a path instead of replacing it, if it was visited more recently (keep-newer, the default) or always (keep-local)");
+
+    let backup_output_arg = Arg::new("output")
+        .long("output")
+        .short('o')
+        .value_name("DIR")
+        .value_hint(ValueHint::DirPath)
+        .help("The directory to write the backup to, defaults to the backups directory in the data directory of scotty");
+
+    let backup_list_arg = Arg::new("list")
+        .long("list")
+        .short('l')
+        .action(ArgAction::SetTrue)
+        .help("List the existing backups instead of writing a new one");
+
+    let restore_file_arg = Arg::new("file")
+        .value_name("FILE")
+        .value_hint(ValueHint::FilePath)
+        .required(true)
+        .help("The backup to restore");
+
     let target_arg = Arg::new("target")
         .value_name("TARGET")
-        .help("The target to jump to")
+        .help("The target to jump to. Multiple terms must all appear in the path, in any order")
+        .value_hint(ValueHint::DirPath)
+        .num_args(1..)
         .required(true);
 
     let exclude_arg = Arg::new("exclude")
@@ -32,7 +581,40 @@ fn main() -> Result<()> {
         .long("exclude")
         .short('e')
         .number_of_values(1)
-        .help("Exclude the given path from the search results");
+        .action(ArgAction::Append)
+        .help("Exclude the given path from the search results, can be given more than once");
+
+    let include_cwd_arg = Arg::new("include-cwd")
+        .long("include-cwd")
+        .action(ArgAction::SetTrue)
+        .help("Also return the current directory, which is left out by default");
+
+    let skip_current_arg = Arg::new("skip-current")
+        .long("skip-current")
+        .visible_alias("no-current")
+        .action(ArgAction::SetTrue)
+        .conflicts_with("include-cwd")
+        .help(
+            "Also leave out the current directory with its symlinks resolved, when --from is given",
+        );
+
+    let canonical_arg = Arg::new("canonical")
+        .long("canonical")
+        .visible_alias("absolute")
+        .action(ArgAction::SetTrue)
+        .help("Print the path with symlinks resolved, instead of how it is stored in the index");
+
+    let from_arg = Arg::new("from")
+        .value_name("DIR")
+        .long("from")
+        .value_hint(ValueHint::DirPath)
+        .help("The directory the jump starts from, remembers the jump for `scotty back`");
+
+    let history_count_arg = Arg::new("count")
+        .value_name("N")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("10")
+        .help("How many jumps to print");
 
     let all_arg = Arg::new("all")
         .long("all")
@@ -40,125 +622,1670 @@ fn main() -> Result<()> {
         .action(ArgAction::SetTrue)
         .help("Return all matched entries instead of only the most relevant one");
 
+    let limit_arg = Arg::new("limit")
+        .value_name("N")
+        .long("limit")
+        .short('n')
+        .value_parser(clap::value_parser!(usize))
+        .help("Return only the N best matches, together with --all");
+
+    let list_limit_arg = Arg::new("limit")
+        .value_name("N")
+        .long("limit")
+        .short('n')
+        .value_parser(clap::value_parser!(usize))
+        .help("Print only the first N paths, in the order of --sort");
+
+    let count_arg = Arg::new("count")
+        .value_name("N")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("10")
+        .help("How many directories to print");
+
+    let entries_json_arg = Arg::new("json")
+        .long("json")
+        .action(ArgAction::SetTrue)
+        .help("Print the entries as a json array, like list --format json");
+
+    let long_arg = Arg::new("long")
+        .long("long")
+        .short('l')
+        .action(ArgAction::SetTrue)
+        .conflicts_with("json")
+        .help("Print a table with the time of the last visit instead of just the paths");
+
+    let interactive_arg = Arg::new("interactive")
+        .long("interactive")
+        .short('i')
+        .action(ArgAction::SetTrue)
+        .conflicts_with("all")
+        .help("Pick the directory from a list of matches that is updated as you type");
+
+    let prefix_arg = Arg::new("prefix")
+        .long("prefix")
+        .action(ArgAction::SetTrue)
+        .help("Match every term against the start of a path component, instead of fuzzily");
+
+    let case_sensitive_arg = Arg::new("case-sensitive")
+        .long("case-sensitive")
+        .short('s')
+        .action(ArgAction::SetTrue)
+        .conflicts_with("case")
+        .help("Only match paths with the same case as the input, short for --case sensitive");
+
+    let case_arg = Arg::new("case")
+        .long("case")
+        .value_name("MODE")
+        .value_parser(
+            PossibleValuesParser::new(["sensitive", "insensitive", "smart"]).map(|case| {
+                match case.as_str() {
+                    "sensitive" => CaseSensitivity::Sensitive,
+                    "smart" => CaseSensitivity::Smart,
+                    _ => CaseSensitivity::Insensitive,
+                }
+            }),
+        )
+        .help("Whether the case of the input has to match, smart only does so when the input has uppercase characters");
+
+    let basename_arg = Arg::new("basename")
+        .long("basename")
+        .action(ArgAction::SetTrue)
+        .help("Only match the name of a directory, the last component of its path");
+
+    let full_path_arg = Arg::new("full-path")
+        .long("full-path")
+        .action(ArgAction::SetTrue)
+        .conflicts_with("basename")
+        .help("Match the whole path of a directory, when the configuration only matches its name");
+
+    let no_fuzzy_arg = Arg::new("no-fuzzy")
+        .long("no-fuzzy")
+        .action(ArgAction::SetTrue)
+        .help("Don't score how well paths match, the most recently visited match wins");
+
+    let no_cwd_boost_arg = Arg::new("no-cwd-boost")
+        .long("no-cwd-boost")
+        .action(ArgAction::SetTrue)
+        .help("Don't prefer paths below the current directory, rank on the fuzzy match only");
+
+    let older_than_arg = Arg::new("older-than")
+        .value_name("DURATION")
+        .long("older-than")
+        .value_parser(duration::parse_duration)
+        .help("Remove paths that have not been visited for DURATION (e.g. 30d, 4w, 6mo, 1y)");
+
+    let pattern_arg = Arg::new("pattern")
+        .value_name("GLOB")
+        .long("pattern")
+        .value_parser(Glob::new)
+        .help("Remove paths matching GLOB (e.g. '/mnt/external/**' or '*/node_modules/*')");
+
+    let prefer_arg = Arg::new("prefer")
+        .value_name("SPELLING")
+        .long("prefer")
+        .value_parser(
+            PossibleValuesParser::new(["canonical", "most-used"]).map(|prefer| {
+                match prefer.as_str() {
+                    "most-used" => DedupePreference::MostUsed,
+                    _ => DedupePreference::Canonical,
+                }
+            }),
+        )
+        .default_value("canonical")
+        .help("Keep the real path of a directory, or the spelling of it that was visited most");
+
+    let dedupe_dry_run_arg = Arg::new("dry-run")
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .help("Only print the paths that would be merged");
+
+    let touch_path_arg = Arg::new("path")
+        .value_name("PATH")
+        .value_hint(ValueHint::DirPath)
+        .required(true)
+        .help("The path to mark as visited, relative paths are resolved against the current directory");
+
+    let bench_query_arg = Arg::new("query")
+        .value_name("QUERY")
+        .long("query")
+        .default_value("src")
+        .help("The target to search for");
+
+    let iterations_arg = Arg::new("iterations")
+        .value_name("N")
+        .long("iterations")
+        .short('n')
+        .value_parser(clap::value_parser!(u32).range(1..).map(|n| n as usize))
+        .default_value("100")
+        .help("How many times every operation runs");
+
+    let forget_dir_arg = Arg::new("dir")
+        .value_name("DIR")
+        .value_hint(ValueHint::DirPath)
+        .required(true)
+        .help("The directory to forget, together with everything below it");
+
+    let forget_dry_run_arg = Arg::new("dry-run")
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .help("Only print the paths that would be forgotten");
+
+    let hit_path_arg = Arg::new("path")
+        .value_name("PATH")
+        .value_hint(ValueHint::DirPath)
+        .required(true)
+        .help("The path that was jumped to");
+
+    let create_arg = Arg::new("create")
+        .long("create")
+        .value_name("BOOL")
+        .value_parser(clap::value_parser!(bool))
+        .num_args(0..=1)
+        .require_equals(true)
+        .default_value("true")
+        .default_missing_value("true")
+        .help("Add the path if it is not indexed yet, with --create=false that is an error");
+
+    let dry_run_arg = Arg::new("dry-run")
+        .long("dry-run")
+        .requires("pattern")
+        .action(ArgAction::SetTrue)
+        .help("Only print the paths that would be removed");
+
+    let yes_arg = Arg::new("yes")
+        .long("yes")
+        .short('y')
+        .action(ArgAction::SetTrue)
+        .help("Don't ask for confirmation");
+
+    let old_path_arg = Arg::new("old")
+        .value_name("OLD")
+        .help("The indexed path that was moved, relative paths are resolved against the current directory")
+        .required(true);
+
+    let new_path_arg = Arg::new("new")
+        .value_name("NEW")
+        .help("The new location of the path, relative paths are resolved against the current directory")
+        .required(true);
+
+    let move_prefix_arg = Arg::new("prefix")
+        .long("prefix")
+        .action(ArgAction::SetTrue)
+        .help("Also move the entries of every path below OLD");
+
+    let edit_dry_run_arg = Arg::new("dry-run")
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .help("Only print the changes, without applying them");
+
+    let fix_arg = Arg::new("fix")
+        .long("fix")
+        .action(ArgAction::SetTrue)
+        .help("Rebuild the search index from the stored paths and drop invalid paths");
+
     let shell_arg = Arg::new("shell")
         .value_name("SHELL")
         .help(format!(
-            "The shell scotty needs to integrate with. One of: {:?}",
+            "The shell scotty needs to integrate with, detected if not given. One of: {:?}",
             Shell::all_variants()
         ))
         .value_parser(parse_shell)
+        .required(false);
+
+    let mangen_subcommand_arg = Arg::new("subcommand")
+        .help("The subcommand to print the man page of, prints the page of scotty if omitted");
+
+    let completions_shell_arg = Arg::new("shell")
+        .value_name("SHELL")
+        .help("The shell to print the completion script for")
+        .value_parser(clap::value_parser!(clap_complete::Shell))
         .required(true);
 
     let json_arg = Arg::new("json")
         .long("json")
+        .visible_alias("ndjson")
         .action(ArgAction::SetTrue)
         .help("Print output as a series of newline delimited json objects");
 
-    let matches = command!()
+    let since_arg = Arg::new("since")
+        .value_name("DATE")
+        .long("since")
+        .value_parser(parse_date)
+        .help("Only show paths visited on or after DATE (YYYY-MM-DD, YYYY/MM/DD, 7d, 1w)");
+
+    let before_arg = Arg::new("before")
+        .value_name("DATE")
+        .long("before")
+        .value_parser(parse_date)
+        .help("Only show paths visited before DATE (YYYY-MM-DD, YYYY/MM/DD, 7d, 1w)");
+
+    let export_from_arg = Arg::new("from")
+        .value_name("DATE")
+        .long("from")
+        .value_parser(parse_date)
+        .help("Only export paths visited on or after DATE (YYYY-MM-DD, YYYY/MM/DD, 7d, 1w)");
+
+    let export_to_arg = Arg::new("to")
+        .value_name("DATE")
+        .long("to")
+        .value_parser(parse_date)
+        .help("Only export paths visited before DATE (YYYY-MM-DD, YYYY/MM/DD, 7d, 1w)");
+
+    let sort_arg = Arg::new("sort")
+        .value_name("KEY")
+        .long("sort")
+        .value_parser(
+            PossibleValuesParser::new(["path", "time", "count"]).map(|key| match key.as_str() {
+                "path" => SortKey::Path,
+                "count" => SortKey::Count,
+                _ => SortKey::Time,
+            }),
+        )
+        .default_value("time")
+        .help("The order of the paths: alphabetical, most recently or most visited first");
+
+    let format_arg = Arg::new("format")
+        .value_name("FORMAT")
+        .long("format")
+        .value_parser(PossibleValuesParser::new(["json", "ndjson"]).map(|format| {
+            match format.as_str() {
+                "json" => ListFormat::Json,
+                _ => ListFormat::Ndjson,
+            }
+        }))
+        .conflicts_with("json")
+        .help("Print the paths as one json array or as newline delimited json objects");
+
+    let list_pattern_arg = Arg::new("pattern")
+        .value_name("PATTERN")
+        .help("Only show paths that match PATTERN, like a search target does");
+
+    let glob_arg = Arg::new("glob")
+        .long("glob")
+        .action(ArgAction::SetTrue)
+        .requires("pattern")
+        .help("Match PATTERN as a glob against the whole path (e.g. '/home/*/projects/**')");
+
+    let reverse_arg = Arg::new("reverse")
+        .long("reverse")
+        .action(ArgAction::SetTrue)
+        .help("Reverse the order of the paths");
+
+    command!()
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about("Transports you into a directory based on previous usage")
         .subcommand_required(true)
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .short('q')
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Only print errors on stderr, no warnings or skipped entries"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .global(true)
+                .action(ArgAction::Count)
+                .conflicts_with("quiet")
+                .help("Print more details of what scotty does, repeat for even more (-vv, -vvv)"),
+        )
+        .arg(
+            Arg::new("json-errors")
+                .long("json-errors")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Print errors on stderr as a json object with an error message and kind"),
+        )
+        .arg(
+            Arg::new("db-path")
+                .value_name("DIR")
+                .long("db-path")
+                .global(true)
+                .value_hint(ValueHint::DirPath)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Use the database in DIR instead of the one in the data directory"),
+        )
         .subcommand(
             Command::new("add")
                 .about("Add a path to the index")
-                .arg(&path_arg),
+                .arg(&path_arg)
+                .arg(&ignore_arg)
+                .arg(&recursive_arg)
+                .arg(&max_depth_arg)
+                .arg(&hidden_arg)
+                .arg(&null_arg)
+                .arg(&if_exists_arg)
+                .arg(&add_weight_arg)
+                .arg(&add_dry_run_arg),
         )
         .subcommand(
             Command::new("search")
                 .about("Searches a directory based on the input and the current index")
                 .arg(&exclude_arg)
+                .arg(&include_cwd_arg)
+                .arg(&skip_current_arg)
+                .arg(&canonical_arg)
+                .arg(from_arg.clone().conflicts_with("all"))
                 .arg(&all_arg)
+                .arg(&limit_arg)
+                .arg(&interactive_arg)
+                .arg(&prefix_arg)
+                .arg(&basename_arg)
+                .arg(&full_path_arg)
+                .arg(&case_sensitive_arg)
+                .arg(&case_arg)
+                .arg(&no_fuzzy_arg)
+                .arg(&no_cwd_boost_arg)
+                .arg(
+                    target_arg
+                        .clone()
+                        .required(false)
+                        .required_unless_present("interactive"),
+                ),
+        )
+        .subcommand(
+            Command::new("back")
+                .about("Print the directory the last jump of search started from")
+                .arg(from_arg.clone().help(
+                    "The directory the jump back starts from, so back returns there next time",
+                )),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Print the last jumps of search, most recent first")
+                .arg(&history_count_arg),
+        )
+        .subcommand(
+            Command::new("rank")
+                .about("Print every path matching the input with its score, best match first")
+                .arg(&exclude_arg)
+                .arg(&prefix_arg)
+                .arg(&basename_arg)
+                .arg(&full_path_arg)
+                .arg(&case_sensitive_arg)
+                .arg(&case_arg)
+                .arg(&no_fuzzy_arg)
+                .arg(&no_cwd_boost_arg)
+                .arg(&json_arg)
                 .arg(&target_arg),
         )
+        .subcommand(
+            Command::new("remove")
+                .about("Remove paths from the index, pinned paths are never removed")
+                .arg(&older_than_arg)
+                .arg(&pattern_arg)
+                .arg(&dry_run_arg)
+                .group(
+                    ArgGroup::new("selection")
+                        .args(["older-than", "pattern"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Remove paths that are no longer a directory, pinned paths are kept")
+                .arg(&apply_ignores_arg),
+        )
+        .subcommand(
+            Command::new("move")
+                .visible_alias("rename")
+                .about("Move the entry of a renamed directory to its new location")
+                .arg(&move_prefix_arg)
+                .arg(&old_path_arg)
+                .arg(&new_path_arg),
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Edit the paths in the index in $VISUAL or $EDITOR")
+                .arg(&edit_dry_run_arg),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .visible_alias("verify")
+                .about("Check that the search index is consistent with the stored paths")
+                .arg(&fix_arg),
+        )
+        .subcommand(
+            Command::new("dedupe")
+                .about("Merge the paths that resolve to the same directory, like symlinks to it")
+                .arg(&prefer_arg)
+                .arg(&dedupe_dry_run_arg),
+        )
+        .subcommand(
+            Command::new("rebuild-index")
+                .visible_alias("rebuild")
+                .about(
+                "Rebuild the search index from the stored paths, e.g. after editing the database",
+            ),
+        )
+        .subcommand(
+            Command::new("db-path")
+                .about("Print where the database is stored, whether it exists and its size"),
+        )
+        .subcommand(
+            Command::new("compact")
+                .about("Rewrite the database to reclaim the disk space it has grown to"),
+        )
+        .subcommand(
+            Command::new("clear")
+                .about("Remove all paths from the index")
+                .arg(&yes_arg),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Revert the last add of a new path, remove, prune or clear of the index"),
+        )
         .subcommand(
             Command::new("init")
                 .about("Integrates scotty in your shell")
                 .arg(&shell_arg),
         )
+        .subcommand(
+            Command::new("completions")
+                .about("Print the tab completion script of the scotty cli for a shell")
+                .arg(&completions_shell_arg),
+        )
+        .subcommand(
+            Command::new("mangen")
+                .about("Print the man page of scotty, or of one of its subcommands, in roff")
+                .hide(true)
+                .arg(&mangen_subcommand_arg),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Time searching and adding paths, to see whether the index got too large")
+                .arg(&bench_query_arg)
+                .arg(&iterations_arg),
+        )
+        .subcommand(
+            Command::new("forget")
+                .about(
+                    "Remove a directory and every path below it from the index, even pinned ones",
+                )
+                .arg(&forget_dir_arg)
+                .arg(&forget_dry_run_arg),
+        )
+        .subcommand(
+            Command::new("hit")
+                .about(
+                    "Record a jump to a path found by search, which ranks it higher than a visit",
+                )
+                .arg(&hit_path_arg),
+        )
+        .subcommand(
+            Command::new("touch")
+                .about("Mark a path as just visited, without counting a visit")
+                .arg(&touch_path_arg)
+                .arg(&create_arg),
+        )
+        .subcommand(
+            Command::new("pin")
+                .about("Pin a path, so it is preferred over any unpinned path it matches")
+                .arg(&pin_path_arg),
+        )
+        .subcommand(
+            Command::new("unpin")
+                .about("Remove the pin from a path")
+                .arg(&unpin_path_arg),
+        )
+        .subcommand(
+            Command::new("boost")
+                .about("Increase the weight of a path, so it ranks higher when it matches")
+                .arg(&weight_path_arg)
+                .arg(&amount_arg),
+        )
+        .subcommand(
+            Command::new("demote")
+                .about("Decrease the weight of a path, so it ranks lower when it matches")
+                .arg(&weight_path_arg)
+                .arg(&amount_arg),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export the index as json, which can be read back by import")
+                .arg(&export_file_arg)
+                .arg(&export_from_arg)
+                .arg(&export_to_arg),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import entries from an export, replacing the entries for known paths")
+                .arg(&import_file_arg)
+                .arg(&import_format_arg)
+                .arg(&import_merge_arg),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Merge an export from another machine into the index, keeping the newest entry of every path")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .required(true)
+                        .value_hint(ValueHint::FilePath)
+                        .help("The export to merge, e.g. one in a synced folder"),
+                )
+                .arg(
+                    Arg::new("write")
+                        .long("write")
+                        .short('w')
+                        .action(ArgAction::SetTrue)
+                        .help("Replace FILE by the merged index, so the other machine can merge it back"),
+                ),
+        )
+        .subcommand(
+            Command::new("backup")
+                .about("Write a timestamped export of the index, which can be read back by restore")
+                .arg(&backup_output_arg)
+                .arg(&backup_list_arg),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Replace all paths in the index by the ones in a backup")
+                .arg(&restore_file_arg)
+                .arg(&yes_arg),
+        )
+        .subcommand(
+            Command::new("top")
+                .about("Print the most visited directories, most visited first")
+                .arg(&count_arg)
+                .arg(&entries_json_arg),
+        )
+        .subcommand(
+            Command::new("recent")
+                .about("Print the most recently visited directories, most recent first")
+                .arg(&count_arg)
+                .arg(&long_arg)
+                .arg(&entries_json_arg),
+        )
         .subcommand(
             Command::new("list")
                 .about("Print the current index")
-                .arg(&json_arg),
+                .arg(&format_arg)
+                .arg(
+                    json_arg
+                        .clone()
+                        .help("Deprecated, use --format ndjson. Prints the raw index entries"),
+                )
+                .arg(&since_arg)
+                .arg(&before_arg)
+                .arg(&sort_arg)
+                .arg(&reverse_arg)
+                .arg(&list_limit_arg)
+                .arg(&glob_arg)
+                .arg(&list_pattern_arg),
         )
-        .get_matches();
+}
 
-    match matches.subcommand() {
-        Some(("add", sub_m)) => {
-            let path = sub_m.get_one::<String>("path").expect("Path is missing");
+// Prints warnings and errors on stderr, every verbose flag adds a more detailed level
+// RUST_LOG can change the levels, unless quiet or verbose is set
+fn init_logger(quiet: bool, verbose: u8) {
+    let level = get_log_level(quiet, verbose);
+    let builder = EnvFilter::builder().with_default_directive(level.into());
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(filters) if !quiet && verbose == 0 => builder.parse_lossy(filters),
+        _ => builder.parse_lossy(""),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .init();
+}
+
+fn get_log_level(quiet: bool, verbose: u8) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    }
+}
+
+fn parse_shell(shell: &str) -> Result<Shell, init::ShellError> {
+    Shell::try_from(shell)
+}
+
+fn parse_date(date: &str) -> Result<SystemTime, date::DateParseError> {
+    date::parse_date(date, SystemTime::now())
+}
+
+// Checks if an error is caused by another process having the database open
+fn is_database_locked(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<IndexError>(),
+        Some(IndexError::DatabaseLocked(_))
+    )
+}
+
+// Returns the database given with --db-path, or the default location
+fn index_path(db_path: Option<&Path>) -> Result<PathBuf> {
+    match db_path {
+        Some(db_path) => Ok(db_path.to_path_buf()),
+        None => Ok(config::get_index_path()?),
+    }
+}
+
+// Opens the index at db_path or its default location, configured with the user settings
+fn open_index(db_path: Option<&Path>) -> Result<Index> {
+    index_builder(&[])?.open(&index_path(db_path)?)
+}
+
+// Opens the index like open_index, ignoring extra_ignores on top of the configured patterns
+fn open_index_with_ignores(db_path: Option<&Path>, extra_ignores: &[String]) -> Result<Index> {
+    index_builder(extra_ignores)?.open(&index_path(db_path)?)
+}
+
+// Configures the index with the settings from the config file
+fn index_builder(extra_ignores: &[String]) -> Result<IndexBuilder> {
+    let mut settings = config::load_config()?;
+    settings.ignore.extend_from_slice(extra_ignores);
+    Ok(IndexBuilder::new()
+        .half_life(settings.half_life())
+        .debounce(settings.debounce())
+        .max_age(settings.max_age())
+        .max_entries(settings.max_entries)
+        .missing_penalty(settings.missing_penalty)
+        .matcher(settings.matcher)
+        .case_sensitivity(settings.case_sensitivity())
+        .basename(settings.basename)
+        .frecency_weight(settings.frecency_weight)
+        .name_bonus(settings.name_bonus)
+        .ignore_patterns(&settings.ignore))
+}
 
-            Ok(run_add(path)?)
+fn run_add(
+    db_path: Option<&Path>,
+    paths: &[PathBuf],
+    ignore: &[String],
+    weight: Option<i64>,
+) -> Result<()> {
+    tracing::debug!(
+        "Running add with paths: {:?}, ignores: {:?} and weight: {:?}",
+        paths,
+        ignore,
+        weight
+    );
+    let index = open_index_with_ignores(db_path, ignore)?;
+    if let [path] = paths {
+        index.add(path)?;
+        return set_weights(&index, paths, weight);
+    }
+
+    let summary = index.add_many(paths)?;
+    set_weights(&index, paths, weight)?;
+    report_rejected(&summary.rejected, paths.len())
+}
+
+// Sets the weight of the paths that were added, if one was given
+// Paths that were ignored or rejected are not in the index and are skipped
+fn set_weights(index: &Index, paths: &[PathBuf], weight: Option<i64>) -> Result<()> {
+    let weight = match weight {
+        Some(weight) => weight,
+        None => return Ok(()),
+    };
+    for path in paths {
+        if index.contains(path)? {
+            index.set_weight(path, weight)?;
         }
-        Some(("search", sub_m)) => {
-            let target = sub_m
-                .get_one::<String>("target")
-                .expect("Target is missing");
-            let excluded_path = sub_m.get_one::<String>("exclude").map(Path::new);
-            let find_all = sub_m.get_flag("all");
+    }
+    Ok(())
+}
 
-            Ok(run_search(target, excluded_path, find_all)?)
+// Drops the paths that are not an existing directory, like a network mount that is gone
+fn existing_dirs(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    paths
+        .into_iter()
+        .filter(|path| {
+            let is_dir = path.is_dir();
+            if !is_dir {
+                tracing::debug!("Skipping path that does not exist: {}", path.display());
+            }
+            is_dir
+        })
+        .collect()
+}
+
+// Adds the paths read from stdin, every valid path is added before the invalid ones are reported
+fn run_add_stdin(
+    db_path: Option<&Path>,
+    ignore: &[String],
+    delimiter: u8,
+    weight: Option<i64>,
+) -> Result<()> {
+    tracing::debug!(
+        "Running add from stdin with ignores: {:?} and delimiter: {:?}",
+        ignore,
+        delimiter as char
+    );
+    let paths = read_paths(io::stdin().lock(), delimiter)?;
+    let index = open_index_with_ignores(db_path, ignore)?;
+    let summary = index.add_many(&paths)?;
+    set_weights(&index, &paths, weight)?;
+    report_rejected(&summary.rejected, paths.len())
+}
+
+// Reads the paths separated by delimiter, ignoring empty ones
+// Newline separated paths are trimmed, NUL separated paths are taken as they are
+fn read_paths<R: BufRead>(reader: R, delimiter: u8) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for item in reader.split(delimiter) {
+        let mut bytes = item?;
+        if delimiter == b'\n' {
+            bytes = bytes.trim_ascii().to_vec();
         }
-        Some(("init", sub_m)) => {
-            let shell = sub_m.get_one("shell").expect("Shell is missing");
+        if !bytes.is_empty() {
+            paths.push(path_from_stdin(bytes)?);
+        }
+    }
+    Ok(paths)
+}
 
-            Ok(run_init(shell)?)
+// Paths on unix are bytes, so they are read without requiring them to be UTF-8
+#[cfg(unix)]
+fn path_from_stdin(bytes: Vec<u8>) -> Result<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_stdin(bytes: Vec<u8>) -> Result<PathBuf> {
+    Ok(PathBuf::from(String::from_utf8(bytes)?))
+}
+
+fn run_add_recursive(
+    db_path: Option<&Path>,
+    roots: &[PathBuf],
+    ignore: &[String],
+    max_depth: Option<usize>,
+    hidden: bool,
+) -> Result<()> {
+    tracing::debug!(
+        "Running recursive add with roots: {:?}, max depth: {:?} and hidden: {}",
+        roots,
+        max_depth,
+        hidden
+    );
+    let index = open_index_with_ignores(db_path, ignore)?;
+    let paths = collect_subdirectories(roots, max_depth, hidden)?;
+
+    let summary = index.add_many(&paths)?;
+    let skipped = paths.len() - summary.added - summary.ignored - summary.rejected.len();
+    println!(
+        "Added {} directories, skipped {} (already indexed).",
+        summary.added, skipped
+    );
+    report_rejected(&summary.rejected, paths.len())
+}
+
+// Returns the roots and every directory below them, relative roots are made absolute
+fn collect_subdirectories(
+    roots: &[PathBuf],
+    max_depth: Option<usize>,
+    hidden: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for root in roots {
+        paths.extend(walk::collect_subdirectories(
+            &std::path::absolute(root)?,
+            max_depth,
+            hidden,
+        ));
+    }
+    Ok(paths)
+}
+
+// Prints what adding the paths would do, without changing the index
+// Fails like add would when a path is not valid
+fn run_add_dry_run(db_path: Option<&Path>, paths: &[PathBuf], ignore: &[String]) -> Result<()> {
+    tracing::debug!(
+        "Running add dry run with paths: {:?} and ignores: {:?}",
+        paths,
+        ignore
+    );
+    let index = open_index_with_ignores(db_path, ignore)?;
+    let mut rejected = 0;
+    for path in paths {
+        match index.check_add(path)? {
+            AddAction::Insert => println!("would add: {}", path.display()),
+            AddAction::Refresh => println!(
+                "already indexed, would refresh timestamp: {}",
+                path.display()
+            ),
+            AddAction::Ignore => println!("ignored: {}", path.display()),
+            AddAction::Reject(error) => {
+                rejected += 1;
+                println!("rejected: {}", error)
+            }
         }
-        Some(("list", sub_m)) => {
-            let is_json = sub_m.get_flag("json");
+    }
+    if rejected > 0 {
+        return Err(anyhow!(
+            "{} of {} paths could not be added",
+            rejected,
+            paths.len()
+        ));
+    }
+    Ok(())
+}
 
-            Ok(run_list(is_json)?)
+// Prints why paths could not be added and turns them into an error
+fn report_rejected(rejected: &[(PathBuf, IndexError)], total: usize) -> Result<()> {
+    if rejected.is_empty() {
+        return Ok(());
+    }
+    for (_, error) in rejected {
+        eprintln!("{}", error);
+    }
+    Err(anyhow!(
+        "{} of {} paths could not be added",
+        rejected.len(),
+        total
+    ))
+}
+
+// How a search matches and scores paths, set on the command line of search and rank
+#[derive(Debug, Clone, Copy)]
+struct SearchOptions {
+    match_mode: MatchMode,
+    // Without --case or --case-sensitive the configured case sensitivity applies
+    case_sensitivity: Option<CaseSensitivity>,
+    // Without --basename or --full-path the configured setting applies
+    basename: Option<bool>,
+    fuzzy_scoring: bool,
+}
+
+impl SearchOptions {
+    fn from_matches(sub_m: &ArgMatches) -> SearchOptions {
+        SearchOptions {
+            match_mode: if sub_m.get_flag("prefix") {
+                MatchMode::Prefix
+            } else {
+                MatchMode::Fuzzy
+            },
+            case_sensitivity: if sub_m.get_flag("case-sensitive") {
+                Some(CaseSensitivity::Sensitive)
+            } else {
+                sub_m.get_one::<CaseSensitivity>("case").copied()
+            },
+            basename: if sub_m.get_flag("basename") {
+                Some(true)
+            } else if sub_m.get_flag("full-path") {
+                Some(false)
+            } else {
+                None
+            },
+            fuzzy_scoring: !sub_m.get_flag("no-fuzzy"),
         }
-        _ => Ok(()), // Unreachable
     }
 }
 
-fn parse_shell(shell: &str) -> Result<Shell, init::ShellError> {
-    Shell::try_from(shell)
+// Opens the index like open_index, with the options of a search
+fn open_search_index(db_path: Option<&Path>, options: SearchOptions) -> Result<Index> {
+    let mut builder = index_builder(&[])?
+        .match_mode(options.match_mode)
+        .fuzzy_scoring(options.fuzzy_scoring);
+    if let Some(case_sensitivity) = options.case_sensitivity {
+        builder = builder.case_sensitivity(case_sensitivity);
+    }
+    if let Some(basename) = options.basename {
+        builder = builder.basename(basename);
+    }
+    builder.open(&index_path(db_path)?)
 }
 
-fn run_add(path: &str) -> Result<()> {
-    log::debug!("Running add with path: {}", path);
-    let index = Index::open(config::get_index_config()?)?;
-    let path_buf = PathBuf::from(path);
-    index.add(&path_buf)?;
-    Ok(())
+// The formats import reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImportFormat {
+    Export,
+    History(HistoryFormat),
+}
+
+// What search prints
+#[derive(Debug, Clone, Copy)]
+enum SearchResults<'a> {
+    // The best match, the jump is remembered if it starts from a directory
+    Best { from: Option<&'a Path> },
+    // Every match, or the best limit ones
+    All { limit: Option<usize> },
+}
+
+// Returns the paths given with --exclude
+fn excluded_paths(matches: &ArgMatches) -> impl Iterator<Item = &Path> {
+    matches
+        .get_many::<String>("exclude")
+        .into_iter()
+        .flatten()
+        .map(Path::new)
 }
 
-fn run_search(target: &str, exclude: Option<&Path>, find_all: bool) -> Result<()> {
-    log::debug!("Running search with target: {}", target);
+fn run_search(
+    db_path: Option<&Path>,
+    terms: &[&str],
+    exclude: &[&Path],
+    results: SearchResults,
+    options: SearchOptions,
+    cwd_boost: bool,
+    canonical: bool,
+) -> Result<()> {
+    tracing::debug!(
+        "Running search with terms: {:?} and options: {:?}",
+        terms,
+        options
+    );
+
+    let index = open_search_index(db_path, options)?;
+    let cwd = if cwd_boost {
+        std::env::current_dir().ok()
+    } else {
+        None
+    };
 
-    let index = Index::open(config::get_index_config()?)?;
+    let from = match results {
+        SearchResults::Best { from } => from,
+        SearchResults::All { limit } => {
+            return print_all_matches(&index, terms, exclude, limit, cwd.as_deref(), canonical)
+        }
+    };
 
-    if find_all {
-        return printer::print_path_slice(&index.find_all(target, exclude)?);
+    // Directories that no longer exist rank last, prune removes them from the index
+    match index.find_one(terms, exclude, cwd.as_deref())? {
+        None => Err(IndexError::NoResults(terms.join(" ")).into()),
+        Some(directory) => {
+            if let Some(from) = from {
+                index.record_jump(&std::path::absolute(from)?, &directory)?;
+            }
+            println!("{}", display_path(directory, canonical).display());
+            Ok(())
+        }
     }
+}
+
+// Prints every match of search --all, or the best limit ones
+fn print_all_matches(
+    index: &Index,
+    terms: &[&str],
+    exclude: &[&Path],
+    limit: Option<usize>,
+    cwd: Option<&Path>,
+    canonical: bool,
+) -> Result<()> {
+    let paths = match limit {
+        Some(n) => index.find_n(terms, n, exclude, cwd)?,
+        None => index.find_all(terms, exclude)?,
+    };
+    let paths = paths
+        .into_iter()
+        .map(|path| display_path(path, canonical))
+        .collect::<Vec<_>>();
+    printer::print_path_slice(&paths)
+}
 
-    loop {
-        let directory = match index.find_one(target, exclude)? {
-            None => return Err(IndexError::NoResults(target.to_owned()).into()),
-            Some(d) => d,
-        };
-        if !directory.is_dir() {
-            index.delete(&directory)?;
-        } else {
-            println!("{}", directory.display());
-            break;
+// Returns the path search prints: with symlinks resolved if canonical is set, unless it can't
+// be resolved because it no longer exists
+fn display_path(path: PathBuf, canonical: bool) -> PathBuf {
+    if !canonical {
+        return path;
+    }
+    match fs::canonicalize(&path) {
+        Ok(real_path) => real_path,
+        Err(e) => {
+            tracing::debug!("Can't resolve {}: {}", path.display(), e);
+            path
         }
     }
+}
+
+fn run_back(db_path: Option<&Path>, from: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running back from: {:?}", from);
+    let index = open_index(db_path)?;
+    let previous = index.previous_location()?.ok_or(IndexError::NoHistory)?;
+    // Jumping back is a jump as well, so running back again returns to where we were
+    if let Some(from) = from {
+        index.record_jump(&std::path::absolute(from)?, &previous)?;
+    }
+    println!("{}", previous.display());
     Ok(())
 }
 
-fn run_list(is_json: bool) -> Result<()> {
-    log::debug!("Running list with raw output: {}", is_json);
-    let index = Index::open(config::get_index_config()?)?;
+fn run_history(db_path: Option<&Path>, count: usize) -> Result<()> {
+    tracing::debug!("Running history with count: {}", count);
+    let mut history = open_index(db_path)?.history()?;
+    history.truncate(count);
+    printer::print_history(&history)
+}
+
+fn run_search_interactive(db_path: Option<&Path>, query: &str, canonical: bool) -> Result<()> {
+    tracing::debug!("Running interactive search with query: {}", query);
+    match interactive::run_interactive(|| open_index(db_path), query)? {
+        Some(directory) => {
+            println!("{}", display_path(directory, canonical).display());
+            Ok(())
+        }
+        None => Err(InteractiveError::Cancelled.into()),
+    }
+}
+
+fn run_rank(
+    db_path: Option<&Path>,
+    terms: &[&str],
+    exclude: &[&Path],
+    options: SearchOptions,
+    cwd_boost: bool,
+    is_json: bool,
+) -> Result<()> {
+    tracing::debug!(
+        "Running rank with terms: {:?} and options: {:?}",
+        terms,
+        options
+    );
+
+    let index = open_search_index(db_path, options)?;
+    let cwd = if cwd_boost {
+        std::env::current_dir().ok()
+    } else {
+        None
+    };
+
+    let scores = index.rank(terms, exclude, cwd.as_deref())?;
     if is_json {
-        printer::print_json(&index.list()?)
+        printer::print_json(&scores)
     } else {
-        printer::print_human(&index.list()?)
+        printer::print_scores(&scores)
+    }
+}
+
+fn run_remove_older_than(db_path: Option<&Path>, older_than: Duration) -> Result<()> {
+    tracing::debug!("Running remove with older than: {:?}", older_than);
+    let index = open_index(db_path)?;
+    let removed = index.expire(older_than)?;
+    for path in &removed {
+        println!("{}", path.display());
     }
+    println!("Removed {} paths", removed.len());
+    Ok(())
+}
+
+fn run_remove_pattern(db_path: Option<&Path>, pattern: &Glob, dry_run: bool) -> Result<()> {
+    tracing::debug!(
+        "Running remove with pattern: {} and dry run: {}",
+        pattern,
+        dry_run
+    );
+    let index = open_index(db_path)?;
+    let matcher = pattern.compile_matcher();
+    let entries = index.list()?;
+    let matched = entries
+        .iter()
+        .filter(|entry| !entry.pinned && matcher.is_match(&entry.path))
+        .map(|entry| entry.path.as_path())
+        .collect::<Vec<_>>();
+
+    for path in &matched {
+        println!("{}", path.display());
+    }
+    if dry_run {
+        println!("Would remove {} paths", matched.len());
+        return Ok(());
+    }
+    println!("Removed {} paths", index.delete_many(&matched)?);
+    Ok(())
+}
+
+fn run_prune(db_path: Option<&Path>, apply_ignores: bool) -> Result<()> {
+    tracing::debug!("Running prune with apply ignores: {}", apply_ignores);
+    let index = open_index(db_path)?;
+    let removed = index.clean(apply_ignores)?;
+    for path in &removed {
+        println!("{}", path.display());
+    }
+    println!("Removed {} paths", removed.len());
+    Ok(())
+}
+
+fn run_move(db_path: Option<&Path>, old: &Path, new: &Path, prefix: bool) -> Result<()> {
+    tracing::debug!(
+        "Running move with old: {}, new: {} and prefix: {}",
+        old.display(),
+        new.display(),
+        prefix
+    );
+    let index = open_index(db_path)?;
+    let (old, new) = (std::path::absolute(old)?, std::path::absolute(new)?);
+    let moved = if prefix {
+        index.rename_prefix(&old, &new)?
+    } else {
+        index.rename(&old, &new)?;
+        1
+    };
+    println!("Moved {} paths", moved);
+    Ok(())
+}
+
+fn run_edit(db_path: Option<&Path>, dry_run: bool) -> Result<()> {
+    tracing::debug!("Running edit with dry run: {}", dry_run);
+    // Paths that are not valid UTF-8 can't be edited as text, they are left untouched
+    let paths = open_index(db_path)?
+        .list()?
+        .into_iter()
+        .map(|entry| entry.path)
+        .filter(|path| path.to_str().is_some())
+        .collect::<Vec<_>>();
+    let edited = edit::edit_paths(&paths, &config::get_cache_dir()?)?;
+    let diff = edit::diff_paths(&paths, &edited)?;
+
+    for path in &diff.removed {
+        println!("- {}", path.display());
+    }
+    for path in &diff.added {
+        println!("+ {}", path.display());
+    }
+    if dry_run || diff.is_empty() {
+        return Ok(());
+    }
+    // The database is locked while it is open, so it isn't kept open while the user edits
+    let index = open_index(db_path)?;
+    index.delete_many(
+        &diff
+            .removed
+            .iter()
+            .map(PathBuf::as_path)
+            .collect::<Vec<_>>(),
+    )?;
+    let summary = index.add_many(&diff.added)?;
+    report_rejected(&summary.rejected, diff.added.len())
+}
+
+fn run_doctor(db_path: Option<&Path>, fix: bool) -> Result<()> {
+    tracing::debug!("Running doctor with fix: {}", fix);
+    let index = open_index(db_path)?;
+    let report = index.verify()?;
+    for path in &report.missing_from_fst {
+        println!("Not searchable: {}", path.display());
+    }
+    for path in &report.missing_from_paths {
+        println!("Not stored: {}", path.display());
+    }
+    for path in &report.invalid_paths {
+        println!("Invalid: {}", path.display());
+    }
+    if report.is_consistent() {
+        println!("The index is consistent");
+        return Ok(());
+    }
+    if !fix {
+        return Err(anyhow!(
+            "The index is not consistent, run doctor --fix to repair it"
+        ));
+    }
+    index.rebuild_fst()?;
+    println!("Rebuilt the search index");
+    Ok(())
+}
+
+fn run_bench(db_path: Option<&Path>, query: &str, iterations: usize) -> Result<()> {
+    tracing::debug!(
+        "Running bench with query: {} and iterations: {}",
+        query,
+        iterations
+    );
+    let index = open_index(db_path)?;
+    let report = bench::run_bench(&index, query, iterations, &config::get_cache_dir()?)?;
+    println!("Paths:        {}", report.stats.paths);
+    println!("Search index: {} bytes", report.stats.fst_bytes);
+    println!("Database:     {} bytes", report.stats.disk_bytes);
+    println!("find_one:     {}", report.find_one);
+    println!("add:          {}", report.add);
+    Ok(())
+}
+
+fn run_forget(db_path: Option<&Path>, dir: &Path, dry_run: bool) -> Result<()> {
+    tracing::debug!(
+        "Running forget with dir: {:?} and dry run: {}",
+        dir,
+        dry_run
+    );
+    let index = open_index(db_path)?;
+    let dir = std::path::absolute(dir)?;
+    if dry_run {
+        let paths = index.list_below(&dir)?;
+        for path in &paths {
+            println!("{}", path.display());
+        }
+        println!("Would forget {} paths", paths.len());
+        return Ok(());
+    }
+    println!("Forgot {} paths", index.forget(&dir)?.len());
+    Ok(())
+}
+
+fn run_hit(db_path: Option<&Path>, path: &Path) -> Result<()> {
+    tracing::debug!("Running hit with path: {:?}", path);
+    let index = open_index(db_path)?;
+    index.hit(&std::path::absolute(path)?)
+}
+
+fn run_touch(db_path: Option<&Path>, path: &Path, create: bool) -> Result<()> {
+    tracing::debug!("Running touch with path: {:?} and create: {}", path, create);
+    let index = open_index(db_path)?;
+    index.touch(&std::path::absolute(path)?, create)
+}
+
+fn run_dedupe(db_path: Option<&Path>, prefer: DedupePreference, dry_run: bool) -> Result<()> {
+    tracing::debug!(
+        "Running dedupe with prefer: {:?} and dry run: {}",
+        prefer,
+        dry_run
+    );
+    let index = open_index(db_path)?;
+    let groups = if dry_run {
+        index.find_duplicates(prefer)?
+    } else {
+        index.dedupe(prefer)?
+    };
+    let verb = if dry_run { "Would merge" } else { "Merged" };
+    for group in &groups {
+        for path in &group.merged {
+            println!("{} {} into {}", verb, path.display(), group.kept.display());
+        }
+    }
+    if groups.is_empty() {
+        println!("No paths resolve to the same directory");
+    }
+    Ok(())
+}
+
+fn run_rebuild_index(db_path: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running rebuild-index");
+    let index = open_index(db_path)?;
+    let len = index.rebuild_fst()?;
+    println!("Rebuilt the search index with {} paths", len);
+    Ok(())
+}
+
+fn run_db_path(db_path: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running db-path");
+    // The database is not opened, that would create it and wait for other processes
+    let db_path = index_path(db_path)?;
+    println!("Database: {}", db_path.display());
+    match config::disk_usage(&db_path) {
+        Ok(size) => println!("Size:     {} bytes", size),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("Size:     does not exist yet")
+        }
+        Err(e) => return Err(e.into()),
+    }
+    println!("Cache:    {}", config::get_data_paths()?.cache.display());
+    Ok(())
+}
+
+fn run_compact(db_path: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running compact");
+    let report = Index::compact_path(&index_path(db_path)?)?;
+    println!(
+        "Reclaimed {} bytes ({} -> {} bytes)",
+        report.reclaimed_bytes(),
+        report.before_bytes,
+        report.after_bytes
+    );
+    Ok(())
+}
+
+fn run_clear(db_path: Option<&Path>, yes: bool) -> Result<()> {
+    tracing::debug!("Running clear with yes: {}", yes);
+    let db_path = index_path(db_path)?;
+    // Opening the index recreates a database in an incompatible format
+    let index = Index::open_path(&db_path)?;
+    if !yes && !confirm(&format!("Remove all paths from {}?", db_path.display()))? {
+        return Ok(());
+    }
+    index.clear()?;
+    println!("Cleared {}", db_path.display());
+    Ok(())
+}
+
+fn run_undo(db_path: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running undo");
+    let report = open_index(db_path)?
+        .undo()?
+        .ok_or(IndexError::NothingToUndo)?;
+    println!(
+        "Undid {}: restored {} paths, removed {} paths",
+        report.operation,
+        report.restored.len(),
+        report.removed.len()
+    );
+    Ok(())
+}
+
+// Asks the user a yes/no question on stderr, anything but an explicit yes counts as no
+fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Selects the entries that list prints
+#[derive(Debug)]
+enum ListFilter {
+    // The paths that search --all finds for the pattern
+    Search(String),
+    Glob(GlobMatcher),
+}
+
+fn run_list(
+    db_path: Option<&Path>,
+    format: ListFormat,
+    range: TimeRange,
+    sort_key: SortKey,
+    reverse: bool,
+    filter: Option<ListFilter>,
+    limit: Option<usize>,
+) -> Result<()> {
+    tracing::debug!(
+        "Running list with format: {:?}, range: {:?}, sort: {:?}, filter: {:?} and limit: {:?}",
+        format,
+        range,
+        sort_key,
+        filter,
+        limit
+    );
+    let index = open_index(db_path)?;
+    let mut entries = index.list()?;
+    entries.retain(|entry| range.contains(entry.timestamp));
+    match filter {
+        Some(ListFilter::Search(pattern)) => {
+            let matches = index
+                .find_all(&[pattern.as_str()], &[])?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            entries.retain(|entry| matches.contains(&entry.path));
+        }
+        Some(ListFilter::Glob(matcher)) => entries.retain(|entry| matcher.is_match(&entry.path)),
+        None => {}
+    }
+    printer::sort_entries(&mut entries, sort_key, reverse);
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+    printer::print_list(&entries, format)
+}
+
+fn run_top(db_path: Option<&Path>, count: usize, json: bool) -> Result<()> {
+    tracing::debug!("Running top with count: {} and json: {}", count, json);
+    let mut entries = open_index(db_path)?.list()?;
+    printer::sort_entries(&mut entries, SortKey::Count, false);
+    entries.truncate(count);
+    if json {
+        printer::print_list(&entries, ListFormat::Json)
+    } else {
+        printer::print_top(&entries)
+    }
+}
+
+// How recent prints the entries
+#[derive(Debug, Clone, Copy)]
+enum RecentFormat {
+    // One path per line
+    Paths,
+    Table,
+    Json,
+}
+
+fn run_recent(db_path: Option<&Path>, count: usize, format: RecentFormat) -> Result<()> {
+    tracing::debug!(
+        "Running recent with count: {} and format: {:?}",
+        count,
+        format
+    );
+    let entries = open_index(db_path)?.recent(count)?;
+    match format {
+        RecentFormat::Paths => {
+            let mut handle = BufWriter::new(io::stdout().lock());
+            for entry in &entries {
+                writeln!(handle, "{}", entry.path.display())?;
+            }
+            Ok(handle.flush()?)
+        }
+        RecentFormat::Table => printer::print_human(&entries),
+        RecentFormat::Json => printer::print_list(&entries, ListFormat::Json),
+    }
+}
+
+fn run_pin(db_path: Option<&Path>, path: &Path, pinned: bool) -> Result<()> {
+    tracing::debug!(
+        "Running pin with path: {} and pinned: {}",
+        path.display(),
+        pinned
+    );
+    let index = open_index(db_path)?;
+    let path_buf = std::path::absolute(path)?;
+    if pinned {
+        index.pin(&path_buf)
+    } else {
+        index.unpin(&path_buf)
+    }
+}
+
+fn run_adjust_weight(db_path: Option<&Path>, path: &Path, delta: i64) -> Result<()> {
+    tracing::debug!(
+        "Running adjust weight with path: {} and delta: {}",
+        path.display(),
+        delta
+    );
+    let index = open_index(db_path)?;
+    let weight = index.adjust_weight(&std::path::absolute(path)?, delta)?;
+    println!("{}", weight);
+    Ok(())
+}
+
+fn run_export(db_path: Option<&Path>, file: Option<&Path>, range: TimeRange) -> Result<()> {
+    tracing::debug!(
+        "Running export with file: {:?} and range: {:?}",
+        file,
+        range
+    );
+    let entries = export::list_range(&open_index(db_path)?, range)?;
+    match file {
+        Some(path) => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            export::write_export(&mut writer, &entries)?;
+            Ok(writer.flush()?)
+        }
+        None => {
+            let mut writer = BufWriter::new(io::stdout().lock());
+            export::write_export(&mut writer, &entries)?;
+            writeln!(writer)?;
+            Ok(writer.flush()?)
+        }
+    }
+}
+
+fn run_import(
+    db_path: Option<&Path>,
+    file: Option<&Path>,
+    resolution: ConflictResolution,
+) -> Result<()> {
+    tracing::debug!(
+        "Running import with file: {:?} and resolution: {:?}",
+        file,
+        resolution
+    );
+    let entries = match file {
+        Some(path) => export::read_export(BufReader::new(File::open(path)?))?,
+        None => export::read_export(io::stdin().lock())?,
+    };
+    let index = open_index(db_path)?;
+    let summary = index.import_with(&entries, resolution)?;
+    for (_, error) in &summary.rejected {
+        tracing::warn!("Skipped: {}", error);
+    }
+    println!(
+        "Added {} new paths, replaced {} and kept {} local records, skipped {} ignored and {} invalid entries",
+        summary.added,
+        summary.replaced,
+        summary.kept,
+        summary.ignored,
+        summary.rejected.len()
+    );
+    Ok(())
+}
+
+fn run_sync(db_path: Option<&Path>, file: &Path, write: bool) -> Result<()> {
+    tracing::debug!("Running sync with file: {:?} and write: {}", file, write);
+    let remote = export::read_export(BufReader::new(File::open(file)?))?
+        .iter()
+        .map(ExportEntry::from)
+        .collect::<Vec<_>>();
+    let index = open_index(db_path)?;
+    let local = index
+        .list()?
+        .iter()
+        .map(ExportEntry::from)
+        .collect::<Vec<_>>();
+    let merged = sync::merge_indexes(&local, &remote)
+        .into_iter()
+        .map(PathIndexEntry::from)
+        .collect::<Vec<_>>();
+
+    // Paths that don't exist on this machine are only kept in the merged export
+    let rejected = index.import(&merged)?;
+    for (_, error) in &rejected {
+        tracing::debug!("Skipped: {}", error);
+    }
+    println!(
+        "Merged {} local and {} remote entries into {} entries",
+        local.len(),
+        remote.len(),
+        merged.len()
+    );
+    if !rejected.is_empty() {
+        println!(
+            "Skipped {} entries that are not a directory on this machine",
+            rejected.len()
+        );
+    }
+    if write {
+        export::write_export_file(file, &merged)?;
+        println!("Wrote the merged entries to {}", file.display());
+    }
+    Ok(())
+}
+
+fn run_import_history(
+    db_path: Option<&Path>,
+    file: Option<&Path>,
+    format: HistoryFormat,
+) -> Result<()> {
+    tracing::debug!(
+        "Running import of {:?} history with file: {:?}",
+        format,
+        file
+    );
+    let home = expand::home_dir().ok_or(ShellHistoryError::NoHomeDirectory)?;
+    let file = match file {
+        Some(path) => path.to_path_buf(),
+        None => home.join(format.file_name()),
+    };
+    let history = File::open(&file)?;
+    // Without timestamps in the history, the visits happened before it was last written
+    let fallback_time = history
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+    let entries =
+        shell_history::read_history(format, BufReader::new(history), &home, fallback_time)?;
+    let index = open_index(db_path)?;
+    let rejected = index.merge(&entries)?;
+    for (_, error) in &rejected {
+        tracing::debug!("Skipped: {}", error);
+    }
+    println!(
+        "Imported {} of {} directories from {}",
+        entries.len() - rejected.len(),
+        entries.len(),
+        file.display()
+    );
+    Ok(())
+}
+
+fn run_backup(db_path: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running backup with output: {:?}", output);
+    let dir = backup_dir(db_path, output)?;
+    let entries = open_index(db_path)?.list()?;
+    let backup_path = backup::write_backup(&dir, &entries, chrono::Utc::now())?;
+    println!(
+        "Backed up {} entries to {}",
+        entries.len(),
+        backup_path.display()
+    );
+    Ok(())
+}
+
+fn run_list_backups(db_path: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    tracing::debug!("Running backup --list with output: {:?}", output);
+    let dir = backup_dir(db_path, output)?;
+    for backup_path in backup::list_backups(&dir)? {
+        println!("{}", backup_path.display());
+    }
+    Ok(())
+}
+
+// Returns the directory given on the command line, or the default backups directory
+fn backup_dir(db_path: Option<&Path>, output: Option<&Path>) -> Result<PathBuf> {
+    Ok(match output {
+        Some(dir) => dir.to_path_buf(),
+        None => config::get_backup_dir(&index_path(db_path)?),
+    })
+}
+
+fn run_restore(db_path: Option<&Path>, file: &Path, yes: bool) -> Result<()> {
+    tracing::debug!("Running restore with file: {:?} and yes: {}", file, yes);
+    // Read the backup first, so a backup with an unknown schema never touches the index
+    let entries = export::read_export(BufReader::new(File::open(file)?))?;
+    let question = format!(
+        "Replace all paths in the index by the {} entries in {}?",
+        entries.len(),
+        file.display()
+    );
+    if !yes && !confirm(&question)? {
+        return Ok(());
+    }
+    // The database is locked while it is open, so it is only opened once the user answered
+    let rejected = open_index(db_path)?.replace(&entries)?;
+    for (_, error) in &rejected {
+        tracing::warn!("Skipped: {}", error);
+    }
+    println!(
+        "Restored {} of {} entries",
+        entries.len() - rejected.len(),
+        entries.len()
+    );
+    Ok(())
 }
 
 fn run_init(shell: &Shell) -> Result<()> {
-    log::debug!("Running init with shell: {:?}", shell);
+    tracing::debug!("Running init with shell: {:?}", shell);
     Ok(init::init_shell(shell)?)
 }
+
+fn run_completions(shell: clap_complete::Shell) -> Result<()> {
+    tracing::debug!("Running completions with shell: {}", shell);
+    Ok(completions::write_completions(
+        shell,
+        &mut build_cli(),
+        &mut io::stdout(),
+    )?)
+}
+
+fn run_mangen(subcommand: Option<&str>) -> Result<()> {
+    tracing::debug!("Running mangen with subcommand: {:?}", subcommand);
+    manpage::write_man_page(build_cli(), subcommand, &mut io::stdout())
+}
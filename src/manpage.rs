@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{self, Write};
+
+use clap::Command;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ManPageError {
+    #[error("Unknown subcommand: {0}")]
+    UnknownSubcommand(String),
+}
+
+/// Writes the man page of the cli in roff, or the one of a subcommand if its name is given
+/// Subcommand pages are named like `scotty-add`, the way git names its pages
+pub fn write_man_page(
+    cli: Command,
+    subcommand: Option<&str>,
+    buf: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let page = match subcommand {
+        Some(name) => subcommand_page(&cli, name)?,
+        None => cli,
+    };
+    render(page, buf)?;
+    Ok(())
+}
+
+fn subcommand_page(cli: &Command, name: &str) -> Result<Command, ManPageError> {
+    let subcommand = cli
+        .get_subcommands()
+        .find(|sub| !sub.is_hide_set() && sub.get_name() == name)
+        .ok_or_else(|| ManPageError::UnknownSubcommand(name.to_owned()))?;
+    let page_name = format!("{}-{}", cli.get_name(), name);
+    Ok(subcommand
+        .clone()
+        .name(page_name)
+        .version(cli.get_version().unwrap_or_default().to_owned()))
+}
+
+fn render(page: Command, buf: &mut dyn Write) -> io::Result<()> {
+    clap_mangen::Man::new(page).render(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    fn test_cli() -> Command {
+        Command::new("scotty")
+            .version("1.0.0")
+            .subcommand(
+                Command::new("search")
+                    .about("Search the index")
+                    .arg(Arg::new("target")),
+            )
+            .subcommand(Command::new("secret").hide(true))
+    }
+
+    fn render_to_string(subcommand: Option<&str>) -> anyhow::Result<String> {
+        let mut buf = Vec::new();
+        write_man_page(test_cli(), subcommand, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    #[test]
+    fn write_man_page_cli() {
+        let page = render_to_string(None).unwrap();
+
+        assert!(page.starts_with(".ie"));
+        assert!(page.contains(".TH scotty 1"));
+        assert!(page.contains("scotty\\-search"))
+    }
+
+    #[test]
+    fn write_man_page_subcommand() {
+        let page = render_to_string(Some("search")).unwrap();
+
+        assert!(page.contains(".TH scotty-search 1"));
+        assert!(page.contains("Search the index"))
+    }
+
+    #[test]
+    fn write_man_page_unknown_subcommand() {
+        for name in ["missing", "secret"] {
+            let error = render_to_string(Some(name)).unwrap_err();
+
+            assert_eq!(
+                error.downcast_ref::<ManPageError>(),
+                Some(&ManPageError::UnknownSubcommand(name.to_owned()))
+            )
+        }
+    }
+}
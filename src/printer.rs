@@ -3,13 +3,99 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use anyhow::Result;
-use chrono::DateTime;
+use chrono::{DateTime, SecondsFormat, Utc};
 use std::convert::TryInto;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::index::PathIndexEntry;
+use serde::Serialize;
+
+use scotty::{IndexError, Jump, PathIndexEntry, Score};
+
+use crate::config::DirectoryError;
+use crate::export::ExportError;
+use crate::interactive::InteractiveError;
+use crate::shell_history::ShellHistoryError;
+
+/// The order in which the entries of the index are listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Alphabetically by path
+    Path,
+    /// Most recently visited first
+    Time,
+    /// Most visited first, ties are broken by the most recent visit
+    Count,
+}
+
+/// The version of the json objects printed by `list --format`, bumped on incompatible changes
+pub const LIST_SCHEMA: u32 = 1;
+
+/// How `list` prints the entries of the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    /// A human readable table
+    Table,
+    /// A single json array of entries
+    Json,
+    /// One json object per line
+    Ndjson,
+    /// The deprecated `--json` output, which serializes the timestamp as serde's SystemTime
+    LegacyNdjson,
+}
+
+// The json representation of an index entry, documented by LIST_SCHEMA
+// The timestamp and epoch are null if the timestamp can't be represented
+#[derive(Debug, Serialize)]
+struct ListEntry<'a> {
+    schema: u32,
+    path: &'a Path,
+    timestamp: Option<String>,
+    epoch: Option<i64>,
+    count: u64,
+    pinned: bool,
+    weight: i64,
+    hits: u64,
+}
+
+impl<'a> From<&'a PathIndexEntry> for ListEntry<'a> {
+    fn from(entry: &'a PathIndexEntry) -> Self {
+        let datetime = get_datetime(&entry.timestamp);
+        ListEntry {
+            schema: LIST_SCHEMA,
+            path: &entry.path,
+            timestamp: datetime.map(|dt| dt.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            epoch: datetime.map(|dt| dt.timestamp()),
+            count: entry.count,
+            pinned: entry.pinned,
+            weight: entry.weight,
+            hits: entry.hits,
+        }
+    }
+}
+
+// Sorts the entries on key, reverse turns the order around
+pub fn sort_entries(entries: &mut [PathIndexEntry], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Path => entries.sort_by(|a, b| a.path.cmp(&b.path)),
+        // Ties are broken by the path, so the order doesn't depend on how the index stores them
+        SortKey::Time => entries.sort_by(|a, b| {
+            b.timestamp
+                .cmp(&a.timestamp)
+                .then_with(|| a.path.cmp(&b.path))
+        }),
+        SortKey::Count => entries.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+                .then_with(|| a.path.cmp(&b.path))
+        }),
+    }
+    if reverse {
+        entries.reverse();
+    }
+}
 
 // Prints a slice of PathBufs in a single line seperated by a space
 pub fn print_path_slice(paths: &[PathBuf]) -> Result<()> {
@@ -23,8 +109,8 @@ pub fn print_path_slice(paths: &[PathBuf]) -> Result<()> {
     Ok(())
 }
 
-// Prints the Vec of index entries as line delimited json objects on stdout
-pub fn print_json(index_entries: &[PathIndexEntry]) -> Result<()> {
+// Prints the Vec of index entries (or scores) as line delimited json objects on stdout
+pub fn print_json<T: Serialize>(index_entries: &[T]) -> Result<()> {
     let stdout = io::stdout();
     let std_lock = stdout.lock();
     let mut handle = io::BufWriter::new(std_lock);
@@ -36,7 +122,107 @@ pub fn print_json(index_entries: &[PathIndexEntry]) -> Result<()> {
     Ok(())
 }
 
+/// An error as it is printed in json, so scripts can handle failures without parsing text
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub error: String,
+    pub kind: &'static str,
+}
+
+impl From<&anyhow::Error> for JsonError {
+    fn from(error: &anyhow::Error) -> Self {
+        JsonError {
+            // The alternate format includes the context that was added to the error
+            error: format!("{:#}", error),
+            kind: error_kind(error),
+        }
+    }
+}
+
+// Returns the kind of the error that caused the failure, "other" if it has no specific kind
+fn error_kind(error: &anyhow::Error) -> &'static str {
+    if let Some(e) = error.downcast_ref::<IndexError>() {
+        e.kind()
+    } else if let Some(e) = error.downcast_ref::<DirectoryError>() {
+        match e {
+            DirectoryError::NoDataDirectory => "no_data_directory",
+            DirectoryError::NoCacheDirectory => "no_cache_directory",
+        }
+    } else if let Some(InteractiveError::Cancelled) = error.downcast_ref::<InteractiveError>() {
+        "cancelled"
+    } else if let Some(ExportError::UnsupportedSchema(_)) = error.downcast_ref::<ExportError>() {
+        "unsupported_export_schema"
+    } else if let Some(ShellHistoryError::NoHomeDirectory) =
+        error.downcast_ref::<ShellHistoryError>()
+    {
+        "no_home_directory"
+    } else if error.downcast_ref::<io::Error>().is_some() {
+        "io"
+    } else {
+        "other"
+    }
+}
+
+// Prints the error as a single json object on stderr
+pub fn print_json_error(error: &anyhow::Error) -> Result<()> {
+    let mut handle = io::stderr().lock();
+    serde_json::to_writer(&mut handle, &JsonError::from(error))?;
+    writeln!(handle)?;
+    Ok(())
+}
+
+// Prints the index entries on stdout in the given format
+pub fn print_list(index_entries: &[PathIndexEntry], format: ListFormat) -> Result<()> {
+    match format {
+        ListFormat::Table => print_human(index_entries),
+        ListFormat::LegacyNdjson => print_json(
+            &index_entries
+                .iter()
+                .filter(|entry| is_json_path(&entry.path))
+                .collect::<Vec<_>>(),
+        ),
+        ListFormat::Json | ListFormat::Ndjson => {
+            let stdout = io::stdout();
+            let mut handle = io::BufWriter::new(stdout.lock());
+            write_list_json(&mut handle, index_entries, format == ListFormat::Json)?;
+            Ok(handle.flush()?)
+        }
+    }
+}
+
+// Writes the index entries as json, either as one array or as one object per line
+fn write_list_json<W: Write>(
+    writer: &mut W,
+    index_entries: &[PathIndexEntry],
+    as_array: bool,
+) -> Result<()> {
+    let entries = index_entries
+        .iter()
+        .filter(|entry| is_json_path(&entry.path))
+        .map(ListEntry::from);
+    if as_array {
+        serde_json::to_writer(&mut *writer, &entries.collect::<Vec<_>>())?;
+        writeln!(writer)?;
+    } else {
+        for entry in entries {
+            serde_json::to_writer(&mut *writer, &entry)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+// JSON can only represent paths that are valid UTF-8, other paths are skipped with a warning
+fn is_json_path(path: &Path) -> bool {
+    let is_utf8 = path.to_str().is_some();
+    if !is_utf8 {
+        tracing::warn!("Skipping non UTF-8 path: {}", path.display());
+    }
+    is_utf8
+}
+
 // Prints the Vec of index entries as a human readable table on stdout
+// Pinned paths are marked with a `*`
 pub fn print_human(index_entries: &[PathIndexEntry]) -> Result<()> {
     let stdout = io::stdout();
     let std_lock = stdout.lock();
@@ -48,8 +234,9 @@ pub fn print_human(index_entries: &[PathIndexEntry]) -> Result<()> {
     for entry in index_entries {
         writeln!(
             tab_handle,
-            "{}\t{}",
+            "{}{}\t{}",
             entry.path.display(),
+            if entry.pinned { " *" } else { "" },
             get_datetime_string(&entry.timestamp)
         )?;
     }
@@ -57,17 +244,305 @@ pub fn print_human(index_entries: &[PathIndexEntry]) -> Result<()> {
     Ok(tab_handle.flush()?)
 }
 
+// Prints the visit count and path of the index entries as a table on stdout, in the order given
+pub fn print_top(index_entries: &[PathIndexEntry]) -> Result<()> {
+    let stdout = io::stdout();
+    let mut handle = io::BufWriter::new(stdout.lock());
+    write_top(&mut handle, index_entries)?;
+    Ok(handle.flush()?)
+}
+
+fn write_top<W: Write>(writer: W, index_entries: &[PathIndexEntry]) -> Result<()> {
+    let mut tab_handle = tabwriter::TabWriter::new(writer);
+
+    writeln!(tab_handle, "VISITS\tPATH")?;
+    for entry in index_entries {
+        writeln!(tab_handle, "{}\t{}", entry.count, entry.path.display())?;
+    }
+
+    Ok(tab_handle.flush()?)
+}
+
+// Prints the jumps as a human readable table on stdout, in the order given
+pub fn print_history(history: &[Jump]) -> Result<()> {
+    let stdout = io::stdout();
+    let handle = io::BufWriter::new(stdout.lock());
+    let mut tab_handle = tabwriter::TabWriter::new(handle);
+
+    writeln!(tab_handle, "TIMESTAMP\tFROM\tTO")?;
+    for jump in history {
+        writeln!(
+            tab_handle,
+            "{}\t{}\t{}",
+            get_datetime_string(&jump.timestamp),
+            jump.from.display(),
+            jump.to.display()
+        )?;
+    }
+
+    Ok(tab_handle.flush()?)
+}
+
+// Prints the scores of a search as a human readable table on stdout, in the order given
+// Pinned paths are marked with a `*`
+pub fn print_scores(scores: &[Score]) -> Result<()> {
+    let stdout = io::stdout();
+    let std_lock = stdout.lock();
+    let handle = io::BufWriter::new(std_lock);
+    let mut tab_handle = tabwriter::TabWriter::new(handle);
+
+    writeln!(
+        tab_handle,
+        "PATH\tTOTAL\tSCORE\tWEIGHT\tBONUS\tPENALTY\tFRECENCY\tTIMESTAMP"
+    )?;
+
+    for score in scores {
+        writeln!(
+            tab_handle,
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{:.2}\t{}",
+            score.path.display(),
+            if score.pinned { " *" } else { "" },
+            score.weighted_score(),
+            score.score,
+            score.weight,
+            score.bonus,
+            score.penalty,
+            score.frecency,
+            score
+                .timestamp
+                .as_ref()
+                .map_or_else(|| "-".to_owned(), get_datetime_string)
+        )?;
+    }
+
+    Ok(tab_handle.flush()?)
+}
+
 // Converts a systemtime into a human readable string
-// Panics if the time is before the UNIX_EPOCH or if the number of seconds after the epoch does not fit in a int64
-// (This should be about 292471208677 years, so I'm ok to run with that assumption)
+// Timestamps that chrono can't represent (before the UNIX_EPOCH or too far into the future)
+// are rendered as "invalid", so one bad entry doesn't prevent printing the rest of the index
 fn get_datetime_string(systime: &SystemTime) -> String {
-    let duration = systime
-        .duration_since(UNIX_EPOCH)
-        .expect("timestamp should be after UNIX_EPOCH");
-    let datetime = DateTime::from_timestamp(
-        duration.as_secs().try_into().unwrap(),
-        duration.subsec_nanos(),
-    )
-    .expect("timestamp out of range");
-    format!("{}", datetime)
+    get_datetime(systime)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "invalid".to_owned())
+}
+
+// Converts a systemtime into a chrono datetime, if chrono can represent it
+fn get_datetime(systime: &SystemTime) -> Option<DateTime<Utc>> {
+    let duration = systime.duration_since(UNIX_EPOCH).ok()?;
+    DateTime::from_timestamp(duration.as_secs().try_into().ok()?, duration.subsec_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn json_error_kinds() {
+        let errors = [
+            (
+                anyhow::Error::from(IndexError::NoResults("foo".to_owned())),
+                "no_results",
+            ),
+            (InteractiveError::Cancelled.into(), "cancelled"),
+            (
+                ExportError::UnsupportedSchema(2).into(),
+                "unsupported_export_schema",
+            ),
+            (io::Error::from(io::ErrorKind::NotFound).into(), "io"),
+            (anyhow::anyhow!("something else"), "other"),
+        ];
+
+        for (error, kind) in errors {
+            assert_eq!(JsonError::from(&error).kind, kind)
+        }
+    }
+
+    #[test]
+    fn json_error_format() {
+        let error =
+            anyhow::Error::from(IndexError::NoResults("foo".to_owned())).context("Search failed");
+
+        let result = serde_json::to_value(JsonError::from(&error)).unwrap();
+
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "error": "Search failed: No path found for pattern `foo`",
+                "kind": "no_results"
+            })
+        )
+    }
+
+    fn entry(path: &str, secs: u64, count: u64) -> PathIndexEntry {
+        PathIndexEntry {
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+            path: PathBuf::from(path),
+            count,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        }
+    }
+
+    fn sorted_paths(key: SortKey, reverse: bool) -> Vec<PathBuf> {
+        let mut entries = vec![
+            entry("/b", 300, 1),
+            entry("/c", 100, 5),
+            entry("/a", 200, 5),
+        ];
+        sort_entries(&mut entries, key, reverse);
+        entries.into_iter().map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn sort_entries_time() {
+        assert_eq!(
+            sorted_paths(SortKey::Time, false),
+            ["/b", "/a", "/c"].map(PathBuf::from)
+        );
+        assert_eq!(
+            sorted_paths(SortKey::Time, true),
+            ["/c", "/a", "/b"].map(PathBuf::from)
+        )
+    }
+
+    #[test]
+    fn sort_entries_path() {
+        assert_eq!(
+            sorted_paths(SortKey::Path, false),
+            ["/a", "/b", "/c"].map(PathBuf::from)
+        );
+        assert_eq!(
+            sorted_paths(SortKey::Path, true),
+            ["/c", "/b", "/a"].map(PathBuf::from)
+        )
+    }
+
+    #[test]
+    fn sort_entries_count() {
+        assert_eq!(
+            sorted_paths(SortKey::Count, false),
+            ["/a", "/c", "/b"].map(PathBuf::from)
+        );
+        assert_eq!(
+            sorted_paths(SortKey::Count, true),
+            ["/b", "/c", "/a"].map(PathBuf::from)
+        )
+    }
+
+    #[test]
+    fn sort_entries_ties() {
+        let mut entries = vec![entry("/b", 100, 1), entry("/a", 100, 1)];
+
+        sort_entries(&mut entries, SortKey::Time, false);
+        assert_eq!(entries[0].path, PathBuf::from("/a"));
+        sort_entries(&mut entries, SortKey::Count, true);
+        assert_eq!(entries[0].path, PathBuf::from("/b"))
+    }
+
+    #[test]
+    fn write_top_table() {
+        let entries = vec![entry("/a", 0, 12), entry("/bar", 0, 3)];
+        let mut output = Vec::new();
+
+        write_top(&mut output, &entries).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "VISITS  PATH\n12      /a\n3       /bar\n"
+        )
+    }
+
+    fn list_json(entries: &[PathIndexEntry], as_array: bool) -> String {
+        let mut output = Vec::new();
+        write_list_json(&mut output, entries, as_array).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn write_list_json_array() {
+        let entries = vec![entry("/a", 1_700_000_000, 2), entry("/b", 0, 1)];
+
+        assert_eq!(
+            list_json(&entries, true),
+            concat!(
+                r#"[{"schema":1,"path":"/a","timestamp":"2023-11-14T22:13:20Z","epoch":1700000000,"count":2,"pinned":false,"weight":0,"hits":0},"#,
+                r#"{"schema":1,"path":"/b","timestamp":"1970-01-01T00:00:00Z","epoch":0,"count":1,"pinned":false,"weight":0,"hits":0}]"#,
+                "\n"
+            )
+        )
+    }
+
+    #[test]
+    fn write_list_json_lines() {
+        let entries = vec![entry("/a", 1_700_000_000, 2), entry("/b", 0, 1)];
+
+        let output = list_json(&entries, false);
+
+        assert_eq!(output.lines().count(), 2);
+        for line in output.lines() {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(value["schema"], LIST_SCHEMA);
+        }
+    }
+
+    #[test]
+    fn write_list_json_empty_array() {
+        assert_eq!(list_json(&[], true), "[]\n");
+        assert_eq!(list_json(&[], false), "")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_list_json_skips_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut invalid = entry("/a", 0, 1);
+        invalid.path = PathBuf::from(OsStr::from_bytes(b"/foo\xffbar"));
+        let entries = vec![invalid, entry("/b", 0, 1)];
+
+        let array: serde_json::Value = serde_json::from_str(&list_json(&entries, true)).unwrap();
+        assert_eq!(array.as_array().unwrap().len(), 1);
+        assert_eq!(array[0]["path"], "/b");
+        let lines = list_json(&entries, false);
+        assert_eq!(lines.lines().count(), 1);
+        assert!(lines.contains("\"/b\""))
+    }
+
+    #[test]
+    fn write_list_json_unrepresentable_timestamp() {
+        let mut invalid = entry("/a", 0, 1);
+        invalid.timestamp = UNIX_EPOCH
+            .checked_add(Duration::from_secs(i64::MAX as u64))
+            .unwrap();
+
+        let value: serde_json::Value = serde_json::from_str(&list_json(&[invalid], false)).unwrap();
+
+        assert!(value["timestamp"].is_null());
+        assert!(value["epoch"].is_null())
+    }
+
+    #[test]
+    fn datetime_string_epoch() {
+        assert_eq!(get_datetime_string(&UNIX_EPOCH), "1970-01-01 00:00:00 UTC")
+    }
+
+    #[test]
+    fn datetime_string_recent() {
+        let input = UNIX_EPOCH + Duration::new(1_700_000_000, 500);
+
+        assert_eq!(get_datetime_string(&input), "2023-11-14 22:13:20 UTC")
+    }
+
+    #[test]
+    fn datetime_string_out_of_range() {
+        let input = UNIX_EPOCH
+            .checked_add(Duration::from_secs(i64::MAX as u64))
+            .unwrap();
+
+        assert_eq!(get_datetime_string(&input), "invalid")
+    }
 }
@@ -1,6 +1,6 @@
 # chpwd hook
 scotty_chpwd() {
-    __SCOTTY__ add "$(pwd)" > /dev/null
+    __SCOTTY__ --quiet add --if-exists "$(pwd)" > /dev/null
 }
 
 case $PROMPT_COMMAND in
@@ -12,13 +12,22 @@ case $PROMPT_COMMAND in
 esac
 
 s() {
-    local output="$(__SCOTTY__ search -e "$(pwd)" ${1})"
+    local output="$(__SCOTTY__ search --from "$(pwd)" "$@")"
     if [[ -d "${output}" ]]; then
         if [[ -t 1 ]]; then # Use color if stdout is a terminal
             echo -e "\\033[31m${output}\\033[0m"
         else
             echo "${output}"
         fi
+        cd "${output}" && __SCOTTY__ --quiet hit "${output}" > /dev/null
+    else
+        false
+    fi
+}
+
+sb() {
+    local output="$(__SCOTTY__ back --from "$(pwd)")"
+    if [[ -d "${output}" ]]; then
         cd "${output}"
     else
         false
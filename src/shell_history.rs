@@ -0,0 +1,408 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use thiserror::Error;
+
+use scotty::PathIndexEntry;
+
+use crate::expand;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ShellHistoryError {
+    #[error("Could not determine the home directory to resolve the history against")]
+    NoHomeDirectory,
+}
+
+// zsh stores the bytes it uses internally as this byte followed by the original byte xor 32
+const ZSH_META: u8 = 0x83;
+
+/// The shells whose history can be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    Bash,
+    /// The extended history format, plain lines without a timestamp are read as well
+    Zsh,
+}
+
+impl HistoryFormat {
+    /// The name of the file in the home directory the shell keeps its history in by default
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            HistoryFormat::Bash => ".bash_history",
+            HistoryFormat::Zsh => ".zsh_history",
+        }
+    }
+}
+
+/// A cd or pushd command found in a shell history
+#[derive(Debug, PartialEq, Eq)]
+struct Visit {
+    target: String,
+    /// The time the command ran, if the history records it
+    timestamp: Option<SystemTime>,
+}
+
+/// Reads a shell history and returns an entry for every directory that was changed into with
+/// cd or pushd, with the number of times it was and the time of the last one
+/// Relative targets are resolved against home, the directory they were run in is not recorded.
+/// Commands without a timestamp count as a visit at fallback_time
+pub fn read_history<R: BufRead>(
+    format: HistoryFormat,
+    reader: R,
+    home: &Path,
+    fallback_time: SystemTime,
+) -> Result<Vec<PathIndexEntry>> {
+    let visits = match format {
+        HistoryFormat::Bash => parse_bash_history(reader)?,
+        HistoryFormat::Zsh => parse_zsh_history(reader)?,
+    };
+    let mut entries = BTreeMap::<PathBuf, PathIndexEntry>::new();
+    for visit in visits {
+        let path = resolve_target(&visit.target, home);
+        let timestamp = visit.timestamp.unwrap_or(fallback_time);
+        let entry = entries
+            .entry(path.clone())
+            .or_insert_with(|| PathIndexEntry {
+                timestamp,
+                path,
+                count: 0,
+                pinned: false,
+                weight: 0,
+                hits: 0,
+            });
+        entry.count += 1;
+        entry.timestamp = entry.timestamp.max(timestamp);
+    }
+    Ok(entries.into_values().collect())
+}
+
+fn parse_bash_history<R: BufRead>(reader: R) -> Result<Vec<Visit>> {
+    let mut visits = Vec::new();
+    let mut timestamp = None;
+    // A history file is not necessarily valid UTF-8
+    for line in reader.split(b'\n') {
+        let line = String::from_utf8_lossy(&line?).into_owned();
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            timestamp = comment.parse::<u64>().ok().and_then(from_epoch_secs);
+            continue;
+        }
+        if let Some(target) = cd_target(line) {
+            visits.push(Visit { target, timestamp });
+        }
+        // The timestamp only belongs to the command that follows it
+        timestamp = None;
+    }
+    Ok(visits)
+}
+
+// Returns the time that is secs seconds after the unix epoch, if it can be represented
+fn from_epoch_secs(secs: u64) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+fn parse_zsh_history<R: BufRead>(reader: R) -> Result<Vec<Visit>> {
+    let mut visits = Vec::new();
+    let mut lines = reader.split(b'\n');
+    while let Some(line) = lines.next() {
+        let mut entry = unmetafy(&line?);
+        // A command that spans several lines continues after a backslash at the end of a line
+        while entry.ends_with(b"\\") {
+            match lines.next() {
+                Some(next) => {
+                    entry.pop();
+                    entry.push(b'\n');
+                    entry.extend(unmetafy(&next?));
+                }
+                None => break,
+            }
+        }
+        let entry = String::from_utf8_lossy(&entry);
+        let (timestamp, command) = split_zsh_entry(&entry);
+        if let Some(target) = cd_target(command.trim()) {
+            visits.push(Visit { target, timestamp });
+        }
+    }
+    Ok(visits)
+}
+
+// Splits an entry of the extended history, `: <start>:<elapsed>;<command>`, into the time the
+// command started and the command. Other entries, and a start that can't be represented, are
+// a command without a timestamp
+fn split_zsh_entry(entry: &str) -> (Option<SystemTime>, &str) {
+    let extended = entry.strip_prefix(": ").and_then(|rest| {
+        let (metadata, command) = rest.split_once(';')?;
+        let (start, _elapsed) = metadata.split_once(':')?;
+        let secs = start.trim().parse::<u64>().ok()?;
+        Some((from_epoch_secs(secs), command))
+    });
+    extended.unwrap_or((None, entry))
+}
+
+// Restores the bytes zsh escaped with ZSH_META when it wrote the history
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            ZSH_META => result.extend(bytes.next().map(|byte| byte ^ 32)),
+            byte => result.push(byte),
+        }
+    }
+    result
+}
+
+// Returns the directory a cd or pushd command changes into, None for any other command and
+// for the ones that don't name a directory, like a bare cd, cd - or pushd +1
+fn cd_target(command: &str) -> Option<String> {
+    let mut words = split_command(command).into_iter();
+    if !matches!(words.next()?.as_str(), "cd" | "pushd") {
+        return None;
+    }
+    let target = words.find(|word| word == "-" || !word.starts_with('-'))?;
+    if target == "-" || target.starts_with('+') {
+        return None;
+    }
+    Some(target)
+}
+
+// Splits the first command on a line into words and removes the quotes, like the shell does
+// The command ends at a `;`, `&` or `|` outside of quotes
+fn split_command(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' | '&' | '|' => break,
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => word
+                .get_or_insert_with(String::new)
+                .extend(chars.by_ref().take_while(|&c| c != '\'')),
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        // Within double quotes a backslash only escapes these characters
+                        '\\' => match chars.next() {
+                            Some(next @ ('"' | '\\' | '$' | '`')) => word.push(next),
+                            Some(next) => word.extend(['\\', next]),
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+// Resolves the target of a cd against home, expanding `~` and variables
+// `..` is resolved by dropping the previous component, which is what cd does by default
+fn resolve_target(target: &str, home: &Path) -> PathBuf {
+    let mut path = PathBuf::new();
+    for component in home.join(expand::expand_target(target)).components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::CurDir => {}
+            component => path.push(component),
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visit(target: &str, timestamp: Option<u64>) -> Visit {
+        Visit {
+            target: target.to_owned(),
+            timestamp: timestamp.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        }
+    }
+
+    #[test]
+    fn cd_target_commands() {
+        assert_eq!(cd_target("cd /src"), Some("/src".to_owned()));
+        assert_eq!(cd_target("  pushd  projects "), Some("projects".to_owned()));
+        assert_eq!(cd_target("cd -P -- /src"), Some("/src".to_owned()));
+        assert_eq!(cd_target("cd /src && make"), Some("/src".to_owned()));
+        assert_eq!(cd_target("cd /src;ls"), Some("/src".to_owned()));
+        for command in [
+            "cd",
+            "cd -",
+            "pushd +1",
+            "ls /src",
+            "cdx /src",
+            "echo cd /src",
+        ] {
+            assert_eq!(cd_target(command), None, "{}", command)
+        }
+    }
+
+    #[test]
+    fn split_command_quotes() {
+        assert_eq!(
+            split_command(r#"cd "my dir"/'sub dir'"#),
+            vec!["cd", "my dir/sub dir"]
+        );
+        assert_eq!(split_command(r"cd my\ dir"), vec!["cd", "my dir"]);
+        assert_eq!(
+            split_command(r#"cd "a\"b\n;c" | less"#),
+            vec!["cd", r#"a"b\n;c"#]
+        );
+        assert_eq!(split_command("cd ''"), vec!["cd", ""]);
+        assert!(split_command("   ").is_empty())
+    }
+
+    #[test]
+    fn parse_bash_history_timestamps() {
+        let history =
+            "#1700000000\ncd /src\nls\ncd /tmp\n#1700000100\npushd /var\n#not a time\ncd /\n";
+
+        assert_eq!(
+            parse_bash_history(history.as_bytes()).unwrap(),
+            vec![
+                visit("/src", Some(1700000000)),
+                visit("/tmp", None),
+                visit("/var", Some(1700000100)),
+                visit("/", None),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_bash_history_out_of_range_timestamp() {
+        let history = "#18446744073709551615\ncd /tmp\n";
+
+        assert_eq!(
+            parse_bash_history(history.as_bytes()).unwrap(),
+            vec![visit("/tmp", None)]
+        )
+    }
+
+    #[test]
+    fn parse_bash_history_invalid_utf8() {
+        let history = b"cd /caf\xe9\ncd /src\n";
+
+        let visits = parse_bash_history(&history[..]).unwrap();
+
+        assert_eq!(visits.len(), 2);
+        assert_eq!(visits[1], visit("/src", None))
+    }
+
+    #[test]
+    fn parse_zsh_history_extended() {
+        let history =
+            ": 1700000000:0;cd /src\n: 1700000005:3;make\n: 1700000100:0;cd /tmp && ls\ncd /var\n";
+
+        assert_eq!(
+            parse_zsh_history(history.as_bytes()).unwrap(),
+            vec![
+                visit("/src", Some(1700000000)),
+                visit("/tmp", Some(1700000100)),
+                visit("/var", None),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_zsh_history_continued_lines() {
+        let history = ": 1700000000:0;cd \\\n/src\n: 1700000001:0;echo foo \\\ncd /tmp\n: 1700000002:0;cd /var\n";
+
+        assert_eq!(
+            parse_zsh_history(history.as_bytes()).unwrap(),
+            vec![
+                visit("/src", Some(1700000000)),
+                visit("/var", Some(1700000002)),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_zsh_history_metafied() {
+        // é is 0xc3 0xa9, zsh escapes the second byte
+        let history = b": 1700000000:0;cd /caf\xc3\x83\x89\n";
+
+        assert_eq!(
+            parse_zsh_history(&history[..]).unwrap(),
+            vec![visit("/caf\u{e9}", Some(1700000000))]
+        )
+    }
+
+    #[test]
+    fn split_zsh_entry_out_of_range_timestamp() {
+        assert_eq!(
+            split_zsh_entry(": 18446744073709551615:0;cd /tmp"),
+            (None, "cd /tmp")
+        )
+    }
+
+    #[test]
+    fn split_zsh_entry_malformed() {
+        assert_eq!(split_zsh_entry(": x:0;cd /src"), (None, ": x:0;cd /src"));
+        assert_eq!(
+            split_zsh_entry(": 1700000000;cd"),
+            (None, ": 1700000000;cd")
+        );
+        assert_eq!(split_zsh_entry("cd /src"), (None, "cd /src"))
+    }
+
+    #[test]
+    fn resolve_target_relative() {
+        let home = Path::new("/home/me");
+
+        assert_eq!(resolve_target("/src", home), PathBuf::from("/src"));
+        assert_eq!(resolve_target("src/", home), PathBuf::from("/home/me/src"));
+        assert_eq!(
+            resolve_target("./src/../tmp", home),
+            PathBuf::from("/home/me/tmp")
+        );
+        assert_eq!(resolve_target("..", home), PathBuf::from("/home"));
+        assert_eq!(resolve_target("/..", home), PathBuf::from("/"))
+    }
+
+    #[test]
+    fn read_history_counts_visits() {
+        let history = "#1700000000\ncd /src\ncd src\n#1700000100\ncd /src/\ncd /tmp\n";
+        let fallback_time = UNIX_EPOCH + Duration::from_secs(1600000000);
+
+        let entries = read_history(
+            HistoryFormat::Bash,
+            history.as_bytes(),
+            Path::new("/"),
+            fallback_time,
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.path.as_path(), entry.count, entry.timestamp))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    Path::new("/src"),
+                    3,
+                    UNIX_EPOCH + Duration::from_secs(1700000100)
+                ),
+                (Path::new("/tmp"), 1, fallback_time),
+            ]
+        )
+    }
+}
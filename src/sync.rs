@@ -0,0 +1,133 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use scotty::normalize_path;
+
+use crate::export::ExportEntry;
+
+/// Merges two exports of an index into one with a single entry for every path
+/// Paths are compared in the spelling the index stores them in, so `/a/` and `/a/.` are the
+/// same entry as `/a`. When both have an entry for a path, the one that was visited last is
+/// kept, the local one on a tie. The merged entries are sorted by path.
+pub fn merge_indexes(local: &[ExportEntry], remote: &[ExportEntry]) -> Vec<ExportEntry> {
+    let mut merged = BTreeMap::<PathBuf, &ExportEntry>::new();
+    for entry in local.iter().chain(remote) {
+        let path = normalize_path(&entry.path);
+        let newer = merged
+            .get(&path)
+            .is_none_or(|kept| entry.timestamp > kept.timestamp);
+        if newer {
+            merged.insert(path, entry);
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(path, entry)| ExportEntry {
+            path,
+            ..entry.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn entry(path: &str, day: u32, count: u64) -> ExportEntry {
+        ExportEntry {
+            path: PathBuf::from(path),
+            timestamp: time(day),
+            count,
+            pinned: false,
+            weight: 0,
+            hits: 0,
+        }
+    }
+
+    fn time(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn merge_indexes_empty() {
+        assert!(merge_indexes(&[], &[]).is_empty());
+        assert_eq!(
+            merge_indexes(&[entry("/a", 1, 1)], &[]),
+            vec![entry("/a", 1, 1)]
+        );
+        assert_eq!(
+            merge_indexes(&[], &[entry("/a", 1, 1)]),
+            vec![entry("/a", 1, 1)]
+        )
+    }
+
+    #[test]
+    fn merge_indexes_disjoint() {
+        let merged = merge_indexes(
+            &[entry("/c", 1, 1), entry("/a", 2, 2)],
+            &[entry("/b", 3, 3)],
+        );
+
+        assert_eq!(
+            merged,
+            vec![entry("/a", 2, 2), entry("/b", 3, 3), entry("/c", 1, 1)]
+        )
+    }
+
+    #[test]
+    fn merge_indexes_conflicts() {
+        let cases = [
+            // Local is newer
+            (entry("/a", 2, 1), entry("/a", 1, 2), entry("/a", 2, 1)),
+            // Remote is newer
+            (entry("/a", 1, 1), entry("/a", 2, 2), entry("/a", 2, 2)),
+            // A tie keeps the local entry
+            (entry("/a", 1, 1), entry("/a", 1, 2), entry("/a", 1, 1)),
+        ];
+        for (local, remote, expected) in cases {
+            assert_eq!(
+                merge_indexes(std::slice::from_ref(&local), std::slice::from_ref(&remote)),
+                vec![expected]
+            );
+        }
+    }
+
+    #[test]
+    fn merge_indexes_keeps_whole_entry() {
+        let remote = ExportEntry {
+            pinned: true,
+            weight: 5,
+            hits: 3,
+            ..entry("/a", 2, 4)
+        };
+
+        let merged = merge_indexes(&[entry("/a", 1, 9)], std::slice::from_ref(&remote));
+
+        assert_eq!(merged, vec![remote])
+    }
+
+    #[test]
+    fn merge_indexes_duplicates_in_one_export() {
+        let merged = merge_indexes(
+            &[entry("/a", 3, 1), entry("/a", 1, 2)],
+            &[entry("/a", 2, 3), entry("/a", 4, 4)],
+        );
+
+        assert_eq!(merged, vec![entry("/a", 4, 4)])
+    }
+
+    #[test]
+    fn merge_indexes_normalizes_paths() {
+        let merged = merge_indexes(
+            &[entry("/a/", 1, 1), entry("/b/.", 3, 3)],
+            &[entry("/a/.", 2, 2), entry("//b", 1, 1)],
+        );
+
+        assert_eq!(merged, vec![entry("/a", 2, 2), entry("/b", 3, 3)])
+    }
+}
@@ -0,0 +1,117 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+
+use walkdir::{DirEntry, WalkDir};
+
+/// Lists root and all directories below it, up to max_depth levels deep if it is set
+/// Hidden directories (names starting with a `.`) and everything below them are skipped,
+/// unless include_hidden is set. Symlinks are not followed and directories that can't be
+/// read are skipped with a warning.
+pub fn collect_subdirectories(
+    root: &Path,
+    max_depth: Option<usize>,
+    include_hidden: bool,
+) -> Vec<PathBuf> {
+    let mut walker = WalkDir::new(root).sort_by_file_name();
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth);
+    }
+    walker
+        .into_iter()
+        // The root was given explicitly, so it is included even if it is hidden
+        .filter_entry(|entry| entry.depth() == 0 || include_hidden || !is_hidden(entry))
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Skipping unreadable directory: {}", e);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_dir())
+        .map(DirEntry::into_path)
+        .collect()
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn create_tree(root: &Path) {
+        for dir in ["a/b/c", "a/.hidden/d", "e"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("a").join("file"), "").unwrap();
+    }
+
+    #[test]
+    fn collect_subdirectories_skips_hidden() {
+        let root = tempdir().unwrap();
+        create_tree(root.path());
+
+        let result = collect_subdirectories(root.path(), None, false);
+
+        assert_eq!(
+            result,
+            ["", "a", "a/b", "a/b/c", "e"]
+                .iter()
+                .map(|dir| root.path().join(dir))
+                .collect::<Vec<_>>()
+        );
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn collect_subdirectories_hidden() {
+        let root = tempdir().unwrap();
+        create_tree(root.path());
+
+        let result = collect_subdirectories(root.path(), None, true);
+
+        assert_eq!(
+            result,
+            ["", "a", "a/.hidden", "a/.hidden/d", "a/b", "a/b/c", "e"]
+                .iter()
+                .map(|dir| root.path().join(dir))
+                .collect::<Vec<_>>()
+        );
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn collect_subdirectories_max_depth() {
+        let root = tempdir().unwrap();
+        create_tree(root.path());
+
+        let result = collect_subdirectories(root.path(), Some(1), false);
+
+        assert_eq!(
+            result,
+            ["", "a", "e"]
+                .iter()
+                .map(|dir| root.path().join(dir))
+                .collect::<Vec<_>>()
+        );
+        root.close().unwrap()
+    }
+
+    #[test]
+    fn collect_subdirectories_hidden_root() {
+        let root = tempdir().unwrap();
+        let hidden_root = root.path().join(".root");
+        fs::create_dir_all(hidden_root.join("a")).unwrap();
+
+        let result = collect_subdirectories(&hidden_root, None, false);
+
+        assert_eq!(result, vec![hidden_root.clone(), hidden_root.join("a")]);
+        root.close().unwrap()
+    }
+}